@@ -376,7 +376,7 @@ pub fn query_all_allowances(
 ) -> StdResult<AllAllowancesResponse> {
     let limit = calc_limit(limit);
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let res: StdResult<Vec<AllowanceInfo>> = ALLOWANCES
@@ -403,7 +403,7 @@ pub fn query_all_permissions(
 ) -> StdResult<AllPermissionsResponse> {
     let limit = calc_limit(limit);
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let res: StdResult<Vec<PermissionsInfo>> = PERMISSIONS
@@ -0,0 +1,157 @@
+#![cfg(test)]
+
+use crate::contract::{execute, instantiate, query};
+use crate::msg::{ExecuteMsg, QueryMsg};
+use crate::state::{Allowance, Permissions};
+use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
+use cosmwasm_std::{
+    coin, coins, BankMsg, Coin, CosmosMsg, Decimal, Empty, HumanAddr, StakingMsg, Validator,
+};
+use cw0::NativeBalance;
+use cw1_whitelist::msg::InstantiateMsg;
+use cw_multi_test::{App, Contract, ContractWrapper, SimpleBank, SimpleStaking};
+
+const VALIDATOR: &str = "validator";
+const DENOM: &str = "ustake";
+const UNBONDING_TIME: u64 = 1000;
+const YEAR: u64 = 365 * 24 * 60 * 60;
+
+// a chain paying 10% yearly rewards on all delegations
+fn mock_app() -> App {
+    let env = mock_env();
+    let api = Box::new(MockApi::default());
+    let staking = SimpleStaking::new(DENOM, Decimal::percent(10), UNBONDING_TIME);
+    let mut app = App::new_with_staking(api, env.block, SimpleBank {}, staking, || {
+        Box::new(MockStorage::new())
+    });
+    app.add_validator(Validator {
+        address: VALIDATOR.into(),
+        commission: Decimal::zero(),
+        max_commission: Decimal::percent(10),
+        max_change_rate: Decimal::percent(1),
+    })
+    .unwrap();
+    app
+}
+
+pub fn contract_subkeys() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(execute, instantiate, query);
+    Box::new(contract)
+}
+
+fn native_balance(app: &App, address: &HumanAddr) -> Coin {
+    app.wrap().query_balance(address, DENOM).unwrap()
+}
+
+fn proxy(msg: CosmosMsg) -> ExecuteMsg {
+    ExecuteMsg::Execute { msgs: vec![msg] }
+}
+
+#[test]
+// subkeys can only stake and spend from the proxy within their permissions and allowance
+fn subkeys_stake_and_spend() {
+    let mut app = mock_app();
+    let code_id = app.store_code(contract_subkeys());
+
+    let admin = HumanAddr::from("admin");
+    let bot = HumanAddr::from("bot");
+    let spender = HumanAddr::from("spender");
+    let msg = InstantiateMsg {
+        admins: vec![admin.clone()],
+        mutable: false,
+    };
+    let contract = app
+        .instantiate_contract(code_id, &admin, &msg, &[], "subkeys")
+        .unwrap();
+    app.set_bank_balance(contract.clone(), coins(1000, DENOM))
+        .unwrap();
+
+    // the bot may delegate and withdraw rewards, but not undelegate
+    let permissions = Permissions {
+        delegate: true,
+        redelegate: false,
+        undelegate: false,
+        withdraw: true,
+    };
+    let msg: ExecuteMsg = ExecuteMsg::SetPermissions {
+        spender: bot.clone(),
+        permissions,
+    };
+    app.execute_contract(&admin, &contract, &msg, &[]).unwrap();
+
+    let delegate = StakingMsg::Delegate {
+        validator: VALIDATOR.into(),
+        amount: coin(500, DENOM),
+    };
+    app.execute_contract(&bot, &contract, &proxy(delegate.into()), &[])
+        .unwrap();
+    assert_eq!(native_balance(&app, &contract), coin(500, DENOM));
+    let delegation = app
+        .wrap()
+        .query_delegation(contract.clone(), VALIDATOR.into())
+        .unwrap()
+        .unwrap();
+    assert_eq!(delegation.amount, coin(500, DENOM));
+
+    let undelegate = StakingMsg::Undelegate {
+        validator: VALIDATOR.into(),
+        amount: coin(500, DENOM),
+    };
+    app.execute_contract(&bot, &contract, &proxy(undelegate.clone().into()), &[])
+        .unwrap_err();
+
+    // the rewards of a year are withdrawn to the proxy
+    app.update_block(|block| block.time += YEAR);
+    let withdraw = StakingMsg::Withdraw {
+        validator: VALIDATOR.into(),
+        recipient: None,
+    };
+    app.execute_contract(&bot, &contract, &proxy(withdraw.into()), &[])
+        .unwrap();
+    assert_eq!(native_balance(&app, &contract), coin(550, DENOM));
+
+    // the spender can send coins up to the allowance
+    let msg: ExecuteMsg = ExecuteMsg::IncreaseAllowance {
+        spender: spender.clone(),
+        amount: coin(100, DENOM),
+        expires: None,
+    };
+    app.execute_contract(&admin, &contract, &msg, &[]).unwrap();
+    let send = |amount| -> CosmosMsg {
+        BankMsg::Send {
+            to_address: spender.clone(),
+            amount: coins(amount, DENOM),
+        }
+        .into()
+    };
+    app.execute_contract(&spender, &contract, &proxy(send(60)), &[])
+        .unwrap();
+    app.execute_contract(&spender, &contract, &proxy(send(50)), &[])
+        .unwrap_err();
+    assert_eq!(native_balance(&app, &spender), coin(60, DENOM));
+    let allowance: Allowance = app
+        .wrap()
+        .query_wasm_smart(
+            &contract,
+            &QueryMsg::<Empty>::Allowance {
+                spender: spender.clone(),
+            },
+        )
+        .unwrap();
+    assert_eq!(allowance.balance, NativeBalance(coins(40, DENOM)));
+
+    // neither can stake
+    let delegate = StakingMsg::Delegate {
+        validator: VALIDATOR.into(),
+        amount: coin(10, DENOM),
+    };
+    app.execute_contract(&spender, &contract, &proxy(delegate.into()), &[])
+        .unwrap_err();
+
+    // the admin can do anything, the unbonded coins come back after the unbonding time
+    app.execute_contract(&admin, &contract, &proxy(undelegate.into()), &[])
+        .unwrap();
+    assert_eq!(native_balance(&app, &contract), coin(490, DENOM));
+    app.update_block(|block| block.time += UNBONDING_TIME);
+    assert_eq!(native_balance(&app, &contract), coin(990, DENOM));
+}
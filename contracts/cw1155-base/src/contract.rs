@@ -462,7 +462,7 @@ fn query_all_approvals(
 ) -> StdResult<ApprovedForAllResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let start_canon = maybe_canonical(deps.api, start_after)?;
-    let start = start_canon.map(Bound::exclusive);
+    let start = start_canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let owner_raw = deps.api.canonical_address(&owner)?;
     let operators = APPROVES
@@ -482,14 +482,13 @@ fn query_tokens(
     limit: Option<u32>,
 ) -> StdResult<TokensResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let start = start_after.map(Bound::exclusive);
+    let start = start_after.map(|s| Bound::exclusive(s.as_str()));
 
     let owner_raw = deps.api.canonical_address(&owner)?;
     let tokens = BALANCES
         .prefix(&owner_raw)
-        .range(deps.storage, start, None, Order::Ascending)
+        .keys_de(deps.storage, start, None, Order::Ascending)
         .take(limit)
-        .map(|item| item.map(|(k, _)| String::from_utf8(k).unwrap()))
         .collect::<StdResult<_>>()?;
     Ok(TokensResponse { tokens })
 }
@@ -500,11 +499,10 @@ fn query_all_tokens(
     limit: Option<u32>,
 ) -> StdResult<TokensResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let start = start_after.map(Bound::exclusive);
+    let start = start_after.map(|s| Bound::exclusive(s.as_str()));
     let tokens = TOKENS
-        .range(deps.storage, start, None, Order::Ascending)
+        .keys_de(deps.storage, start, None, Order::Ascending)
         .take(limit)
-        .map(|item| item.map(|(k, _)| String::from_utf8(k).unwrap()))
         .collect::<StdResult<_>>()?;
     Ok(TokensResponse { tokens })
 }
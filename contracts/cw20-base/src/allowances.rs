@@ -24,7 +24,8 @@ pub fn execute_increase_allowance(
 
     ALLOWANCES.update(
         deps.storage,
-        (&owner_raw, &spender_raw),
+        owner_raw,
+        spender_raw,
         |allow| -> StdResult<_> {
             let mut val = allow.unwrap_or_default();
             if let Some(exp) = expires {
@@ -65,16 +66,16 @@ pub fn execute_decrease_allowance(
     let owner_raw = &deps.api.canonical_address(&info.sender)?;
 
     // load value and delete if it hits 0, or update otherwise
-    let mut allowance = ALLOWANCES.load(deps.storage, (&owner_raw, &spender_raw))?;
+    let mut allowance = ALLOWANCES.load(deps.storage, owner_raw, spender_raw)?;
     if amount < allowance.allowance {
         // update the new amount
         allowance.allowance = (allowance.allowance - amount)?;
         if let Some(exp) = expires {
             allowance.expires = exp;
         }
-        ALLOWANCES.save(deps.storage, (&owner_raw, &spender_raw), &allowance)?;
+        ALLOWANCES.save(deps.storage, owner_raw, spender_raw, &allowance)?;
     } else {
-        ALLOWANCES.remove(deps.storage, (&owner_raw, &spender_raw));
+        ALLOWANCES.remove(deps.storage, owner_raw, spender_raw);
     }
 
     let res = Response {
@@ -99,7 +100,7 @@ pub fn deduct_allowance(
     block: &BlockInfo,
     amount: Uint128,
 ) -> Result<AllowanceResponse, ContractError> {
-    ALLOWANCES.update(storage, (&owner, &spender), |current| {
+    ALLOWANCES.update(storage, owner, spender, |current| {
         match current {
             Some(mut a) => {
                 if a.expires.is_expired(block) {
@@ -251,7 +252,7 @@ pub fn query_allowance(
     let owner_raw = deps.api.canonical_address(&owner)?;
     let spender_raw = deps.api.canonical_address(&spender)?;
     let allowance = ALLOWANCES
-        .may_load(deps.storage, (&owner_raw, &spender_raw))?
+        .may_load(deps.storage, &owner_raw, &spender_raw)?
         .unwrap_or_default();
     Ok(allowance)
 }
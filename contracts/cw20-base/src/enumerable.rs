@@ -18,12 +18,11 @@ pub fn query_all_allowances(
     let owner_raw = deps.api.canonical_address(&owner)?;
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let allowances: StdResult<Vec<AllowanceInfo>> = ALLOWANCES
-        .prefix(&owner_raw)
-        .range(deps.storage, start, None, Order::Ascending)
+        .entries_of(deps.storage, &owner_raw, start, None, Order::Ascending)
         .take(limit)
         .map(|item| {
             let (k, v) = item?;
@@ -46,7 +45,7 @@ pub fn query_all_accounts(
 ) -> StdResult<AllAccountsResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let accounts: StdResult<Vec<_>> = BALANCES
@@ -2,7 +2,7 @@ use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
 use cosmwasm_std::{CanonicalAddr, Uint128};
-use cw_storage_plus::{Item, Map};
+use cw_storage_plus::{Item, Map, NestedMap};
 
 use cw20::AllowanceResponse;
 
@@ -31,4 +31,4 @@ impl TokenInfo {
 
 pub const TOKEN_INFO: Item<TokenInfo> = Item::new("token_info");
 pub const BALANCES: Map<&[u8], Uint128> = Map::new("balance");
-pub const ALLOWANCES: Map<(&[u8], &[u8]), AllowanceResponse> = Map::new("allowance");
+pub const ALLOWANCES: NestedMap<&[u8], &[u8], AllowanceResponse> = NestedMap::new("allowance");
@@ -1,7 +1,7 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{Api, CanonicalAddr, Coin, Env, HumanAddr, Order, StdError, StdResult, Storage};
+use cosmwasm_std::{Api, CanonicalAddr, Coin, Env, HumanAddr, Order, StdResult, Storage};
 use cw_storage_plus::Map;
 
 use cw20::{Balance, Cw20Coin};
@@ -98,8 +98,7 @@ pub const ESCROWS: Map<&str, Escrow> = Map::new("escrow");
 /// This returns the list of ids for all registered escrows
 pub fn all_escrow_ids(storage: &dyn Storage) -> StdResult<Vec<String>> {
     ESCROWS
-        .keys(storage, None, None, Order::Ascending)
-        .map(|k| String::from_utf8(k).map_err(|_| StdError::invalid_utf8("parsing escrow key")))
+        .keys_de(storage, None, None, Order::Ascending)
         .collect()
 }
 
@@ -6,6 +6,7 @@ use cosmwasm_std::{
 };
 
 use cw2::set_contract_version;
+use cw_storage_plus::transactional;
 use cw20::{BalanceResponse, Cw20CoinHuman, Cw20ReceiveMsg, MinterResponse, TokenInfoResponse};
 
 use crate::allowances::{
@@ -155,21 +156,24 @@ pub fn execute_close_ico(
     }
     if config.target_amount > config.raised_amount {
         // return fund to investors as this is emergengy
-        let all: StdResult<Vec<_>> = INVESTORS.range(deps.storage, None, None, Order::Ascending).collect();
-        let all = all.unwrap();
-        for (addr, money) in all {
-            BALANCES.update(
-                    deps.storage,
-                    &addr,
-                    |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + money) },
-                    )?;
-                    // decrease money from sender (which is owner of ICO)
-            BALANCES.update(
-                    deps.storage,
-                    &sender_raw,
-                    |balance: Option<Uint128>| -> StdResult<_> { balance.unwrap_or_default() - money },
-                    )?;
-        }
+        // all refunds are written together, so a failure cannot leave some investors refunded
+        transactional(deps.storage, |store| -> StdResult<_> {
+            let all: StdResult<Vec<_>> = INVESTORS.range(store, None, None, Order::Ascending).collect();
+            for (addr, money) in all? {
+                BALANCES.update(
+                        store,
+                        &addr,
+                        |balance: Option<Uint128>| -> StdResult<_> { Ok(balance.unwrap_or_default() + money) },
+                        )?;
+                        // decrease money from sender (which is owner of ICO)
+                BALANCES.update(
+                        store,
+                        &sender_raw,
+                        |balance: Option<Uint128>| -> StdResult<_> { balance.unwrap_or_default() - money },
+                        )?;
+            }
+            Ok(())
+        })?;
     } else {
         allocate_tokens(deps, _env)?;
     }
@@ -18,7 +18,7 @@ pub fn query_all_allowances(
     let owner_raw = deps.api.canonical_address(&owner)?;
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let allowances: StdResult<Vec<AllowanceInfo>> = ALLOWANCES
@@ -46,7 +46,7 @@ pub fn query_all_accounts(
 ) -> StdResult<AllAccountsResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let accounts: StdResult<Vec<_>> = BALANCES
@@ -175,10 +175,9 @@ pub fn query_channel(deps: Deps, id: String) -> StdResult<ChannelResponse> {
     // this returns Vec<(outstanding, total)>
     let state: StdResult<Vec<_>> = CHANNEL_STATE
         .prefix(&id)
-        .range(deps.storage, None, None, Order::Ascending)
+        .range_de(deps.storage, None, None, Order::Ascending)
         .map(|r| {
-            let (k, v) = r?;
-            let denom = String::from_utf8(k)?;
+            let (denom, v) = r?;
             let outstanding = Amount::from_parts(denom.clone(), v.outstanding);
             let total = Amount::from_parts(denom, v.total_sent);
             Ok((outstanding, total))
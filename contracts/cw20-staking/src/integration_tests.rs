@@ -0,0 +1,148 @@
+#![cfg(test)]
+
+use crate::contract::{execute, instantiate, query};
+use crate::msg::{ClaimsResponse, ExecuteMsg, InstantiateMsg, InvestmentResponse, QueryMsg};
+use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
+use cosmwasm_std::{coin, coins, Coin, Decimal, Empty, HumanAddr, Uint128, Validator};
+use cw0::Duration;
+use cw20::BalanceResponse;
+use cw_multi_test::{App, Contract, ContractWrapper, SimpleBank, SimpleStaking};
+
+const VALIDATOR: &str = "validator";
+const DENOM: &str = "ustake";
+const UNBONDING_TIME: u64 = 1000;
+const YEAR: u64 = 365 * 24 * 60 * 60;
+
+// a chain paying 10% yearly rewards on all delegations
+fn mock_app() -> App {
+    let env = mock_env();
+    let api = Box::new(MockApi::default());
+    let staking = SimpleStaking::new(DENOM, Decimal::percent(10), UNBONDING_TIME);
+    let mut app = App::new_with_staking(api, env.block, SimpleBank {}, staking, || {
+        Box::new(MockStorage::new())
+    });
+    app.add_validator(Validator {
+        address: VALIDATOR.into(),
+        commission: Decimal::zero(),
+        max_commission: Decimal::percent(10),
+        max_change_rate: Decimal::percent(1),
+    })
+    .unwrap();
+    app
+}
+
+pub fn contract_staking() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(execute, instantiate, query);
+    Box::new(contract)
+}
+
+fn token_balance(app: &App, contract: &HumanAddr, address: &HumanAddr) -> Uint128 {
+    let msg = QueryMsg::Balance {
+        address: address.clone(),
+    };
+    let res: BalanceResponse = app.wrap().query_wasm_smart(contract, &msg).unwrap();
+    res.balance
+}
+
+fn native_balance(app: &App, address: &HumanAddr) -> Coin {
+    app.wrap().query_balance(address, DENOM).unwrap()
+}
+
+#[test]
+// bonding, reinvesting rewards, unbonding and claiming against the staking module
+fn staking_derivative_lifecycle() {
+    let mut app = mock_app();
+    let code_id = app.store_code(contract_staking());
+
+    let owner = HumanAddr::from("owner");
+    let bob = HumanAddr::from("bob");
+    let alice = HumanAddr::from("alice");
+    app.set_bank_balance(bob.clone(), coins(1000, DENOM))
+        .unwrap();
+
+    let msg = InstantiateMsg {
+        name: "Staked Token".to_string(),
+        symbol: "DRV".to_string(),
+        decimals: 6,
+        validator: VALIDATOR.into(),
+        unbonding_period: Duration::Time(UNBONDING_TIME),
+        exit_tax: Decimal::percent(10),
+        min_withdrawal: Uint128(50),
+    };
+    let contract = app
+        .instantiate_contract(code_id, &owner, &msg, &[], "derivative")
+        .unwrap();
+
+    // bonding delegates the coins and mints derivative tokens 1:1
+    app.execute_contract(&bob, &contract, &ExecuteMsg::Bond {}, &coins(1000, DENOM))
+        .unwrap();
+    assert_eq!(token_balance(&app, &contract, &bob), Uint128(1000));
+    assert_eq!(native_balance(&app, &bob), coin(0, DENOM));
+    let delegation = app
+        .wrap()
+        .query_delegation(contract.clone(), VALIDATOR.into())
+        .unwrap()
+        .unwrap();
+    assert_eq!(delegation.amount, coin(1000, DENOM));
+
+    // after a year, anyone can reinvest the rewards, which raises the value of the token
+    app.update_block(|block| block.time += YEAR);
+    app.execute_contract(&alice, &contract, &ExecuteMsg::Reinvest {}, &[])
+        .unwrap();
+    assert_eq!(native_balance(&app, &contract), coin(100, DENOM));
+    // contracts only query the committed state in multi-test, so the rewards withdrawn above
+    // were not visible to _BondAllTokens yet, and are bonded by the next reinvest
+    app.execute_contract(&alice, &contract, &ExecuteMsg::Reinvest {}, &[])
+        .unwrap();
+    assert_eq!(native_balance(&app, &contract), coin(0, DENOM));
+    let invest: InvestmentResponse = app
+        .wrap()
+        .query_wasm_smart(&contract, &QueryMsg::Investment {})
+        .unwrap();
+    assert_eq!(invest.staked_tokens, coin(1100, DENOM));
+    assert_eq!(invest.token_supply, Uint128(1000));
+    assert_eq!(invest.nominal_value, Decimal::percent(110));
+
+    // unbonding pays the exit tax to the owner and creates a claim
+    app.execute_contract(
+        &bob,
+        &contract,
+        &ExecuteMsg::Unbond {
+            amount: Uint128(500),
+        },
+        &[],
+    )
+    .unwrap();
+    assert_eq!(token_balance(&app, &contract, &bob), Uint128(500));
+    assert_eq!(token_balance(&app, &contract, &owner), Uint128(50));
+    // (500 - 50 tax) * 1.1
+    let claims: ClaimsResponse = app
+        .wrap()
+        .query_wasm_smart(
+            &contract,
+            &QueryMsg::Claims {
+                address: bob.clone(),
+            },
+        )
+        .unwrap();
+    assert_eq!(claims.claims.len(), 1);
+    assert_eq!(claims.claims[0].amount, Uint128(495));
+    let delegation = app
+        .wrap()
+        .query_delegation(contract.clone(), VALIDATOR.into())
+        .unwrap()
+        .unwrap();
+    assert_eq!(delegation.amount, coin(605, DENOM));
+
+    // nothing to claim until the coins are unbonded
+    app.execute_contract(&bob, &contract, &ExecuteMsg::Claim {}, &[])
+        .unwrap_err();
+
+    // the staking module pays out the contract, and bob can claim
+    app.update_block(|block| block.time += UNBONDING_TIME);
+    assert_eq!(native_balance(&app, &contract), coin(495, DENOM));
+    app.execute_contract(&bob, &contract, &ExecuteMsg::Claim {}, &[])
+        .unwrap();
+    assert_eq!(native_balance(&app, &bob), coin(495, DENOM));
+    assert_eq!(native_balance(&app, &contract), coin(0, DENOM));
+}
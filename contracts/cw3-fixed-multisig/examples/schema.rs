@@ -3,7 +3,7 @@ use std::fs::create_dir_all;
 
 use cosmwasm_schema::{export_schema, export_schema_with_title, remove_schemas, schema_for};
 
-use cw3_fixed_multisig::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
+use cw3_fixed_multisig::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
 
 fn main() {
     let mut out_dir = current_dir().unwrap();
@@ -14,4 +14,5 @@ fn main() {
     export_schema(&schema_for!(InstantiateMsg), &out_dir);
     export_schema_with_title(&mut schema_for!(ExecuteMsg), &out_dir, "ExecuteMsg");
     export_schema_with_title(&mut schema_for!(QueryMsg), &out_dir, "QueryMsg");
+    export_schema(&schema_for!(MigrateMsg), &out_dir);
 }
@@ -8,7 +8,7 @@ use cosmwasm_std::{
 };
 
 use cw0::{maybe_canonical, Expiration};
-use cw2::set_contract_version;
+use cw2::{get_contract_version, set_contract_version};
 use cw3::{
     ProposalListResponse, ProposalResponse, Status, ThresholdResponse, Vote, VoteInfo,
     VoteListResponse, VoteResponse, VoterDetail, VoterListResponse, VoterResponse,
@@ -16,10 +16,8 @@ use cw3::{
 use cw_storage_plus::Bound;
 
 use crate::error::ContractError;
-use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{
-    next_id, parse_id, Ballot, Config, Proposal, BALLOTS, CONFIG, PROPOSALS, VOTERS,
-};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, QueryMsg};
+use crate::state::{Ballot, Config, Proposal, BALLOTS, CONFIG, PROPOSALS, PROPOSAL_COUNT, VOTERS};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:cw3-fixed-multisig";
@@ -125,7 +123,7 @@ pub fn execute_propose(
         yes_weight: vote_power,
         required_weight: cfg.required_weight,
     };
-    let id = next_id(deps.storage)?;
+    let id = PROPOSAL_COUNT.next(deps.storage)?;
     PROPOSALS.save(deps.storage, id.into(), &prop)?;
 
     // add the first yes vote from voter
@@ -273,6 +271,18 @@ pub fn execute_close(
     })
 }
 
+#[cfg_attr(not(feature = "library"), entry_point)]
+pub fn migrate(deps: DepsMut, _env: Env, _msg: MigrateMsg) -> Result<Response, ContractError> {
+    let version = get_contract_version(deps.storage)?;
+    if version.contract != CONTRACT_NAME {
+        return Err(ContractError::CannotMigrate {
+            previous_contract: version.contract,
+        });
+    }
+    set_contract_version(deps.storage, CONTRACT_NAME, CONTRACT_VERSION)?;
+    Ok(Response::default())
+}
+
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn query(deps: Deps, env: Env, msg: QueryMsg) -> StdResult<Binary> {
     match msg {
@@ -345,7 +355,7 @@ fn list_proposals(
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let start = start_after.map(Bound::exclusive_int);
     let props: StdResult<Vec<_>> = PROPOSALS
-        .range(deps.storage, start, None, Order::Ascending)
+        .range_de(deps.storage, start, None, Order::Ascending)
         .take(limit)
         .map(|p| map_proposal(&env.block, &threshold, p))
         .collect();
@@ -368,7 +378,7 @@ fn reverse_proposals(
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let end = start_before.map(Bound::exclusive_int);
     let props: StdResult<Vec<_>> = PROPOSALS
-        .range(deps.storage, None, end, Order::Descending)
+        .range_de(deps.storage, None, end, Order::Descending)
         .take(limit)
         .map(|p| map_proposal(&env.block, &threshold, p))
         .collect();
@@ -379,12 +389,12 @@ fn reverse_proposals(
 fn map_proposal(
     block: &BlockInfo,
     threshold: &ThresholdResponse,
-    item: StdResult<(Vec<u8>, Proposal)>,
+    item: StdResult<(u64, Proposal)>,
 ) -> StdResult<ProposalResponse> {
-    let (key, prop) = item?;
+    let (id, prop) = item?;
     let status = prop.current_status(block);
     Ok(ProposalResponse {
-        id: parse_id(&key)?,
+        id,
         title: prop.title,
         description: prop.description,
         msgs: prop.msgs,
@@ -413,7 +423,7 @@ fn list_votes(
 ) -> StdResult<VoteListResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let votes: StdResult<Vec<_>> = BALLOTS
@@ -446,7 +456,7 @@ fn list_voters(
 ) -> StdResult<VoterListResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let voters: StdResult<Vec<_>> = VOTERS
@@ -1,13 +1,13 @@
 #![cfg(test)]
 
-use crate::contract::{execute, instantiate, query};
-use crate::msg::{ExecuteMsg, InstantiateMsg, Voter};
+use crate::contract::{execute, instantiate, migrate, query};
+use crate::msg::{ExecuteMsg, InstantiateMsg, MigrateMsg, Voter};
 use cosmwasm_std::testing::{mock_env, MockApi, MockStorage};
 use cosmwasm_std::{from_binary, to_binary, Empty, HumanAddr, Uint128, WasmMsg, WasmQuery};
 use cw0::Duration;
 use cw20::{BalanceResponse, MinterResponse};
 use cw20_base::msg::QueryMsg;
-use cw3::Vote;
+use cw3::{ThresholdResponse, Vote};
 use cw_multi_test::{App, Contract, ContractWrapper, SimpleBank};
 
 fn mock_app() -> App {
@@ -19,7 +19,7 @@ fn mock_app() -> App {
 }
 
 pub fn contract_cw3_fixed_multisig() -> Box<dyn Contract<Empty>> {
-    let contract = ContractWrapper::new(execute, instantiate, query);
+    let contract = ContractWrapper::new(execute, instantiate, query).with_migrate(migrate);
     Box::new(contract)
 }
 
@@ -154,3 +154,82 @@ fn cw3_controls_cw20() {
     // compare minted amount
     assert_eq!(balance.balance, mint_amount);
 }
+
+#[test]
+// only the admin can migrate the multisig, and only from another cw3-fixed-multisig
+fn migrate_multisig() {
+    let mut router = mock_app();
+
+    let cw3_id = router.store_code(contract_cw3_fixed_multisig());
+    let new_cw3_id = router.store_code(contract_cw3_fixed_multisig());
+    let cw20_id = router.store_code(contract_cw20());
+
+    let admin = HumanAddr::from("admin");
+    let voter = HumanAddr::from("voter");
+    let cw3_instantiate_msg = InstantiateMsg {
+        voters: vec![Voter {
+            addr: voter.clone(),
+            weight: 1,
+        }],
+        required_weight: 1,
+        max_voting_period: Duration::Height(3),
+    };
+    let multisig_addr = router
+        .instantiate_contract_with_admin(
+            cw3_id,
+            &voter,
+            &cw3_instantiate_msg,
+            &[],
+            "Consortium",
+            Some(admin.clone()),
+        )
+        .unwrap();
+    assert_eq!(
+        router.contract_admin(&multisig_addr).unwrap(),
+        Some(admin.clone())
+    );
+
+    // a voter is not the admin
+    router
+        .migrate_contract(&voter, &multisig_addr, new_cw3_id, &MigrateMsg {})
+        .unwrap_err();
+
+    // the admin can migrate, and the state is kept
+    router
+        .migrate_contract(&admin, &multisig_addr, new_cw3_id, &MigrateMsg {})
+        .unwrap();
+    let threshold: ThresholdResponse = router
+        .wrap()
+        .query_wasm_smart(&multisig_addr, &crate::msg::QueryMsg::Threshold {})
+        .unwrap();
+    assert_eq!(
+        threshold,
+        ThresholdResponse::AbsoluteCount {
+            weight: 1,
+            total_weight: 1
+        }
+    );
+
+    // a cw20 contract cannot be migrated to the multisig code
+    let cw20_instantiate_msg = cw20_base::msg::InstantiateMsg {
+        name: "Consortium Token".parse().unwrap(),
+        symbol: "CST".parse().unwrap(),
+        decimals: 6,
+        initial_balances: vec![],
+        mint: None,
+    };
+    let cw20_addr = router
+        .instantiate_contract_with_admin(
+            cw20_id,
+            &admin,
+            &cw20_instantiate_msg,
+            &[],
+            "Consortium",
+            Some(admin.clone()),
+        )
+        .unwrap();
+    let err = router
+        .migrate_contract(&admin, &cw20_addr, new_cw3_id, &MigrateMsg {})
+        .unwrap_err();
+    assert!(err.contains("Cannot migrate from different contract type"));
+}
@@ -18,9 +18,7 @@ use cw_storage_plus::Bound;
 
 use crate::error::ContractError;
 use crate::msg::{ExecuteMsg, InstantiateMsg, QueryMsg};
-use crate::state::{
-    next_id, parse_id, Ballot, Config, Proposal, Votes, BALLOTS, CONFIG, PROPOSALS,
-};
+use crate::state::{Ballot, Config, Proposal, Votes, BALLOTS, CONFIG, PROPOSALS, PROPOSAL_COUNT};
 
 // version info for migration info
 const CONTRACT_NAME: &str = "crates.io:cw3-flex-multisig";
@@ -121,7 +119,7 @@ pub fn execute_propose(
         total_weight: cfg.group_addr.total_weight(&deps.querier)?,
     };
     prop.update_status(&env.block);
-    let id = next_id(deps.storage)?;
+    let id = PROPOSAL_COUNT.next(deps.storage)?;
     PROPOSALS.save(deps.storage, id.into(), &prop)?;
 
     // add the first yes vote from voter
@@ -343,7 +341,7 @@ fn list_proposals(
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let start = start_after.map(Bound::exclusive_int);
     let props: StdResult<Vec<_>> = PROPOSALS
-        .range(deps.storage, start, None, Order::Ascending)
+        .range_de(deps.storage, start, None, Order::Ascending)
         .take(limit)
         .map(|p| map_proposal(&env.block, p))
         .collect();
@@ -360,7 +358,7 @@ fn reverse_proposals(
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let end = start_before.map(Bound::exclusive_int);
     let props: StdResult<Vec<_>> = PROPOSALS
-        .range(deps.storage, None, end, Order::Descending)
+        .range_de(deps.storage, None, end, Order::Descending)
         .take(limit)
         .map(|p| map_proposal(&env.block, p))
         .collect();
@@ -370,13 +368,13 @@ fn reverse_proposals(
 
 fn map_proposal(
     block: &BlockInfo,
-    item: StdResult<(Vec<u8>, Proposal)>,
+    item: StdResult<(u64, Proposal)>,
 ) -> StdResult<ProposalResponse> {
-    let (key, prop) = item?;
+    let (id, prop) = item?;
     let status = prop.current_status(block);
     let threshold = prop.threshold.to_response(prop.total_weight);
     Ok(ProposalResponse {
-        id: parse_id(&key)?,
+        id,
         title: prop.title,
         description: prop.description,
         msgs: prop.msgs,
@@ -405,7 +403,7 @@ fn list_votes(
 ) -> StdResult<VoteListResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
     let votes: StdResult<Vec<_>> = BALLOTS
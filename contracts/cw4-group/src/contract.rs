@@ -50,7 +50,7 @@ pub fn create(
         let raw = deps.api.canonical_address(&member.addr)?;
         MEMBERS.save(deps.storage, &raw, &member.weight, height)?;
     }
-    TOTAL.save(deps.storage, &total)?;
+    TOTAL.save(deps.storage, &total, height)?;
 
     Ok(())
 }
@@ -134,7 +134,7 @@ pub fn update_members(
         }
     }
 
-    TOTAL.save(deps.storage, &total)?;
+    TOTAL.save(deps.storage, &total, height)?;
     Ok(MemberChangedHookMsg { diffs })
 }
 
@@ -145,9 +145,11 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             addr,
             at_height: height,
         } => to_binary(&query_member(deps, addr, height)?),
-        QueryMsg::ListMembers { start_after, limit } => {
-            to_binary(&list_members(deps, start_after, limit)?)
-        }
+        QueryMsg::ListMembers {
+            start_after,
+            limit,
+            at_height,
+        } => to_binary(&list_members(deps, start_after, limit, at_height)?),
         QueryMsg::TotalWeight {} => to_binary(&query_total_weight(deps)?),
         QueryMsg::Admin {} => to_binary(&ADMIN.query_admin(deps)?),
         QueryMsg::Hooks {} => to_binary(&HOOKS.query_hooks(deps)?),
@@ -176,14 +178,20 @@ fn list_members(
     deps: Deps,
     start_after: Option<HumanAddr>,
     limit: Option<u32>,
+    at_height: Option<u64>,
 ) -> StdResult<MemberListResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
-    let members: StdResult<Vec<_>> = MEMBERS
-        .range(deps.storage, start, None, Order::Ascending)
+    let members = match at_height {
+        Some(height) => {
+            MEMBERS.range_at_height(deps.storage, height, start, None, Order::Ascending)?
+        }
+        None => MEMBERS.range(deps.storage, start, None, Order::Ascending),
+    };
+    let members: StdResult<Vec<_>> = members
         .take(limit)
         .map(|item| {
             let (key, weight) = item?;
@@ -255,7 +263,7 @@ mod tests {
         let member3 = query_member(deps.as_ref(), USER3.into(), None).unwrap();
         assert_eq!(member3.weight, None);
 
-        let members = list_members(deps.as_ref(), None, None).unwrap();
+        let members = list_members(deps.as_ref(), None, None, None).unwrap();
         assert_eq!(members.members.len(), 2);
         // TODO: assert the set is proper
     }
@@ -276,17 +284,17 @@ mod tests {
         let member3 = query_member(deps.as_ref(), USER3.into(), height).unwrap();
         assert_eq!(member3.weight, user3_weight);
 
+        // compute expected metrics
+        let weights = [user1_weight, user2_weight, user3_weight];
+        let count = weights.iter().filter(|x| x.is_some()).count();
+
+        // TODO: more detailed compare?
+        let members = list_members(deps.as_ref(), None, None, height).unwrap();
+        assert_eq!(count, members.members.len());
+
         // this is only valid if we are not doing a historical query
         if height.is_none() {
-            // compute expected metrics
-            let weights = vec![user1_weight, user2_weight, user3_weight];
             let sum: u64 = weights.iter().map(|x| x.unwrap_or_default()).sum();
-            let count = weights.iter().filter(|x| x.is_some()).count();
-
-            // TODO: more detailed compare?
-            let members = list_members(deps.as_ref(), None, None).unwrap();
-            assert_eq!(count, members.members.len());
-
             let total = query_total_weight(deps.as_ref()).unwrap();
             assert_eq!(sum, total.weight); // 17 - 11 + 15 = 21
         }
@@ -331,6 +339,14 @@ mod tests {
 
         // snapshot still shows old value
         assert_users(&deps, Some(11), Some(6), None, Some(height + 1));
+
+        // as does the total weight
+        let total = TOTAL.may_load_at_height(&deps.storage, height + 1).unwrap();
+        assert_eq!(Some(17), total);
+        let total = TOTAL
+            .may_load_at_height(&deps.storage, height + 11)
+            .unwrap();
+        assert_eq!(Some(21), total);
     }
 
     #[test]
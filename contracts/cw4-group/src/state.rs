@@ -1,11 +1,16 @@
-use cw4::TOTAL_KEY;
+use cw4::{TOTAL_CHANGELOG, TOTAL_CHECKPOINTS, TOTAL_KEY};
 use cw_controllers::{Admin, Hooks};
-use cw_storage_plus::{Item, SnapshotMap, Strategy};
+use cw_storage_plus::{SnapshotItem, SnapshotMap, Strategy};
 
 pub const ADMIN: Admin = Admin::new("admin");
 pub const HOOKS: Hooks = Hooks::new("cw4-hooks");
 
-pub const TOTAL: Item<u64> = Item::new(TOTAL_KEY);
+pub const TOTAL: SnapshotItem<u64> = SnapshotItem::new(
+    TOTAL_KEY,
+    TOTAL_CHECKPOINTS,
+    TOTAL_CHANGELOG,
+    Strategy::EveryBlock,
+);
 
 pub const MEMBERS: SnapshotMap<&[u8], u64> = SnapshotMap::new(
     cw4::MEMBERS_KEY,
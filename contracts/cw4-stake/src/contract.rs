@@ -27,7 +27,7 @@ const CONTRACT_VERSION: &str = env!("CARGO_PKG_VERSION");
 #[cfg_attr(not(feature = "library"), entry_point)]
 pub fn instantiate(
     mut deps: DepsMut,
-    _env: Env,
+    env: Env,
     _info: MessageInfo,
     msg: InstantiateMsg,
 ) -> Result<Response, ContractError> {
@@ -47,7 +47,7 @@ pub fn instantiate(
         unbonding_period: msg.unbonding_period,
     };
     CONFIG.save(deps.storage, &config)?;
-    TOTAL.save(deps.storage, &0)?;
+    TOTAL.save(deps.storage, &0, env.block.height)?;
 
     Ok(Response::default())
 }
@@ -205,7 +205,7 @@ fn update_membership(
     }?;
 
     // update total
-    TOTAL.update(storage, |total| -> StdResult<_> {
+    TOTAL.update(storage, height, |total| -> StdResult<_> {
         Ok(total + new.unwrap_or_default() - old.unwrap_or_default())
     })?;
 
@@ -281,9 +281,11 @@ pub fn query(deps: Deps, _env: Env, msg: QueryMsg) -> StdResult<Binary> {
             addr,
             at_height: height,
         } => to_binary(&query_member(deps, addr, height)?),
-        QueryMsg::ListMembers { start_after, limit } => {
-            to_binary(&list_members(deps, start_after, limit)?)
-        }
+        QueryMsg::ListMembers {
+            start_after,
+            limit,
+            at_height,
+        } => to_binary(&list_members(deps, start_after, limit, at_height)?),
         QueryMsg::TotalWeight {} => to_binary(&query_total_weight(deps)?),
         QueryMsg::Claims { address } => to_binary(&CLAIMS.query_claims(deps, address)?),
         QueryMsg::Staked { address } => to_binary(&query_staked(deps, address)?),
@@ -332,14 +334,20 @@ fn list_members(
     deps: Deps,
     start_after: Option<HumanAddr>,
     limit: Option<u32>,
+    at_height: Option<u64>,
 ) -> StdResult<MemberListResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let canon = maybe_canonical(deps.api, start_after)?;
-    let start = canon.map(Bound::exclusive);
+    let start = canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let api = &deps.api;
-    let members: StdResult<Vec<_>> = MEMBERS
-        .range(deps.storage, start, None, Order::Ascending)
+    let members = match at_height {
+        Some(height) => {
+            MEMBERS.range_at_height(deps.storage, height, start, None, Order::Ascending)?
+        }
+        None => MEMBERS.range(deps.storage, start, None, Order::Ascending),
+    };
+    let members: StdResult<Vec<_>> = members
         .take(limit)
         .map(|item| {
             let (key, weight) = item?;
@@ -465,22 +473,23 @@ mod tests {
         let member3 = get_member(deps, USER3.into(), height);
         assert_eq!(member3, user3_weight);
 
+        // compute expected metrics
+        let weights = [user1_weight, user2_weight, user3_weight];
+        let count = weights.iter().filter(|x| x.is_some()).count();
+
+        // TODO: more detailed compare?
+        let msg = QueryMsg::ListMembers {
+            start_after: None,
+            limit: None,
+            at_height: height,
+        };
+        let raw = query(deps, mock_env(), msg).unwrap();
+        let members: MemberListResponse = from_slice(&raw).unwrap();
+        assert_eq!(count, members.members.len());
+
         // this is only valid if we are not doing a historical query
         if height.is_none() {
-            // compute expected metrics
-            let weights = vec![user1_weight, user2_weight, user3_weight];
             let sum: u64 = weights.iter().map(|x| x.unwrap_or_default()).sum();
-            let count = weights.iter().filter(|x| x.is_some()).count();
-
-            // TODO: more detailed compare?
-            let msg = QueryMsg::ListMembers {
-                start_after: None,
-                limit: None,
-            };
-            let raw = query(deps, mock_env(), msg).unwrap();
-            let members: MemberListResponse = from_slice(&raw).unwrap();
-            assert_eq!(count, members.members.len());
-
             let raw = query(deps, mock_env(), QueryMsg::TotalWeight {}).unwrap();
             let total: TotalWeightResponse = from_slice(&raw).unwrap();
             assert_eq!(sum, total.weight); // 17 - 11 + 15 = 21
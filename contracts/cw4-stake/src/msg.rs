@@ -59,6 +59,7 @@ pub enum QueryMsg {
     ListMembers {
         start_after: Option<HumanAddr>,
         limit: Option<u32>,
+        at_height: Option<u64>,
     },
     /// Returns MemberResponse
     Member {
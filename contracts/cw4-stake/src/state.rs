@@ -4,9 +4,9 @@ use serde::{Deserialize, Serialize};
 
 use cw0::Duration;
 use cw20::Denom;
-use cw4::TOTAL_KEY;
+use cw4::{TOTAL_CHANGELOG, TOTAL_CHECKPOINTS, TOTAL_KEY};
 use cw_controllers::{Admin, Claims, Hooks};
-use cw_storage_plus::{Item, Map, SnapshotMap, Strategy};
+use cw_storage_plus::{Item, Map, SnapshotItem, SnapshotMap, Strategy};
 
 pub const CLAIMS: Claims = Claims::new("claims");
 
@@ -22,7 +22,12 @@ pub struct Config {
 pub const ADMIN: Admin = Admin::new("admin");
 pub const HOOKS: Hooks = Hooks::new("cw4-hooks");
 pub const CONFIG: Item<Config> = Item::new("config");
-pub const TOTAL: Item<u64> = Item::new(TOTAL_KEY);
+pub const TOTAL: SnapshotItem<u64> = SnapshotItem::new(
+    TOTAL_KEY,
+    TOTAL_CHECKPOINTS,
+    TOTAL_CHANGELOG,
+    Strategy::EveryBlock,
+);
 
 pub const MEMBERS: SnapshotMap<&[u8], u64> = SnapshotMap::new(
     cw4::MEMBERS_KEY,
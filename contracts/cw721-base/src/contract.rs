@@ -2,7 +2,7 @@
 use cosmwasm_std::entry_point;
 use cosmwasm_std::{
     attr, to_binary, Api, Binary, BlockInfo, Deps, DepsMut, Env, HumanAddr, MessageInfo, Order,
-    Response, StdError, StdResult, KV,
+    Response, StdResult, KV,
 };
 
 use cw0::maybe_canonical;
@@ -14,9 +14,7 @@ use cw721::{
 
 use crate::error::ContractError;
 use crate::msg::{ExecuteMsg, InstantiateMsg, MintMsg, MinterResponse, QueryMsg};
-use crate::state::{
-    increment_tokens, num_tokens, tokens, Approval, TokenInfo, CONTRACT_INFO, MINTER, OPERATORS,
-};
+use crate::state::{tokens, Approval, TokenInfo, CONTRACT_INFO, MINTER, OPERATORS, TOKEN_COUNT};
 use cw_storage_plus::{Bound, PkOwned};
 
 // version info for migration info
@@ -101,7 +99,7 @@ pub fn execute_mint(
         None => Ok(token),
     })?;
 
-    increment_tokens(deps.storage)?;
+    TOKEN_COUNT.next(deps.storage)?;
 
     Ok(Response {
         submessages: vec![],
@@ -445,7 +443,7 @@ fn query_contract_info(deps: Deps) -> StdResult<ContractInfoResponse> {
 }
 
 fn query_num_tokens(deps: Deps) -> StdResult<NumTokensResponse> {
-    let count = num_tokens(deps.storage)?;
+    let count = TOKEN_COUNT.current(deps.storage)?;
     Ok(NumTokensResponse { count })
 }
 
@@ -484,7 +482,7 @@ fn query_all_approvals(
 ) -> StdResult<ApprovedForAllResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
     let start_canon = maybe_canonical(deps.api, start_after)?;
-    let start = start_canon.map(Bound::exclusive);
+    let start = start_canon.map(|c| Bound::exclusive(c.as_slice()));
 
     let owner_raw = deps.api.canonical_address(&owner)?;
     let res: StdResult<Vec<_>> = OPERATORS
@@ -511,26 +509,17 @@ fn query_tokens(
     limit: Option<u32>,
 ) -> StdResult<TokensResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let start = start_after.map(Bound::exclusive);
+    let start = start_after.map(|s| Bound::exclusive(s.as_str()));
 
     let owner_raw = deps.api.canonical_address(&owner)?;
-    let res: Result<Vec<_>, _> = tokens()
+    let tokens: StdResult<Vec<String>> = tokens()
         .idx
         .owner
-        .pks(
-            deps.storage,
-            PkOwned(owner_raw.into()),
-            start,
-            None,
-            Order::Ascending,
-        )
+        .prefix(PkOwned(owner_raw.into()))
+        .keys_de(deps.storage, start, None, Order::Ascending)
         .take(limit)
         .collect();
-    let pks = res?;
-
-    let res: Result<Vec<_>, _> = pks.iter().map(|v| String::from_utf8(v.to_vec())).collect();
-    let tokens = res.map_err(StdError::invalid_utf8)?;
-    Ok(TokensResponse { tokens })
+    Ok(TokensResponse { tokens: tokens? })
 }
 
 fn query_all_tokens(
@@ -539,12 +528,11 @@ fn query_all_tokens(
     limit: Option<u32>,
 ) -> StdResult<TokensResponse> {
     let limit = limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT) as usize;
-    let start = start_after.map(Bound::exclusive);
+    let start = start_after.map(|s| Bound::exclusive(s.as_str()));
 
     let tokens: StdResult<Vec<String>> = tokens()
-        .range(deps.storage, start, None, Order::Ascending)
+        .keys_de(deps.storage, start, None, Order::Ascending)
         .take(limit)
-        .map(|item| item.map(|(k, _)| String::from_utf8_lossy(&k).to_string()))
         .collect();
     Ok(TokensResponse { tokens: tokens? })
 }
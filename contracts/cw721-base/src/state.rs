@@ -1,9 +1,9 @@
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
-use cosmwasm_std::{CanonicalAddr, StdResult, Storage};
+use cosmwasm_std::CanonicalAddr;
 use cw721::{ContractInfoResponse, Expiration};
-use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, PkOwned};
+use cw_storage_plus::{Index, IndexList, IndexedMap, Item, Map, MultiIndex, PkOwned, Sequence};
 
 #[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
 pub struct TokenInfo {
@@ -30,24 +30,13 @@ pub struct Approval {
 
 pub const CONTRACT_INFO: Item<ContractInfoResponse> = Item::new("nft_info");
 pub const MINTER: Item<CanonicalAddr> = Item::new("minter");
-pub const TOKEN_COUNT: Item<u64> = Item::new("num_tokens");
+pub const TOKEN_COUNT: Sequence = Sequence::new("num_tokens");
 
 // pub const TOKENS: Map<&str, TokenInfo> = Map::new("tokens");
 pub const OPERATORS: Map<(&[u8], &[u8]), Expiration> = Map::new("operators");
 
-pub fn num_tokens(storage: &dyn Storage) -> StdResult<u64> {
-    Ok(TOKEN_COUNT.may_load(storage)?.unwrap_or_default())
-}
-
-pub fn increment_tokens(storage: &mut dyn Storage) -> StdResult<u64> {
-    let val = num_tokens(storage)? + 1;
-    TOKEN_COUNT.save(storage, &val)?;
-    Ok(val)
-}
-
 pub struct TokenIndexes<'a> {
-    // pk goes to second tuple element
-    pub owner: MultiIndex<'a, (PkOwned, PkOwned), TokenInfo>,
+    pub owner: MultiIndex<'a, PkOwned, TokenInfo, String>,
 }
 
 impl<'a> IndexList<TokenInfo> for TokenIndexes<'a> {
@@ -59,11 +48,24 @@ impl<'a> IndexList<TokenInfo> for TokenIndexes<'a> {
 
 pub fn tokens<'a>() -> IndexedMap<'a, &'a str, TokenInfo, TokenIndexes<'a>> {
     let indexes = TokenIndexes {
-        owner: MultiIndex::new(
-            |d, k| (PkOwned(d.owner.to_vec()), PkOwned(k)),
-            "tokens",
-            "tokens__owner",
-        ),
+        owner: MultiIndex::new(|d| PkOwned(d.owner.to_vec()), "tokens", "tokens__owner"),
     };
     IndexedMap::new("tokens", indexes)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cw_storage_plus::StorageRegistry;
+
+    #[test]
+    fn storage_keys_dont_collide() {
+        StorageRegistry::new()
+            .register("CONTRACT_INFO", &CONTRACT_INFO)
+            .register("MINTER", &MINTER)
+            .register("TOKEN_COUNT", &TOKEN_COUNT)
+            .register("OPERATORS", &OPERATORS)
+            .register("tokens", &tokens())
+            .assert_no_collisions();
+    }
+}
@@ -137,7 +137,28 @@ impl Cw4Contract {
         start_after: Option<HumanAddr>,
         limit: Option<u32>,
     ) -> StdResult<Vec<Member>> {
-        let query = self.encode_smart_query(Cw4QueryMsg::ListMembers { start_after, limit })?;
+        let query = self.encode_smart_query(Cw4QueryMsg::ListMembers {
+            start_after,
+            limit,
+            at_height: None,
+        })?;
+        let res: MemberListResponse = querier.query(&query)?;
+        Ok(res.members)
+    }
+
+    /// Return the members as they were at the given snapshot - requires a smart query
+    pub fn list_members_at_height(
+        &self,
+        querier: &QuerierWrapper,
+        start_after: Option<HumanAddr>,
+        limit: Option<u32>,
+        height: u64,
+    ) -> StdResult<Vec<Member>> {
+        let query = self.encode_smart_query(Cw4QueryMsg::ListMembers {
+            start_after,
+            limit,
+            at_height: Some(height),
+        })?;
         let res: MemberListResponse = querier.query(&query)?;
         Ok(res.members)
     }
@@ -14,6 +14,7 @@ pub enum Cw4QueryMsg {
     ListMembers {
         start_after: Option<HumanAddr>,
         limit: Option<u32>,
+        at_height: Option<u64>,
     },
     /// Returns MemberResponse
     Member {
@@ -60,6 +61,8 @@ pub struct HooksResponse {
 
 /// TOTAL_KEY is meant for raw queries
 pub const TOTAL_KEY: &str = "total";
+pub const TOTAL_CHECKPOINTS: &str = "total__checkpoints";
+pub const TOTAL_CHANGELOG: &str = "total__changelog";
 pub const MEMBERS_KEY: &str = "members";
 pub const MEMBERS_CHECKPOINTS: &str = "members__checkpoints";
 pub const MEMBERS_CHANGELOG: &str = "members__changelog";
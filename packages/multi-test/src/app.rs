@@ -1,24 +1,79 @@
+use serde::de::DeserializeOwned;
 use serde::Serialize;
 
 #[cfg(test)]
 use cosmwasm_std::testing::{mock_env, MockApi};
 use cosmwasm_std::{
-    from_slice, to_binary, to_vec, Api, Attribute, BankMsg, Binary, BlockInfo, Coin,
-    ContractResult, CosmosMsg, Empty, HumanAddr, MessageInfo, Querier, QuerierResult,
-    QuerierWrapper, QueryRequest, Response, SystemError, SystemResult, WasmMsg,
+    attr, from_slice, to_binary, to_vec, Api, Attribute, BankMsg, Binary, BlockInfo, Coin,
+    ContractResult, CosmosMsg, CustomQuery, Empty, Event, HumanAddr, MessageInfo, Querier,
+    QuerierResult, QuerierWrapper, QueryRequest, Reply, Response, StakingMsg, SubMsg,
+    SubcallResponse, SystemError, SystemResult, Validator, WasmMsg,
 };
 
 use crate::bank::{Bank, BankCache, BankOps, BankRouter};
+use crate::custom_handler::{CustomCache, CustomHandler, CustomOps, CustomRouter};
+use crate::staking::{
+    SimpleStaking, Staking, StakingCache, StakingOps, StakingRouter, STAKING_MODULE,
+};
 use crate::wasm::{Contract, StorageFactory, WasmCache, WasmOps, WasmRouter};
 use schemars::JsonSchema;
 use std::fmt;
 
 #[derive(Default, Clone, Debug)]
 pub struct AppResponse {
+    /// The attributes of all contracts called, flattened in execution order
     pub attributes: Vec<Attribute>,
+    /// wasmd-style events, eg. `execute` and `wasm` with the `_contract_address`, or `transfer`
+    pub events: Vec<Event>,
     pub data: Option<Binary>,
 }
 
+impl AppResponse {
+    /// Returns true if there is an event of the same kind, which has all attributes of the
+    /// expected one (and maybe more), so `_contract_address` can be left out if not needed.
+    pub fn has_event(&self, expected: &Event) -> bool {
+        self.events.iter().any(|ev| {
+            ev.kind == expected.kind
+                && expected
+                    .attributes
+                    .iter()
+                    .all(|at| ev.attributes.contains(at))
+        })
+    }
+
+    /// Like has_event, but panics with all events if there is no match
+    pub fn assert_event(&self, expected: &Event) {
+        assert!(
+            self.has_event(expected),
+            "Expected event {:?} not found in {:?}",
+            expected,
+            self.events
+        );
+    }
+}
+
+/// Creates an event with the `_contract_address` attribute first, like wasmd does
+fn contract_event(kind: &str, contract: &HumanAddr, attributes: &[Attribute]) -> Event {
+    let mut attrs = vec![attr("_contract_address", contract)];
+    attrs.extend_from_slice(attributes);
+    Event::new(kind, attrs)
+}
+
+fn transfer_event(sender: &HumanAddr, recipient: &HumanAddr, amount: &[Coin]) -> Event {
+    let amount: Vec<String> = amount
+        .iter()
+        .map(|c| format!("{}{}", c.amount, c.denom))
+        .collect();
+    Event::new(
+        "transfer",
+        vec![
+            attr("recipient", recipient),
+            attr("sender", sender),
+            attr("amount", amount.join(",")),
+        ],
+    )
+}
+
 // This can be Response, Response, MigrationResponse
 #[derive(Default, Clone)]
 pub struct ActionResponse<C>
@@ -26,8 +81,11 @@ where
     C: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
     // TODO: allow T != Empty
+    pub submessages: Vec<SubMsg<C>>,
     pub messages: Vec<CosmosMsg<C>>,
     pub attributes: Vec<Attribute>,
+    /// events emitted by the App before the contract returned, eg. `execute`
+    pub events: Vec<Event>,
     pub data: Option<Binary>,
 }
 
@@ -37,8 +95,10 @@ where
 {
     fn from(input: Response<C>) -> Self {
         ActionResponse {
+            submessages: input.submessages,
             messages: input.messages,
             attributes: input.attributes,
+            events: vec![],
             data: input.data,
         }
     }
@@ -50,8 +110,10 @@ where
 {
     fn init(input: Response<C>, address: HumanAddr) -> Self {
         ActionResponse {
+            submessages: input.submessages,
             messages: input.messages,
             attributes: input.attributes,
+            events: vec![],
             data: Some(address.as_bytes().into()),
         }
     }
@@ -62,7 +124,10 @@ where
     C: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
     fn raw_query(&self, bin_request: &[u8]) -> QuerierResult {
-        // TODO: we need to make a new custom type for queries
+        // custom queries only parse with the query type of the custom handler, so it goes first
+        if let Some(res) = self.custom.query(&self.block_info(), bin_request) {
+            return SystemResult::Ok(res.into());
+        }
         let request: QueryRequest<Empty> = match from_slice(bin_request) {
             Ok(v) => v,
             Err(e) => {
@@ -72,7 +137,11 @@ where
                 })
             }
         };
-        let contract_result: ContractResult<Binary> = self.query(request).into();
+        let res = match request {
+            QueryRequest::Custom(_) => Err("No custom handler for this query".to_string()),
+            request => self.query(request),
+        };
+        let contract_result: ContractResult<Binary> = res.into();
         SystemResult::Ok(contract_result)
     }
 }
@@ -89,6 +158,8 @@ where
 {
     wasm: WasmRouter<C>,
     bank: BankRouter,
+    staking: StakingRouter,
+    custom: CustomRouter<C>,
 }
 
 impl<C> App<C>
@@ -100,25 +171,63 @@ where
         block: BlockInfo,
         bank: B,
         storage_factory: StorageFactory,
+    ) -> Self {
+        Self::new_with_staking(api, block, bank, SimpleStaking::default(), storage_factory)
+    }
+
+    /// Like new, but with a custom staking module, eg. a SimpleStaking with rewards
+    pub fn new_with_staking<B: Bank + 'static, S: Staking + 'static>(
+        api: Box<dyn Api>,
+        block: BlockInfo,
+        bank: B,
+        staking: S,
+        storage_factory: StorageFactory,
     ) -> Self {
         App {
             wasm: WasmRouter::new(api, block, storage_factory),
             bank: BankRouter::new(bank, storage_factory()),
+            staking: StakingRouter::new(staking, storage_factory()),
+            custom: CustomRouter::new(storage_factory()),
         }
     }
 
+    /// Registers the handler for CosmosMsg::Custom messages and QueryRequest::Custom<Q> queries.
+    /// Without one, both return an error.
+    pub fn set_custom_handler<H, Q>(&mut self, handler: H)
+    where
+        H: CustomHandler<C, Q> + 'static,
+        Q: CustomQuery + DeserializeOwned + 'static,
+        C: 'static,
+    {
+        self.custom.set_handler(handler);
+    }
+
     pub fn cache(&'_ self) -> AppCache<'_, C> {
         AppCache::new(self)
     }
 
     /// This can set the block info to any value. Must be done before taking a cache
+    /// Unbonding delegations that are complete at the new block are paid out.
     pub fn set_block(&mut self, block: BlockInfo) {
         self.wasm.set_block(block);
+        self.process_staking_queue();
     }
 
     /// This let's use use "next block" steps that add eg. one height and 5 seconds
+    /// Unbonding delegations that are complete at the new block are paid out.
     pub fn update_block<F: Fn(&mut BlockInfo)>(&mut self, action: F) {
         self.wasm.update_block(action);
+        self.process_staking_queue();
+    }
+
+    // This panics if the staking module cannot pay out, which only happens if a test
+    // changed its balance, eg. with set_bank_balance
+    fn process_staking_queue(&mut self) {
+        let mut cache = self.cache();
+        if let Err(e) = cache.process_staking_queue() {
+            panic!("staking module cannot pay out unbonded coins: {}", e);
+        }
+        cache.prepare().commit(self);
     }
 
     /// Returns a copy of the current block_info
@@ -135,6 +244,11 @@ where
         self.bank.set_balance(account, amount)
     }
 
+    /// This is an "admin" function to let us add validators to the staking module
+    pub fn add_validator(&mut self, validator: Validator) -> Result<(), String> {
+        self.staking.add_validator(validator)
+    }
+
     /// This registers contract code (like uploading wasm bytecode on a chain),
     /// so it can later be used to instantiate a contract.
     pub fn store_code(&mut self, code: Box<dyn Contract<C>>) -> u64 {
@@ -153,6 +267,7 @@ where
         match request {
             QueryRequest::Wasm(req) => self.wasm.query(self, req),
             QueryRequest::Bank(req) => self.bank.query(req),
+            QueryRequest::Staking(req) => self.staking.query(&self.block_info(), req),
             _ => unimplemented!(),
         }
     }
@@ -180,6 +295,88 @@ where
         parse_contract_addr(&res.data)
     }
 
+    /// Create a contract with an admin, who may later migrate it, and get the new address.
+    /// WasmMsg::Instantiate has no admin, so this calls into the cache directly.
+    pub fn instantiate_contract_with_admin<T: Serialize, U: Into<String>, V: Into<HumanAddr>>(
+        &mut self,
+        code_id: u64,
+        sender: V,
+        init_msg: &T,
+        send_funds: &[Coin],
+        label: U,
+        admin: Option<HumanAddr>,
+    ) -> Result<HumanAddr, String> {
+        let init_msg = to_binary(init_msg).map_err(|e| e.to_string())?;
+        let mut cache = self.cache();
+
+        let res = cache
+            .instantiate(
+                sender.into(),
+                code_id,
+                init_msg,
+                send_funds.to_vec(),
+                label.into(),
+                admin,
+            )
+            .and_then(|(addr, res)| cache.process_response(addr, res));
+
+        // this only happens if all messages run successfully
+        if res.is_ok() {
+            let ops = cache.prepare();
+            ops.commit(self);
+        }
+        parse_contract_addr(&res?.data)
+    }
+
+    /// Migrate a contract to new code and process all returned messages.
+    /// Only the admin of the contract may do so.
+    /// This is just a helper around execute()
+    pub fn migrate_contract<T: Serialize, U: Into<HumanAddr>, V: Into<HumanAddr>>(
+        &mut self,
+        sender: U,
+        contract_addr: V,
+        new_code_id: u64,
+        msg: &T,
+    ) -> Result<AppResponse, String> {
+        let msg = to_binary(msg).map_err(|e| e.to_string())?;
+        let msg = WasmMsg::Migrate {
+            contract_addr: contract_addr.into(),
+            new_code_id,
+            msg,
+        }
+        .into();
+        self.execute(sender.into(), msg)
+    }
+
+    /// Sets a new admin of the contract, or none to make it immutable.
+    /// Only the current admin may do so.
+    pub fn update_admin<U: Into<HumanAddr>, V: Into<HumanAddr>>(
+        &mut self,
+        sender: U,
+        contract_addr: V,
+        new_admin: Option<HumanAddr>,
+    ) -> Result<(), String> {
+        let mut cache = self.cache();
+        cache
+            .wasm
+            .update_admin(contract_addr.into(), sender.into(), new_admin)?;
+        cache.prepare().commit(self);
+        Ok(())
+    }
+
+    /// Returns the admin of the contract, if any
+    pub fn contract_admin<U: Into<HumanAddr>>(
+        &self,
+        contract_addr: U,
+    ) -> Result<Option<HumanAddr>, String> {
+        self.wasm.contract_admin(&contract_addr.into())
+    }
+
+    /// Returns the label the contract was instantiated with
+    pub fn contract_label<U: Into<HumanAddr>>(&self, contract_addr: U) -> Result<String, String> {
+        self.wasm.contract_label(&contract_addr.into())
+    }
+
     /// Execute a contract and process all returned messages.
     /// This is just a helper around execute()
     pub fn execute_contract<T: Serialize, U: Into<HumanAddr>>(
@@ -265,11 +462,15 @@ where
     router: &'a App<C>,
     wasm: WasmCache<'a, C>,
     bank: BankCache<'a>,
+    staking: StakingCache<'a>,
+    custom: CustomCache<'a, C>,
 }
 
 pub struct AppOps {
     wasm: WasmOps,
     bank: BankOps,
+    staking: StakingOps,
+    custom: CustomOps,
 }
 
 impl AppOps {
@@ -278,8 +479,21 @@ impl AppOps {
         C: Clone + fmt::Debug + PartialEq + JsonSchema,
     {
         self.bank.commit(&mut router.bank);
+        self.staking.commit(&mut router.staking);
+        self.custom.commit(&mut router.custom);
         self.wasm.commit(&mut router.wasm);
     }
+
+    /// Applies the changes of a nested cache to the cache it was created from
+    pub fn commit_cache<C>(self, cache: &mut AppCache<C>)
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
+        self.bank.commit_cache(&mut cache.bank);
+        self.staking.commit_cache(&mut cache.staking);
+        self.custom.commit_cache(&mut cache.custom);
+        self.wasm.commit_cache(&mut cache.wasm);
+    }
 }
 
 impl<'a, C> AppCache<'a, C>
@@ -291,6 +505,20 @@ where
             router,
             wasm: router.wasm.cache(),
             bank: router.bank.cache(),
+            staking: router.staking.cache(),
+            custom: router.custom.cache(),
+        }
+    }
+
+    /// Creates a nested cache on top of this one, eg. to run a submessage,
+    /// whose changes can be applied to this one with `AppOps::commit_cache`, or dropped.
+    pub fn cache(&'_ self) -> AppCache<'_, C> {
+        AppCache {
+            router: self.router,
+            wasm: self.wasm.cache(),
+            bank: self.bank.cache(),
+            staking: self.staking.cache(),
+            custom: self.custom.cache(),
         }
     }
 
@@ -301,6 +529,8 @@ where
         AppOps {
             wasm: self.wasm.prepare(),
             bank: self.bank.prepare(),
+            staking: self.staking.prepare(),
+            custom: self.custom.prepare(),
         }
     }
 
@@ -314,40 +544,142 @@ where
         match msg {
             CosmosMsg::Wasm(msg) => {
                 let (resender, res) = self.handle_wasm(sender, msg)?;
-                let mut attributes = res.attributes;
-                // recurse in all messages
-                for resend in res.messages {
-                    let subres = self.execute(resender.clone(), resend)?;
-                    // ignore the data now, just like in wasmd
-                    // append the events
-                    attributes.extend_from_slice(&subres.attributes);
-                }
-                Ok(AppResponse {
-                    attributes,
-                    data: res.data,
-                })
+                self.process_response(resender, res)
             }
             CosmosMsg::Bank(msg) => {
+                let events = match &msg {
+                    BankMsg::Send { to_address, amount } => {
+                        vec![transfer_event(&sender, to_address, amount)]
+                    }
+                    _ => vec![],
+                };
                 self.bank.execute(sender, msg)?;
+                Ok(AppResponse {
+                    events,
+                    ..AppResponse::default()
+                })
+            }
+            CosmosMsg::Staking(msg) => {
+                self.handle_staking(sender, msg)?;
                 Ok(AppResponse::default())
             }
+            CosmosMsg::Custom(msg) => {
+                let block = self.router.block_info();
+                self.custom.execute(&mut self.bank, &block, sender, msg)
+            }
             _ => unimplemented!(),
         }
     }
 
+    fn handle_staking(&mut self, sender: HumanAddr, msg: StakingMsg) -> Result<(), String> {
+        // the staking module holds the delegated coins
+        if let StakingMsg::Delegate { amount, .. } = &msg {
+            self.send(&sender, STAKING_MODULE, std::slice::from_ref(amount))?;
+        }
+        let block = self.router.block_info();
+        let rewards = self.staking.execute(&block, sender, msg)?;
+        for (recipient, amount) in rewards {
+            self.bank.mint(recipient, vec![amount])?;
+        }
+        Ok(())
+    }
+
+    /// Pays out all unbonding delegations that are complete at the current block
+    fn process_staking_queue(&mut self) -> Result<(), String> {
+        let block = self.router.block_info();
+        let released = self.staking.process_queue(&block)?;
+        for (delegator, amount) in released {
+            self.send(STAKING_MODULE, delegator, &[amount])?;
+        }
+        Ok(())
+    }
+
     fn sudo(&mut self, contract_addr: HumanAddr, msg: Vec<u8>) -> Result<AppResponse, String> {
         let res = self.wasm.sudo(contract_addr.clone(), self.router, msg)?;
+        let mut res: ActionResponse<C> = res.into();
+        res.events = vec![contract_event("sudo", &contract_addr, &[])];
+        self.process_response(contract_addr, res)
+    }
+
+    /// Runs the submessages (with their replies) and then the messages returned by a contract
+    fn process_response(
+        &mut self,
+        contract: HumanAddr,
+        res: ActionResponse<C>,
+    ) -> Result<AppResponse, String> {
+        let mut events = res.events;
+        if !res.attributes.is_empty() {
+            events.push(contract_event("wasm", &contract, &res.attributes));
+        }
         let mut attributes = res.attributes;
+        let mut data = res.data;
+        // submessages run first, and the reply may overwrite the data
+        for sub in res.submessages {
+            let subres = self.execute_submsg(contract.clone(), sub)?;
+            attributes.extend_from_slice(&subres.attributes);
+            events.extend_from_slice(&subres.events);
+            if subres.data.is_some() {
+                data = subres.data;
+            }
+        }
         // recurse in all messages
         for resend in res.messages {
-            let subres = self.execute(contract_addr.clone(), resend)?;
+            let subres = self.execute(contract.clone(), resend)?;
             // ignore the data now, just like in wasmd
             // append the events
             attributes.extend_from_slice(&subres.attributes);
+            events.extend_from_slice(&subres.events);
         }
         Ok(AppResponse {
             attributes,
-            data: res.data,
+            events,
+            data,
+        })
+    }
+
+    /// Runs the submessage in a nested cache, which is only committed if it succeeds,
+    /// and then calls reply on the contract with the result. Only an error in reply
+    /// makes this fail, just like in wasmd.
+    fn execute_submsg(
+        &mut self,
+        contract: HumanAddr,
+        sub: SubMsg<C>,
+    ) -> Result<AppResponse, String> {
+        let SubMsg { id, msg, .. } = sub;
+        let res = {
+            let mut subcache = self.cache();
+            subcache
+                .execute(contract.clone(), msg)
+                .map(|res| (res, subcache.prepare()))
+        };
+
+        // the events of a failed submessage are dropped along with its changes
+        let mut attributes = vec![];
+        let mut events = vec![];
+        let result = match res {
+            Ok((res, ops)) => {
+                ops.commit_cache(self);
+                attributes = res.attributes;
+                events = res.events.clone();
+                ContractResult::Ok(SubcallResponse {
+                    events: res.events,
+                    data: res.data,
+                })
+            }
+            Err(e) => ContractResult::Err(e),
+        };
+
+        let reply = Reply { id, result };
+        let res = self.wasm.reply(contract.clone(), self.router, reply)?;
+        let mut res: ActionResponse<C> = res.into();
+        res.events = vec![contract_event("reply", &contract, &[])];
+        let mut reply_res = self.process_response(contract, res)?;
+        attributes.append(&mut reply_res.attributes);
+        events.append(&mut reply_res.events);
+        Ok(AppResponse {
+            attributes,
+            events,
+            data: reply_res.data,
         })
     }
 
@@ -364,7 +696,8 @@ where
                 send,
             } => {
                 // first move the cash
-                self.send(&sender, &contract_addr, &send)?;
+                let mut events = self.send(&sender, &contract_addr, &send)?.events;
+                events.push(contract_event("execute", &contract_addr, &[]));
                 // then call the contract
                 let info = MessageInfo {
                     sender,
@@ -373,50 +706,93 @@ where
                 let res =
                     self.wasm
                         .handle(contract_addr.clone(), self.router, info, msg.to_vec())?;
-                Ok((contract_addr, res.into()))
+                let mut res: ActionResponse<C> = res.into();
+                res.events = events;
+                Ok((contract_addr, res))
             }
             WasmMsg::Instantiate {
                 code_id,
                 msg,
                 send,
-                label: _,
+                label,
+            } => self.instantiate(sender, code_id, msg, send, label, None),
+            WasmMsg::Migrate {
+                contract_addr,
+                new_code_id,
+                msg,
             } => {
-                let contract_addr = self.wasm.register_contract(code_id as usize)?;
-                // move the cash
-                self.send(&sender, &contract_addr, &send)?;
-                // then call the contract
-                let info = MessageInfo {
-                    sender,
-                    funds: send,
-                };
-                let res = self
-                    .wasm
-                    .init(contract_addr.clone(), self.router, info, msg.to_vec())?;
-                Ok((
+                let res = self.wasm.migrate(
                     contract_addr.clone(),
-                    ActionResponse::init(res, contract_addr),
-                ))
+                    self.router,
+                    sender,
+                    new_code_id as usize,
+                    msg.to_vec(),
+                )?;
+                let mut res: ActionResponse<C> = res.into();
+                res.events = vec![contract_event(
+                    "migrate",
+                    &contract_addr,
+                    &[attr("code_id", new_code_id)],
+                )];
+                Ok((contract_addr, res))
             }
-            WasmMsg::Migrate { .. } => unimplemented!(),
             m => panic!("Unsupported wasm message: {:?}", m),
         }
     }
 
+    fn instantiate(
+        &mut self,
+        sender: HumanAddr,
+        code_id: u64,
+        msg: Binary,
+        send: Vec<Coin>,
+        label: String,
+        admin: Option<HumanAddr>,
+    ) -> Result<(HumanAddr, ActionResponse<C>), String> {
+        let contract_addr = self
+            .wasm
+            .register_contract(code_id as usize, admin, label)?;
+        // move the cash
+        let mut events = self.send(&sender, &contract_addr, &send)?.events;
+        events.push(contract_event(
+            "instantiate",
+            &contract_addr,
+            &[attr("code_id", code_id)],
+        ));
+        // then call the contract
+        let info = MessageInfo {
+            sender,
+            funds: send,
+        };
+        let res = self
+            .wasm
+            .init(contract_addr.clone(), self.router, info, msg.to_vec())?;
+        let mut res = ActionResponse::init(res, contract_addr.clone());
+        res.events = events;
+        Ok((contract_addr, res))
+    }
+
     fn send<T: Into<HumanAddr>, U: Into<HumanAddr>>(
         &mut self,
         sender: T,
         recipient: U,
         amount: &[Coin],
     ) -> Result<AppResponse, String> {
-        if !amount.is_empty() {
-            let sender: HumanAddr = sender.into();
-            let msg = BankMsg::Send {
-                to_address: recipient.into(),
-                amount: amount.to_vec(),
-            };
-            self.bank.execute(sender, msg)?;
+        if amount.is_empty() {
+            return Ok(AppResponse::default());
         }
-        Ok(AppResponse::default())
+        let sender: HumanAddr = sender.into();
+        let recipient: HumanAddr = recipient.into();
+        let events = vec![transfer_event(&sender, &recipient, amount)];
+        let msg = BankMsg::Send {
+            to_address: recipient,
+            amount: amount.to_vec(),
+        };
+        self.bank.execute(sender, msg)?;
+        Ok(AppResponse {
+            events,
+            ..AppResponse::default()
+        })
     }
 }
 
@@ -433,13 +809,16 @@ pub fn parse_contract_addr(data: &Option<Binary>) -> Result<HumanAddr, String> {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::staking::DelegationResponse;
     use crate::test_helpers::{
-        contract_payout, contract_payout_custom, contract_reflect, CustomMsg, EmptyMsg,
-        PayoutMessage, ReflectMessage, ReflectResponse, ReflectSudoMsg,
+        contract_ledger, contract_payout, contract_payout_custom, contract_payout_migrate,
+        contract_reflect, storage_reads, CountingStorage, CustomEmulator, CustomMsg,
+        CustomQueryMsg, EmptyMsg, LedgerMessage, LedgerQuery, PayoutMessage, ReflectMessage,
+        ReflectResponse, ReflectSudoMsg, REFLECT_REPLIES,
     };
     use crate::SimpleBank;
     use cosmwasm_std::testing::MockStorage;
-    use cosmwasm_std::{attr, coin, coins};
+    use cosmwasm_std::{attr, coin, coins, Decimal, StakingQuery};
 
     fn mock_router() -> App {
         let env = mock_env();
@@ -556,6 +935,382 @@ mod test {
         assert_eq!(funds, coins(18, "eth"));
     }
 
+    #[test]
+    fn events_of_nested_calls() {
+        let mut router = custom_router();
+
+        let owner = HumanAddr::from("owner");
+        let init_funds = vec![coin(20, "btc"), coin(100, "eth")];
+        router.set_bank_balance(owner.clone(), init_funds).unwrap();
+
+        let payout_id = router.store_code(contract_payout_custom());
+        let msg = PayoutMessage {
+            payout: coin(5, "eth"),
+        };
+        let payout_addr = router
+            .instantiate_contract(payout_id, &owner, &msg, &coins(10, "eth"), "Payout")
+            .unwrap();
+        let reflect_id = router.store_code(contract_reflect());
+        let reflect_addr = router
+            .instantiate_contract(reflect_id, &owner, &EmptyMsg {}, &[], "Reflect")
+            .unwrap();
+
+        // reflect calls payout, which pays out to reflect
+        let msg = WasmMsg::Execute {
+            contract_addr: payout_addr.clone(),
+            msg: to_binary(&EmptyMsg {}).unwrap(),
+            send: vec![],
+        };
+        let msgs = ReflectMessage {
+            messages: vec![msg.into()],
+            sub_msgs: vec![],
+        };
+        let res = router
+            .execute_contract(&owner, &reflect_addr, &msgs, &coins(3, "btc"))
+            .unwrap();
+
+        // the flattened attributes are still there
+        assert_eq!(res.attributes, vec![attr("action", "payout")]);
+        let expected = vec![
+            Event::new(
+                "transfer",
+                vec![
+                    attr("recipient", &reflect_addr),
+                    attr("sender", &owner),
+                    attr("amount", "3btc"),
+                ],
+            ),
+            Event::new("execute", vec![attr("_contract_address", &reflect_addr)]),
+            Event::new("execute", vec![attr("_contract_address", &payout_addr)]),
+            Event::new(
+                "wasm",
+                vec![
+                    attr("_contract_address", &payout_addr),
+                    attr("action", "payout"),
+                ],
+            ),
+            Event::new(
+                "transfer",
+                vec![
+                    attr("recipient", &reflect_addr),
+                    attr("sender", &payout_addr),
+                    attr("amount", "5eth"),
+                ],
+            ),
+        ];
+        assert_eq!(res.events, expected);
+
+        // match on some attributes only
+        res.assert_event(&Event::new("wasm", vec![attr("action", "payout")]));
+        assert!(res.has_event(&Event::new(
+            "wasm",
+            vec![attr("_contract_address", &payout_addr)]
+        )));
+        assert!(!res.has_event(&Event::new(
+            "wasm",
+            vec![attr("_contract_address", &reflect_addr)]
+        )));
+        assert!(!res.has_event(&Event::new("instantiate", vec![])));
+    }
+
+    #[test]
+    fn migrate_contract_by_admin() {
+        let mut router = mock_router();
+
+        let owner = HumanAddr::from("owner");
+        let admin = HumanAddr::from("admin");
+        let random = HumanAddr::from("random");
+
+        let code_id = router.store_code(contract_payout());
+        let new_code_id = router.store_code(contract_payout_migrate());
+        let msg = PayoutMessage {
+            payout: coin(5, "eth"),
+        };
+        let contract_addr = router
+            .instantiate_contract_with_admin(
+                code_id,
+                &owner,
+                &msg,
+                &[],
+                "Payout",
+                Some(admin.clone()),
+            )
+            .unwrap();
+        assert_eq!(
+            router.contract_admin(&contract_addr).unwrap(),
+            Some(admin.clone())
+        );
+        assert_eq!(router.contract_label(&contract_addr).unwrap(), "Payout");
+
+        // only the admin can migrate
+        let new_msg = PayoutMessage {
+            payout: coin(7, "btc"),
+        };
+        let err = router
+            .migrate_contract(&owner, &contract_addr, new_code_id, &new_msg)
+            .unwrap_err();
+        assert!(err.contains("Only the admin"), "{}", err);
+        // and only to registered code
+        router
+            .migrate_contract(&admin, &contract_addr, new_code_id + 1, &new_msg)
+            .unwrap_err();
+
+        let res = router
+            .migrate_contract(&admin, &contract_addr, new_code_id, &new_msg)
+            .unwrap();
+        assert_eq!(res.attributes, vec![attr("action", "migrate")]);
+        let payout: PayoutMessage = router
+            .wrap()
+            .query_wasm_smart(&contract_addr, &EmptyMsg {})
+            .unwrap();
+        assert_eq!(payout.payout, coin(7, "btc"));
+
+        // the old code has no migrate entry point
+        let err = router
+            .migrate_contract(&admin, &contract_addr, code_id, &msg)
+            .unwrap_err();
+        assert!(err.contains("migrate not implemented"), "{}", err);
+
+        // only the admin can change the admin
+        router
+            .update_admin(&random, &contract_addr, Some(random.clone()))
+            .unwrap_err();
+        router.update_admin(&admin, &contract_addr, None).unwrap();
+        assert_eq!(router.contract_admin(&contract_addr).unwrap(), None);
+
+        // no one can migrate a contract without an admin
+        router
+            .migrate_contract(&admin, &contract_addr, new_code_id, &new_msg)
+            .unwrap_err();
+        let other_addr = router
+            .instantiate_contract(code_id, &owner, &msg, &[], "Other payout")
+            .unwrap();
+        assert_eq!(router.contract_admin(&other_addr).unwrap(), None);
+        assert_eq!(router.contract_label(&other_addr).unwrap(), "Other payout");
+        router
+            .migrate_contract(&owner, &other_addr, new_code_id, &new_msg)
+            .unwrap_err();
+    }
+
+    #[test]
+    fn staking_and_unbonding() {
+        let env = mock_env();
+        let api = Box::new(MockApi::default());
+        let staking = SimpleStaking::new("stake", Decimal::percent(10), 1000);
+        let mut router: App = App::new_with_staking(api, env.block, SimpleBank {}, staking, || {
+            Box::new(MockStorage::new())
+        });
+        router
+            .add_validator(Validator {
+                address: "val1".into(),
+                commission: Decimal::zero(),
+                max_commission: Decimal::percent(10),
+                max_change_rate: Decimal::percent(1),
+            })
+            .unwrap();
+
+        let owner = HumanAddr::from("owner");
+        router
+            .set_bank_balance(owner.clone(), coins(2000, "stake"))
+            .unwrap();
+
+        // cannot delegate more than we have
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(3000, "stake"),
+        };
+        router
+            .execute(owner.clone(), CosmosMsg::Staking(msg))
+            .unwrap_err();
+
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(1000, "stake"),
+        };
+        router
+            .execute(owner.clone(), CosmosMsg::Staking(msg))
+            .unwrap();
+        assert_eq!(get_balance(&router, &owner), coins(1000, "stake"));
+        assert_eq!(
+            get_balance(&router, &STAKING_MODULE.into()),
+            coins(1000, "stake")
+        );
+
+        let query = StakingQuery::Delegation {
+            delegator: owner.clone(),
+            validator: "val1".into(),
+        };
+        let raw = router.query(QueryRequest::Staking(query)).unwrap();
+        let res: DelegationResponse = from_slice(&raw).unwrap();
+        assert_eq!(res.delegation.unwrap().amount, coin(1000, "stake"));
+
+        // rewards are minted when withdrawn
+        router.update_block(|block| block.time += 365 * 24 * 60 * 60 / 10);
+        let msg = StakingMsg::Withdraw {
+            validator: "val1".into(),
+            recipient: None,
+        };
+        router
+            .execute(owner.clone(), CosmosMsg::Staking(msg))
+            .unwrap();
+        assert_eq!(get_balance(&router, &owner), coins(1010, "stake"));
+
+        // unbonded coins come back after the unbonding time
+        let msg = StakingMsg::Undelegate {
+            validator: "val1".into(),
+            amount: coin(600, "stake"),
+        };
+        router
+            .execute(owner.clone(), CosmosMsg::Staking(msg))
+            .unwrap();
+        router.update_block(|block| block.time += 999);
+        assert_eq!(get_balance(&router, &owner), coins(1010, "stake"));
+        router.update_block(|block| block.time += 1);
+        assert_eq!(get_balance(&router, &owner), coins(1610, "stake"));
+        assert_eq!(
+            get_balance(&router, &STAKING_MODULE.into()),
+            coins(400, "stake")
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "staking module cannot pay out unbonded coins")]
+    fn unbonding_needs_staking_balance() {
+        let env = mock_env();
+        let api = Box::new(MockApi::default());
+        let staking = SimpleStaking::new("stake", Decimal::percent(10), 1000);
+        let mut router: App = App::new_with_staking(api, env.block, SimpleBank {}, staking, || {
+            Box::new(MockStorage::new())
+        });
+        router
+            .add_validator(Validator {
+                address: "val1".into(),
+                commission: Decimal::zero(),
+                max_commission: Decimal::percent(10),
+                max_change_rate: Decimal::percent(1),
+            })
+            .unwrap();
+
+        let owner = HumanAddr::from("owner");
+        router
+            .set_bank_balance(owner.clone(), coins(1000, "stake"))
+            .unwrap();
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(1000, "stake"),
+        };
+        router
+            .execute(owner.clone(), CosmosMsg::Staking(msg))
+            .unwrap();
+        let msg = StakingMsg::Undelegate {
+            validator: "val1".into(),
+            amount: coin(400, "stake"),
+        };
+        router.execute(owner, CosmosMsg::Staking(msg)).unwrap();
+
+        // the test drained the staking module, so the payout panics
+        router
+            .set_bank_balance(STAKING_MODULE.into(), coins(100, "stake"))
+            .unwrap();
+        router.update_block(|block| block.time += 1000);
+    }
+
+    #[test]
+    fn custom_handler() {
+        let mut router = custom_router();
+
+        let owner = HumanAddr::from("owner");
+        let reflect_id = router.store_code(contract_reflect());
+        let reflect_addr = router
+            .instantiate_contract(reflect_id, &owner, &EmptyMsg {}, &[], "Reflect")
+            .unwrap();
+
+        // without a handler, custom messages and queries fail
+        let msgs = ReflectMessage {
+            messages: vec![CosmosMsg::Custom(CustomMsg::SetName {
+                name: "Alice".to_string(),
+            })],
+            sub_msgs: vec![],
+        };
+        router
+            .execute_contract(&owner, &reflect_addr, &msgs, &[])
+            .unwrap_err();
+        let query = QueryRequest::Custom(CustomQueryMsg::Name {});
+        router
+            .wrap()
+            .custom_query::<_, Option<String>>(&query)
+            .unwrap_err();
+
+        router.set_custom_handler(CustomEmulator {});
+        let res = router
+            .execute_contract(&owner, &reflect_addr, &msgs, &[])
+            .unwrap();
+        assert_eq!(res.attributes, vec![attr("action", "custom")]);
+        let name: Option<String> = router.wrap().custom_query(&query).unwrap();
+        assert_eq!(name, Some("Alice".to_string()));
+
+        // a failing custom message reverts the changes of the others
+        let msgs = ReflectMessage {
+            messages: vec![
+                CosmosMsg::Custom(CustomMsg::SetAge { age: 30 }),
+                CosmosMsg::Custom(CustomMsg::SetAge { age: 200 }),
+            ],
+            sub_msgs: vec![],
+        };
+        router
+            .execute_contract(&owner, &reflect_addr, &msgs, &[])
+            .unwrap_err();
+        let query = QueryRequest::Custom(CustomQueryMsg::Age {});
+        let age: Option<u32> = router.wrap().custom_query(&query).unwrap();
+        assert_eq!(age, None);
+
+        // other queries still work
+        let funds = get_balance(&router, &owner);
+        assert_eq!(funds, vec![]);
+
+        // malformed queries are rejected as invalid, not passed to the handler
+        let res = router.raw_query(br#"{"bank":{"balance":{}}}"#);
+        assert!(matches!(
+            res,
+            SystemResult::Err(SystemError::InvalidRequest { .. })
+        ));
+    }
+
+    #[test]
+    fn custom_handler_swaps_coins() {
+        let mut router = custom_router();
+        router.set_custom_handler(CustomEmulator {});
+
+        let owner = HumanAddr::from("owner");
+        let reflect_id = router.store_code(contract_reflect());
+        let reflect_addr = router
+            .instantiate_contract(reflect_id, &owner, &EmptyMsg {}, &[], "Reflect")
+            .unwrap();
+        router
+            .set_bank_balance(reflect_addr.clone(), coins(100, "uluna"))
+            .unwrap();
+
+        let swap = |amount| ReflectMessage {
+            messages: vec![CosmosMsg::Custom(CustomMsg::Swap {
+                offer: coin(amount, "uluna"),
+                ask_denom: "uusd".to_string(),
+            })],
+            sub_msgs: vec![],
+        };
+        router
+            .execute_contract(&owner, &reflect_addr, &swap(30), &[])
+            .unwrap();
+        let funds = get_balance(&router, &reflect_addr);
+        assert_eq!(funds, vec![coin(70, "uluna"), coin(60, "uusd")]);
+
+        // cannot offer more than the balance, nothing is minted
+        router
+            .execute_contract(&owner, &reflect_addr, &swap(80), &[])
+            .unwrap_err();
+        let funds = get_balance(&router, &reflect_addr);
+        assert_eq!(funds, vec![coin(70, "uluna"), coin(60, "uusd")]);
+    }
+
     #[test]
     fn reflect_success() {
         let mut router = custom_router();
@@ -601,6 +1356,7 @@ mod test {
         .into();
         let msgs = ReflectMessage {
             messages: vec![msg],
+            sub_msgs: vec![],
         };
         let res = router
             .execute_contract(&HumanAddr::from("random"), &reflect_addr, &msgs, &[])
@@ -658,6 +1414,7 @@ mod test {
         .into();
         let msgs = ReflectMessage {
             messages: vec![msg],
+            sub_msgs: vec![],
         };
         let res = router
             .execute_contract(&random, &reflect_addr, &msgs, &[])
@@ -687,6 +1444,7 @@ mod test {
         .into();
         let msgs = ReflectMessage {
             messages: vec![msg, msg2],
+            sub_msgs: vec![],
         };
         let err = router
             .execute_contract(&random, &reflect_addr, &msgs, &[])
@@ -705,6 +1463,207 @@ mod test {
         assert_eq!(2, qres.count);
     }
 
+    // returns the reply the reflect contract received for the given id
+    fn get_reply(router: &App<CustomMsg>, reflect_addr: &HumanAddr, id: u64) -> Option<Reply> {
+        let key = REFLECT_REPLIES.key(id.into()).to_vec();
+        let raw = router.wrap().query_wasm_raw(reflect_addr, key).unwrap();
+        raw.map(|r| from_slice(&r).unwrap())
+    }
+
+    #[test]
+    fn reflect_submessage_reply() {
+        let mut router = custom_router();
+
+        let owner = HumanAddr::from("owner");
+        let init_funds = vec![coin(20, "btc"), coin(100, "eth")];
+        router.set_bank_balance(owner.clone(), init_funds).unwrap();
+
+        let reflect_id = router.store_code(contract_reflect());
+        let reflect_addr = router
+            .instantiate_contract(
+                reflect_id,
+                &owner,
+                &EmptyMsg {},
+                &coins(40, "eth"),
+                "Reflect",
+            )
+            .unwrap();
+        let random = HumanAddr::from("random");
+
+        // sending 7 eth in a submessage works, and calls reply
+        let msg = BankMsg::Send {
+            to_address: random.clone(),
+            amount: coins(7, "eth"),
+        };
+        let msgs = ReflectMessage {
+            messages: vec![],
+            sub_msgs: vec![SubMsg {
+                id: 7,
+                msg: msg.into(),
+                gas_limit: None,
+            }],
+        };
+        let res = router
+            .execute_contract(&random, &reflect_addr, &msgs, &[])
+            .unwrap();
+        assert_eq!(res.attributes, vec![attr("reply", 7)]);
+        assert_eq!(get_balance(&router, &random), coins(7, "eth"));
+        let reply = get_reply(&router, &reflect_addr, 7).unwrap();
+        assert_eq!(
+            reply.result,
+            ContractResult::Ok(SubcallResponse {
+                events: vec![Event::new(
+                    "transfer",
+                    vec![
+                        attr("recipient", &random),
+                        attr("sender", &reflect_addr),
+                        attr("amount", "7eth"),
+                    ]
+                )],
+                data: None
+            })
+        );
+
+        // a failing submessage is passed to reply, while the contract's own changes stay
+        let msg = BankMsg::Send {
+            to_address: random.clone(),
+            amount: coins(3, "btc"),
+        };
+        let msgs = ReflectMessage {
+            messages: vec![],
+            sub_msgs: vec![SubMsg {
+                id: 8,
+                msg: msg.into(),
+                gas_limit: None,
+            }],
+        };
+        router
+            .execute_contract(&random, &reflect_addr, &msgs, &[])
+            .unwrap();
+        let reply = get_reply(&router, &reflect_addr, 8).unwrap();
+        assert_eq!(
+            reply.result,
+            ContractResult::Err("Cannot subtract 3 from 0".to_string())
+        );
+        let ReflectResponse { count } = router
+            .wrap()
+            .query_wasm_smart(&reflect_addr, &EmptyMsg {})
+            .unwrap();
+        assert_eq!(3, count);
+    }
+
+    #[test]
+    fn failed_submessage_is_rolled_back() {
+        let mut router = custom_router();
+
+        let owner = HumanAddr::from("owner");
+        let init_funds = vec![coin(20, "btc"), coin(100, "eth")];
+        router.set_bank_balance(owner.clone(), init_funds).unwrap();
+
+        let reflect_id = router.store_code(contract_reflect());
+        let reflect_addr = router
+            .instantiate_contract(
+                reflect_id,
+                &owner,
+                &EmptyMsg {},
+                &coins(40, "eth"),
+                "Reflect",
+            )
+            .unwrap();
+        let random = HumanAddr::from("random");
+
+        // the submessage makes reflect call itself, pay 5 eth and then fail on 3 btc,
+        // and emit the attributes of the first reply
+        let pay = |amount| -> CosmosMsg<CustomMsg> {
+            BankMsg::Send {
+                to_address: random.clone(),
+                amount,
+            }
+            .into()
+        };
+        let inner = ReflectMessage {
+            messages: vec![pay(coins(5, "eth")), pay(coins(3, "btc"))],
+            sub_msgs: vec![SubMsg {
+                id: 1,
+                msg: pay(coins(1, "eth")),
+                gas_limit: None,
+            }],
+        };
+        let msg = WasmMsg::Execute {
+            contract_addr: reflect_addr.clone(),
+            msg: to_binary(&inner).unwrap(),
+            send: vec![],
+        };
+        let msgs = ReflectMessage {
+            messages: vec![],
+            sub_msgs: vec![SubMsg {
+                id: 2,
+                msg: msg.into(),
+                gas_limit: None,
+            }],
+        };
+        let res = router
+            .execute_contract(&random, &reflect_addr, &msgs, &[])
+            .unwrap();
+        // only the outer reply is left
+        assert_eq!(res.attributes, vec![attr("reply", 2)]);
+
+        // all changes of the submessage were reverted: no payment, no reply 1 and
+        // only the outer count increment
+        assert_eq!(get_balance(&router, &random), vec![]);
+        assert_eq!(get_balance(&router, &reflect_addr), coins(40, "eth"));
+        assert_eq!(get_reply(&router, &reflect_addr, 1), None);
+        let reply = get_reply(&router, &reflect_addr, 2).unwrap();
+        assert_eq!(
+            reply.result,
+            ContractResult::Err("Cannot subtract 3 from 0".to_string())
+        );
+        let ReflectResponse { count } = router
+            .wrap()
+            .query_wasm_smart(&reflect_addr, &EmptyMsg {})
+            .unwrap();
+        assert_eq!(2, count);
+    }
+
+    #[test]
+    fn reply_requires_entry_point() {
+        let mut router = mock_router();
+
+        let owner = HumanAddr::from("owner");
+        let payout_id = router.store_code(contract_payout());
+        let payout_addr = router
+            .instantiate_contract(
+                payout_id,
+                &owner,
+                &PayoutMessage {
+                    payout: coin(5, "eth"),
+                },
+                &[],
+                "Payout",
+            )
+            .unwrap();
+
+        // the payout contract doesn't implement reply
+        let mut cache = router.cache();
+        let res = ActionResponse {
+            submessages: vec![SubMsg {
+                id: 1,
+                msg: BankMsg::Send {
+                    to_address: owner.clone(),
+                    amount: coins(1, "eth"),
+                }
+                .into(),
+                gas_limit: None,
+            }],
+            messages: vec![],
+            attributes: vec![],
+            events: vec![],
+            data: None,
+        };
+        let err = cache.process_response(payout_addr, res).unwrap_err();
+        assert_eq!(err, "reply not implemented for contract");
+    }
+
     #[test]
     fn sudo_works() {
         let mut router = custom_router();
@@ -733,4 +1692,55 @@ mod test {
             .unwrap();
         assert_eq!(25, count);
     }
+
+    #[test]
+    fn cached_items_read_less() {
+        let env = mock_env();
+        let api = Box::new(MockApi::default());
+        let mut router = App::new(api, env.block, SimpleBank {}, || {
+            Box::new(CountingStorage::default())
+        });
+
+        let owner = HumanAddr::from("owner");
+        let code_id = router.store_code(contract_ledger());
+        let plain = router
+            .instantiate_contract(code_id, &owner, &EmptyMsg {}, &[], "Plain")
+            .unwrap();
+        let cached = router
+            .instantiate_contract(code_id, &owner, &EmptyMsg {}, &[], "Cached")
+            .unwrap();
+
+        let payouts: Vec<_> = vec![("john", 10), ("mary", 20), ("john", 30), ("john", 40)]
+            .into_iter()
+            .map(|(addr, amount)| (addr.to_string(), amount))
+            .collect();
+        let mut measure = |contract: &HumanAddr, cached: bool| {
+            let msg = LedgerMessage {
+                payouts: payouts.clone(),
+                cached,
+            };
+            let before = storage_reads();
+            router
+                .execute_contract(&owner, contract, &msg, &[])
+                .unwrap();
+            storage_reads() - before
+        };
+        let plain_reads = measure(&plain, false);
+        let cached_reads = measure(&cached, true);
+
+        // the fee is read once rather than 4 times. The balances are read from storage
+        // once either way, as the transaction of the execution holds the updates
+        assert_eq!(plain_reads - cached_reads, 3);
+
+        // with the same result
+        for (addr, expected) in &[("john", 77), ("mary", 19)] {
+            for contract in &[&plain, &cached] {
+                let query = LedgerQuery {
+                    address: addr.to_string(),
+                };
+                let balance: u64 = router.wrap().query_wasm_smart(*contract, &query).unwrap();
+                assert_eq!(balance, *expected);
+            }
+        }
+    }
 }
@@ -3,8 +3,8 @@ use cosmwasm_std::{
     Binary, Coin, HumanAddr, Storage,
 };
 
-use crate::transactions::{RepLog, StorageTransaction};
 use cw0::NativeBalance;
+use cw_storage_plus::{RepLog, StorageTransaction};
 
 /// Bank is a minimal contract-like interface that implements a bank module
 /// It is initialized outside of the trait
@@ -69,6 +69,11 @@ impl BankOps {
     pub fn commit(self, router: &mut BankRouter) {
         self.0.commit(router.storage.as_mut())
     }
+
+    /// Applies the changes of a nested cache to the cache it was created from
+    pub fn commit_cache(self, cache: &mut BankCache) {
+        self.0.commit(&mut cache.state)
+    }
 }
 
 impl<'a> BankCache<'a> {
@@ -86,9 +91,45 @@ impl<'a> BankCache<'a> {
         BankOps(self.state.prepare())
     }
 
+    /// Creates a nested cache on top of this one, whose changes can be applied to this one
+    /// with `BankOps::commit_cache`, or dropped.
+    pub fn cache(&'_ self) -> BankCache<'_> {
+        BankCache {
+            router: self.router,
+            state: StorageTransaction::new(&self.state),
+        }
+    }
+
     pub fn execute(&mut self, sender: HumanAddr, msg: BankMsg) -> Result<(), String> {
         self.router.bank.handle(&mut self.state, sender, msg)
     }
+
+    /// Adds coins to the account out of nowhere, eg. to pay staking rewards
+    pub fn mint(&mut self, recipient: HumanAddr, amount: Vec<Coin>) -> Result<(), String> {
+        let balance = self.all_balances(&recipient)?;
+        let total = NativeBalance(balance) + NativeBalance(amount);
+        self.router
+            .bank
+            .set_balance(&mut self.state, recipient, total.into_vec())
+    }
+
+    /// Removes coins from the account, failing if it doesn't hold enough
+    pub fn burn(&mut self, account: HumanAddr, amount: Vec<Coin>) -> Result<(), String> {
+        let balance = self.all_balances(&account)?;
+        let remaining = (NativeBalance(balance) - amount).map_err(|e| e.to_string())?;
+        self.router
+            .bank
+            .set_balance(&mut self.state, account, remaining.into_vec())
+    }
+
+    fn all_balances(&self, account: &HumanAddr) -> Result<Vec<Coin>, String> {
+        let query = BankQuery::AllBalances {
+            address: account.clone(),
+        };
+        let raw = self.router.bank.query(&self.state, query)?;
+        let balance: AllBalanceResponse = from_slice(&raw).map_err(|e| e.to_string())?;
+        Ok(balance.amount)
+    }
 }
 
 #[derive(Default)]
@@ -0,0 +1,188 @@
+use serde::de::DeserializeOwned;
+use std::marker::PhantomData;
+
+use cosmwasm_std::{from_slice, Binary, BlockInfo, CustomQuery, HumanAddr, QueryRequest, Storage};
+use cw_storage_plus::{RepLog, StorageTransaction};
+
+use crate::app::AppResponse;
+use crate::bank::BankCache;
+
+/// CustomHandler emulates the chain-specific modules, eg. a Terra-style market module.
+/// It handles the `CosmosMsg::Custom` messages returned by contracts, and the
+/// `QueryRequest::Custom` queries. It is initialized outside of the trait.
+///
+/// C is the custom message of the App, Q the custom query.
+pub trait CustomHandler<C, Q> {
+    /// The bank lets the handler move, mint or burn native coins, eg. for a swap.
+    /// Its changes are reverted along with the storage changes if the message fails.
+    fn execute(
+        &self,
+        storage: &mut dyn Storage,
+        bank: &mut BankCache,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: C,
+    ) -> Result<AppResponse, String>;
+
+    fn query(&self, storage: &dyn Storage, block: &BlockInfo, request: Q)
+        -> Result<Binary, String>;
+}
+
+/// This hides the query type of the handler, so App only needs to be generic over C.
+/// The handler parses the raw query itself.
+trait RawCustomHandler<C> {
+    fn execute(
+        &self,
+        storage: &mut dyn Storage,
+        bank: &mut BankCache,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: C,
+    ) -> Result<AppResponse, String>;
+
+    /// Returns None if the request is not a QueryRequest::Custom of the handler's query type
+    fn raw_query(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        bin_request: &[u8],
+    ) -> Option<Result<Binary, String>>;
+}
+
+struct TypedHandler<H, Q> {
+    handler: H,
+    query: PhantomData<Q>,
+}
+
+impl<C, Q, H> RawCustomHandler<C> for TypedHandler<H, Q>
+where
+    H: CustomHandler<C, Q>,
+    Q: CustomQuery + DeserializeOwned,
+{
+    fn execute(
+        &self,
+        storage: &mut dyn Storage,
+        bank: &mut BankCache,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: C,
+    ) -> Result<AppResponse, String> {
+        self.handler.execute(storage, bank, block, sender, msg)
+    }
+
+    fn raw_query(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        bin_request: &[u8],
+    ) -> Option<Result<Binary, String>> {
+        match from_slice(bin_request) {
+            Ok(QueryRequest::Custom(query)) => Some(self.handler.query(storage, block, query)),
+            _ => None,
+        }
+    }
+}
+
+pub struct CustomRouter<C> {
+    handler: Option<Box<dyn RawCustomHandler<C>>>,
+    storage: Box<dyn Storage>,
+}
+
+impl<C> CustomRouter<C> {
+    pub fn new(storage: Box<dyn Storage>) -> Self {
+        CustomRouter {
+            handler: None,
+            storage,
+        }
+    }
+
+    /// Registers the handler, replacing any previous one. Its state is kept.
+    pub fn set_handler<H, Q>(&mut self, handler: H)
+    where
+        H: CustomHandler<C, Q> + 'static,
+        Q: CustomQuery + DeserializeOwned + 'static,
+        C: 'static,
+    {
+        self.handler = Some(Box::new(TypedHandler {
+            handler,
+            query: PhantomData,
+        }));
+    }
+
+    pub fn has_handler(&self) -> bool {
+        self.handler.is_some()
+    }
+
+    pub fn cache(&'_ self) -> CustomCache<'_, C> {
+        CustomCache::new(self)
+    }
+
+    /// Takes the full serialized QueryRequest. Returns None if there is no handler, or the
+    /// request is not a QueryRequest::Custom of its query type, so the caller can handle it.
+    pub fn query(&self, block: &BlockInfo, bin_request: &[u8]) -> Option<Result<Binary, String>> {
+        self.handler
+            .as_ref()?
+            .raw_query(self.storage.as_ref(), block, bin_request)
+    }
+
+    fn handler(&self) -> Result<&dyn RawCustomHandler<C>, String> {
+        self.handler
+            .as_deref()
+            .ok_or_else(|| "No custom handler registered".to_string())
+    }
+}
+
+pub struct CustomCache<'a, C> {
+    router: &'a CustomRouter<C>,
+    state: StorageTransaction<'a>,
+}
+
+pub struct CustomOps(RepLog);
+
+impl CustomOps {
+    pub fn commit<C>(self, router: &mut CustomRouter<C>) {
+        self.0.commit(router.storage.as_mut())
+    }
+
+    /// Applies the changes of a nested cache to the cache it was created from
+    pub fn commit_cache<C>(self, cache: &mut CustomCache<C>) {
+        self.0.commit(&mut cache.state)
+    }
+}
+
+impl<'a, C> CustomCache<'a, C> {
+    fn new(router: &'a CustomRouter<C>) -> Self {
+        CustomCache {
+            router,
+            state: StorageTransaction::new(router.storage.as_ref()),
+        }
+    }
+
+    /// When we want to commit the CustomCache, we need a 2 step process to satisfy Rust reference counting:
+    /// 1. prepare() consumes CustomCache, releasing &CustomRouter, and creating a self-owned update info.
+    /// 2. CustomOps::commit() can now take &mut CustomRouter and updates the underlying state
+    pub fn prepare(self) -> CustomOps {
+        CustomOps(self.state.prepare())
+    }
+
+    /// Creates a nested cache on top of this one, whose changes can be applied to this one
+    /// with `CustomOps::commit_cache`, or dropped.
+    pub fn cache(&'_ self) -> CustomCache<'_, C> {
+        CustomCache {
+            router: self.router,
+            state: StorageTransaction::new(&self.state),
+        }
+    }
+
+    pub fn execute(
+        &mut self,
+        bank: &mut BankCache,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: C,
+    ) -> Result<AppResponse, String> {
+        self.router
+            .handler()?
+            .execute(&mut self.state, bank, block, sender, msg)
+    }
+}
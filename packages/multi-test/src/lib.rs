@@ -1,9 +1,14 @@
 mod app;
 mod bank;
+mod custom_handler;
+mod staking;
 mod test_helpers;
-mod transactions;
 mod wasm;
 
-pub use crate::app::{parse_contract_addr, App, AppCache, AppOps};
+pub use crate::app::{parse_contract_addr, App, AppCache, AppOps, AppResponse};
 pub use crate::bank::{Bank, BankCache, BankOps, SimpleBank};
+pub use crate::custom_handler::{CustomCache, CustomHandler, CustomOps, CustomRouter};
+pub use crate::staking::{
+    SimpleStaking, Staking, StakingCache, StakingOps, StakingRouter, STAKING_MODULE,
+};
 pub use crate::wasm::{next_block, Contract, ContractWrapper, WasmCache, WasmOps, WasmRouter};
@@ -0,0 +1,656 @@
+use schemars::JsonSchema;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{
+    coin, to_binary, Binary, BlockInfo, BondedDenomResponse, Coin, Decimal, FullDelegation,
+    HumanAddr, StakingMsg, StakingQuery, Storage, Uint128, Validator, ValidatorsResponse,
+};
+#[cfg(feature = "iterator")]
+use cosmwasm_std::{AllDelegationsResponse, Delegation, Order};
+use cw_storage_plus::{Item, Map, RepLog, StorageTransaction};
+
+/// The account holding all bonded and unbonding coins
+pub const STAKING_MODULE: &str = "staking_module";
+
+const SECONDS_PER_YEAR: u64 = 365 * 24 * 60 * 60;
+
+/// Staking is a minimal contract-like interface that implements the staking and distribution modules.
+/// It is initialized outside of the trait.
+///
+/// It only does the bookkeeping, the App moves the coins: delegated coins are sent to the
+/// `STAKING_MODULE` account before `handle` is called, and the returned payouts are paid out
+/// by the App (rewards are minted, unbonded coins come from the module account).
+pub trait Staking {
+    /// Handles a message of the delegator and returns the rewards to mint.
+    /// Like in the sdk, any change of a delegation withdraws its rewards.
+    fn handle(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: StakingMsg,
+    ) -> Result<Vec<(HumanAddr, Coin)>, String>;
+
+    fn query(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        request: StakingQuery,
+    ) -> Result<Binary, String>;
+
+    /// Removes all unbondings that are complete at this block, and returns the coins
+    /// to send from the module account to the delegators.
+    fn process_queue(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+    ) -> Result<Vec<(HumanAddr, Coin)>, String>;
+
+    // this is an "admin" function to let us add validators
+    fn add_validator(&self, storage: &mut dyn Storage, validator: Validator) -> Result<(), String>;
+
+    fn clone(&self) -> Box<dyn Staking>;
+}
+
+pub struct StakingRouter {
+    staking: Box<dyn Staking>,
+    storage: Box<dyn Storage>,
+}
+
+impl StakingRouter {
+    pub fn new<S: Staking + 'static>(staking: S, storage: Box<dyn Storage>) -> Self {
+        StakingRouter {
+            staking: Box::new(staking),
+            storage,
+        }
+    }
+
+    // this is an "admin" function to let us add validators
+    pub fn add_validator(&mut self, validator: Validator) -> Result<(), String> {
+        self.staking.add_validator(self.storage.as_mut(), validator)
+    }
+
+    pub fn cache(&'_ self) -> StakingCache<'_> {
+        StakingCache::new(self)
+    }
+
+    pub fn query(&self, block: &BlockInfo, request: StakingQuery) -> Result<Binary, String> {
+        self.staking.query(self.storage.as_ref(), block, request)
+    }
+}
+
+pub struct StakingCache<'a> {
+    router: &'a StakingRouter,
+    state: StorageTransaction<'a>,
+}
+
+pub struct StakingOps(RepLog);
+
+impl StakingOps {
+    pub fn commit(self, router: &mut StakingRouter) {
+        self.0.commit(router.storage.as_mut())
+    }
+
+    /// Applies the changes of a nested cache to the cache it was created from
+    pub fn commit_cache(self, cache: &mut StakingCache) {
+        self.0.commit(&mut cache.state)
+    }
+}
+
+impl<'a> StakingCache<'a> {
+    fn new(router: &'a StakingRouter) -> Self {
+        StakingCache {
+            router,
+            state: StorageTransaction::new(router.storage.as_ref()),
+        }
+    }
+
+    /// When we want to commit the StakingCache, we need a 2 step process to satisfy Rust reference counting:
+    /// 1. prepare() consumes StakingCache, releasing &StakingRouter, and creating a self-owned update info.
+    /// 2. StakingOps::commit() can now take &mut StakingRouter and updates the underlying state
+    pub fn prepare(self) -> StakingOps {
+        StakingOps(self.state.prepare())
+    }
+
+    /// Creates a nested cache on top of this one, whose changes can be applied to this one
+    /// with `StakingOps::commit_cache`, or dropped.
+    pub fn cache(&'_ self) -> StakingCache<'_> {
+        StakingCache {
+            router: self.router,
+            state: StorageTransaction::new(&self.state),
+        }
+    }
+
+    pub fn execute(
+        &mut self,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: StakingMsg,
+    ) -> Result<Vec<(HumanAddr, Coin)>, String> {
+        self.router
+            .staking
+            .handle(&mut self.state, block, sender, msg)
+    }
+
+    pub fn process_queue(&mut self, block: &BlockInfo) -> Result<Vec<(HumanAddr, Coin)>, String> {
+        self.router.staking.process_queue(&mut self.state, block)
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+struct Stake {
+    amount: Uint128,
+    /// rewards accrue from this time (in seconds) on
+    rewards_since: u64,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+struct Unbonding {
+    delegator: HumanAddr,
+    amount: Uint128,
+    /// block time (in seconds) when the coins are released
+    release_at: u64,
+}
+
+/// Same as `cosmwasm_std::DelegationResponse`, which is not exported
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) struct DelegationResponse {
+    pub delegation: Option<FullDelegation>,
+}
+
+const VALIDATORS: Item<Vec<Validator>> = Item::new("validators");
+const DELEGATIONS: Map<(&[u8], &[u8]), Stake> = Map::new("delegations");
+const UNBONDING: Item<Vec<Unbonding>> = Item::new("unbonding");
+
+/// SimpleStaking accrues rewards at a fixed yearly rate on all delegations,
+/// minus the commission of the validator.
+/// Redelegation is not limited, so `can_redelegate` is always the full amount.
+pub struct SimpleStaking {
+    pub bonded_denom: String,
+    /// yearly rewards, eg. 0.1 for 10%
+    pub apr: Decimal,
+    /// seconds until undelegated coins are released
+    pub unbonding_time: u64,
+}
+
+impl Default for SimpleStaking {
+    fn default() -> Self {
+        SimpleStaking::new("stake", Decimal::zero(), 21 * 24 * 60 * 60)
+    }
+}
+
+impl SimpleStaking {
+    pub fn new<T: Into<String>>(bonded_denom: T, apr: Decimal, unbonding_time: u64) -> Self {
+        SimpleStaking {
+            bonded_denom: bonded_denom.into(),
+            apr,
+            unbonding_time,
+        }
+    }
+
+    fn validator(&self, storage: &dyn Storage, address: &HumanAddr) -> Result<Validator, String> {
+        VALIDATORS
+            .may_load(storage)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default()
+            .into_iter()
+            .find(|v| &v.address == address)
+            .ok_or_else(|| format!("Unknown validator {}", address))
+    }
+
+    fn check_denom(&self, amount: &Coin) -> Result<(), String> {
+        if amount.denom != self.bonded_denom {
+            return Err(format!(
+                "Cannot stake {}, only {}",
+                amount.denom, self.bonded_denom
+            ));
+        }
+        Ok(())
+    }
+
+    fn load_stake(
+        &self,
+        storage: &dyn Storage,
+        delegator: &HumanAddr,
+        validator: &HumanAddr,
+    ) -> Result<Option<Stake>, String> {
+        DELEGATIONS
+            .may_load(storage, (delegator.as_bytes(), validator.as_bytes()))
+            .map_err(|e| e.to_string())
+    }
+
+    /// The rewards of the stake up to this block, after the commission of the validator
+    fn pending_rewards(&self, stake: &Stake, validator: &Validator, block: &BlockInfo) -> Uint128 {
+        let elapsed = block.time.saturating_sub(stake.rewards_since);
+        let rewards = stake.amount.multiply_ratio(elapsed, SECONDS_PER_YEAR) * self.apr;
+        let commission = rewards * validator.commission;
+        Uint128(rewards.u128() - commission.u128())
+    }
+
+    /// Changes the delegation by the given amount, and returns the rewards earned so far
+    fn update_stake(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        delegator: &HumanAddr,
+        validator: &HumanAddr,
+        add: Uint128,
+        sub: Uint128,
+    ) -> Result<Uint128, String> {
+        let validator = self.validator(storage, validator)?;
+        let key = (delegator.as_bytes(), validator.address.as_bytes());
+        let stake = self.load_stake(storage, delegator, &validator.address)?;
+        let (amount, rewards) = match stake {
+            Some(stake) => (
+                stake.amount,
+                self.pending_rewards(&stake, &validator, block),
+            ),
+            None => (Uint128::zero(), Uint128::zero()),
+        };
+        let amount = (amount + add - sub).map_err(|_| "Not enough delegated".to_string())?;
+        if amount.is_zero() {
+            DELEGATIONS.remove(storage, key);
+        } else {
+            let stake = Stake {
+                amount,
+                rewards_since: block.time,
+            };
+            DELEGATIONS
+                .save(storage, key, &stake)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(rewards)
+    }
+
+    fn payout(&self, recipient: &HumanAddr, amount: Uint128) -> Vec<(HumanAddr, Coin)> {
+        if amount.is_zero() {
+            vec![]
+        } else {
+            vec![(recipient.clone(), coin(amount.u128(), &self.bonded_denom))]
+        }
+    }
+}
+
+impl Staking for SimpleStaking {
+    fn handle(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+        sender: HumanAddr,
+        msg: StakingMsg,
+    ) -> Result<Vec<(HumanAddr, Coin)>, String> {
+        match msg {
+            StakingMsg::Delegate { validator, amount } => {
+                self.check_denom(&amount)?;
+                let rewards = self.update_stake(
+                    storage,
+                    block,
+                    &sender,
+                    &validator,
+                    amount.amount,
+                    0u128.into(),
+                )?;
+                Ok(self.payout(&sender, rewards))
+            }
+            StakingMsg::Undelegate { validator, amount } => {
+                self.check_denom(&amount)?;
+                let rewards = self.update_stake(
+                    storage,
+                    block,
+                    &sender,
+                    &validator,
+                    0u128.into(),
+                    amount.amount,
+                )?;
+                let mut queue = UNBONDING
+                    .may_load(storage)
+                    .map_err(|e| e.to_string())?
+                    .unwrap_or_default();
+                queue.push(Unbonding {
+                    delegator: sender.clone(),
+                    amount: amount.amount,
+                    release_at: block.time + self.unbonding_time,
+                });
+                UNBONDING.save(storage, &queue).map_err(|e| e.to_string())?;
+                Ok(self.payout(&sender, rewards))
+            }
+            StakingMsg::Redelegate {
+                src_validator,
+                dst_validator,
+                amount,
+            } => {
+                self.check_denom(&amount)?;
+                let src_rewards = self.update_stake(
+                    storage,
+                    block,
+                    &sender,
+                    &src_validator,
+                    0u128.into(),
+                    amount.amount,
+                )?;
+                let dst_rewards = self.update_stake(
+                    storage,
+                    block,
+                    &sender,
+                    &dst_validator,
+                    amount.amount,
+                    0u128.into(),
+                )?;
+                Ok(self.payout(&sender, src_rewards + dst_rewards))
+            }
+            StakingMsg::Withdraw {
+                validator,
+                recipient,
+            } => {
+                if self.load_stake(storage, &sender, &validator)?.is_none() {
+                    return Err(format!("No delegation to {}", validator));
+                }
+                let rewards = self.update_stake(
+                    storage,
+                    block,
+                    &sender,
+                    &validator,
+                    0u128.into(),
+                    0u128.into(),
+                )?;
+                Ok(self.payout(&recipient.unwrap_or(sender), rewards))
+            }
+            m => panic!("Unsupported staking message: {:?}", m),
+        }
+    }
+
+    fn query(
+        &self,
+        storage: &dyn Storage,
+        block: &BlockInfo,
+        request: StakingQuery,
+    ) -> Result<Binary, String> {
+        match request {
+            StakingQuery::BondedDenom {} => {
+                let res = BondedDenomResponse {
+                    denom: self.bonded_denom.clone(),
+                };
+                to_binary(&res).map_err(|e| e.to_string())
+            }
+            StakingQuery::Validators {} => {
+                let validators = VALIDATORS
+                    .may_load(storage)
+                    .map_err(|e| e.to_string())?
+                    .unwrap_or_default();
+                to_binary(&ValidatorsResponse { validators }).map_err(|e| e.to_string())
+            }
+            StakingQuery::Delegation {
+                delegator,
+                validator,
+            } => {
+                let validator = self.validator(storage, &validator)?;
+                let stake = self.load_stake(storage, &delegator, &validator.address)?;
+                let delegation = stake.map(|stake| {
+                    let amount = coin(stake.amount.u128(), &self.bonded_denom);
+                    let rewards = self.pending_rewards(&stake, &validator, block);
+                    FullDelegation {
+                        delegator,
+                        validator: validator.address.clone(),
+                        can_redelegate: amount.clone(),
+                        amount,
+                        accumulated_rewards: vec![coin(rewards.u128(), &self.bonded_denom)],
+                    }
+                });
+                to_binary(&DelegationResponse { delegation }).map_err(|e| e.to_string())
+            }
+            #[cfg(feature = "iterator")]
+            StakingQuery::AllDelegations { delegator } => {
+                let delegations: Result<Vec<_>, String> = DELEGATIONS
+                    .prefix(delegator.as_bytes())
+                    .range(storage, None, None, Order::Ascending)
+                    .map(|item| {
+                        let (validator, stake) = item.map_err(|e| e.to_string())?;
+                        let validator = String::from_utf8(validator).map_err(|e| e.to_string())?;
+                        Ok(Delegation {
+                            delegator: delegator.clone(),
+                            validator: validator.into(),
+                            amount: coin(stake.amount.u128(), &self.bonded_denom),
+                        })
+                    })
+                    .collect();
+                let res = AllDelegationsResponse {
+                    delegations: delegations?,
+                };
+                to_binary(&res).map_err(|e| e.to_string())
+            }
+            q => panic!("Unsupported staking query: {:?}", q),
+        }
+    }
+
+    fn process_queue(
+        &self,
+        storage: &mut dyn Storage,
+        block: &BlockInfo,
+    ) -> Result<Vec<(HumanAddr, Coin)>, String> {
+        let queue = UNBONDING
+            .may_load(storage)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default();
+        let (done, pending): (Vec<_>, Vec<_>) =
+            queue.into_iter().partition(|u| u.release_at <= block.time);
+        if !done.is_empty() {
+            UNBONDING
+                .save(storage, &pending)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(done
+            .into_iter()
+            .map(|u| (u.delegator, coin(u.amount.u128(), &self.bonded_denom)))
+            .collect())
+    }
+
+    // this is an "admin" function to let us add validators
+    fn add_validator(&self, storage: &mut dyn Storage, validator: Validator) -> Result<(), String> {
+        let mut validators = VALIDATORS
+            .may_load(storage)
+            .map_err(|e| e.to_string())?
+            .unwrap_or_default();
+        if validators.iter().any(|v| v.address == validator.address) {
+            return Err(format!("Validator {} already exists", validator.address));
+        }
+        validators.push(validator);
+        VALIDATORS
+            .save(storage, &validators)
+            .map_err(|e| e.to_string())
+    }
+
+    fn clone(&self) -> Box<dyn Staking> {
+        Box::new(SimpleStaking {
+            bonded_denom: self.bonded_denom.clone(),
+            apr: self.apr,
+            unbonding_time: self.unbonding_time,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use cosmwasm_std::from_slice;
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+
+    fn validator(address: &str, commission: u64) -> Validator {
+        Validator {
+            address: address.into(),
+            commission: Decimal::percent(commission),
+            max_commission: Decimal::percent(20),
+            max_change_rate: Decimal::percent(1),
+        }
+    }
+
+    fn delegation(
+        staking: &SimpleStaking,
+        store: &dyn Storage,
+        block: &BlockInfo,
+        delegator: &str,
+        validator: &str,
+    ) -> Option<FullDelegation> {
+        let req = StakingQuery::Delegation {
+            delegator: delegator.into(),
+            validator: validator.into(),
+        };
+        let raw = staking.query(store, block, req).unwrap();
+        let res: DelegationResponse = from_slice(&raw).unwrap();
+        res.delegation
+    }
+
+    #[test]
+    fn delegate_and_accrue_rewards() {
+        let mut store = MockStorage::new();
+        let mut block = mock_env().block;
+        let staking = SimpleStaking::new("stake", Decimal::percent(10), 100);
+        staking
+            .add_validator(&mut store, validator("val1", 0))
+            .unwrap();
+        staking
+            .add_validator(&mut store, validator("val2", 20))
+            .unwrap();
+        staking
+            .add_validator(&mut store, validator("val1", 5))
+            .unwrap_err();
+
+        let raw = staking
+            .query(&store, &block, StakingQuery::Validators {})
+            .unwrap();
+        let res: ValidatorsResponse = from_slice(&raw).unwrap();
+        assert_eq!(res.validators.len(), 2);
+
+        // only known validators and the bonded denom
+        let msg = StakingMsg::Delegate {
+            validator: "val3".into(),
+            amount: coin(1000, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, "alice".into(), msg)
+            .unwrap_err();
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(1000, "eth"),
+        };
+        staking
+            .handle(&mut store, &block, "alice".into(), msg)
+            .unwrap_err();
+
+        for val in &["val1", "val2"] {
+            let msg = StakingMsg::Delegate {
+                validator: (*val).into(),
+                amount: coin(1000, "stake"),
+            };
+            let rewards = staking
+                .handle(&mut store, &block, "alice".into(), msg)
+                .unwrap();
+            assert_eq!(rewards, vec![]);
+        }
+
+        // half a year later, 5% rewards, minus commission
+        block.time += SECONDS_PER_YEAR / 2;
+        let del = delegation(&staking, &store, &block, "alice", "val1").unwrap();
+        assert_eq!(del.amount, coin(1000, "stake"));
+        assert_eq!(del.accumulated_rewards, vec![coin(50, "stake")]);
+        let del = delegation(&staking, &store, &block, "alice", "val2").unwrap();
+        assert_eq!(del.accumulated_rewards, vec![coin(40, "stake")]);
+        assert_eq!(delegation(&staking, &store, &block, "bob", "val1"), None);
+
+        // withdraw to another account resets the rewards
+        let msg = StakingMsg::Withdraw {
+            validator: "val1".into(),
+            recipient: Some("bob".into()),
+        };
+        let rewards = staking
+            .handle(&mut store, &block, "alice".into(), msg)
+            .unwrap();
+        assert_eq!(rewards, vec![("bob".into(), coin(50, "stake"))]);
+        let del = delegation(&staking, &store, &block, "alice", "val1").unwrap();
+        assert_eq!(del.accumulated_rewards, vec![coin(0, "stake")]);
+
+        // redelegation withdraws the rewards of both
+        let msg = StakingMsg::Redelegate {
+            src_validator: "val2".into(),
+            dst_validator: "val1".into(),
+            amount: coin(400, "stake"),
+        };
+        let rewards = staking
+            .handle(&mut store, &block, "alice".into(), msg)
+            .unwrap();
+        assert_eq!(rewards, vec![("alice".into(), coin(40, "stake"))]);
+        let del = delegation(&staking, &store, &block, "alice", "val1").unwrap();
+        assert_eq!(del.amount, coin(1400, "stake"));
+        let del = delegation(&staking, &store, &block, "alice", "val2").unwrap();
+        assert_eq!(del.amount, coin(600, "stake"));
+
+        #[cfg(feature = "iterator")]
+        {
+            let req = StakingQuery::AllDelegations {
+                delegator: "alice".into(),
+            };
+            let raw = staking.query(&store, &block, req).unwrap();
+            let res: AllDelegationsResponse = from_slice(&raw).unwrap();
+            let amounts: Vec<_> = res.delegations.into_iter().map(|d| d.amount).collect();
+            assert_eq!(amounts, vec![coin(1400, "stake"), coin(600, "stake")]);
+        }
+    }
+
+    #[test]
+    fn unbonding_queue() {
+        let mut store = MockStorage::new();
+        let mut block = mock_env().block;
+        let staking = SimpleStaking::new("stake", Decimal::zero(), 100);
+        staking
+            .add_validator(&mut store, validator("val1", 0))
+            .unwrap();
+
+        let msg = StakingMsg::Delegate {
+            validator: "val1".into(),
+            amount: coin(1000, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, "alice".into(), msg)
+            .unwrap();
+
+        // cannot undelegate more than delegated
+        let msg = StakingMsg::Undelegate {
+            validator: "val1".into(),
+            amount: coin(1001, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, "alice".into(), msg)
+            .unwrap_err();
+
+        let msg = StakingMsg::Undelegate {
+            validator: "val1".into(),
+            amount: coin(300, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, "alice".into(), msg)
+            .unwrap();
+        block.time += 50;
+        let msg = StakingMsg::Undelegate {
+            validator: "val1".into(),
+            amount: coin(700, "stake"),
+        };
+        staking
+            .handle(&mut store, &block, "alice".into(), msg)
+            .unwrap();
+        // fully undelegated
+        assert_eq!(delegation(&staking, &store, &block, "alice", "val1"), None);
+
+        // released one after the other
+        let released = staking.process_queue(&mut store, &block).unwrap();
+        assert_eq!(released, vec![]);
+        block.time += 50;
+        let released = staking.process_queue(&mut store, &block).unwrap();
+        assert_eq!(released, vec![("alice".into(), coin(300, "stake"))]);
+        let released = staking.process_queue(&mut store, &block).unwrap();
+        assert_eq!(released, vec![]);
+        block.time += 50;
+        let released = staking.process_queue(&mut store, &block).unwrap();
+        assert_eq!(released, vec![("alice".into(), coin(700, "stake"))]);
+    }
+}
@@ -1,14 +1,21 @@
 #![cfg(test)]
 use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
+use std::cell::Cell;
 use std::fmt;
 
+use cosmwasm_std::testing::MockStorage;
 use cosmwasm_std::{
-    attr, to_binary, BankMsg, Binary, Coin, CosmosMsg, Deps, DepsMut, Empty, Env, MessageInfo,
-    Response, StdError,
+    attr, coin, to_binary, BankMsg, Binary, BlockInfo, Coin, CosmosMsg, CustomQuery, Deps, DepsMut,
+    Empty, Env, HumanAddr, MessageInfo, Reply, Response, StdError, StdResult, Storage, SubMsg,
 };
-use cw_storage_plus::Item;
+#[cfg(feature = "iterator")]
+use cosmwasm_std::{Order, KV};
+use cw_storage_plus::{CachedItem, CachedMap, Item, Map, U64Key};
 
+use crate::app::AppResponse;
+use crate::bank::BankCache;
+use crate::custom_handler::CustomHandler;
 use crate::wasm::{Contract, ContractWrapper};
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -100,6 +107,21 @@ pub fn contract_payout() -> Box<dyn Contract<Empty>> {
     Box::new(contract)
 }
 
+fn migrate_payout(deps: DepsMut, _env: Env, msg: PayoutMessage) -> Result<Response, StdError> {
+    // the new code pays out a different amount
+    PAYOUT.save(deps.storage, &msg)?;
+    Ok(Response {
+        attributes: vec![attr("action", "migrate")],
+        ..Response::default()
+    })
+}
+
+pub fn contract_payout_migrate() -> Box<dyn Contract<Empty>> {
+    let contract =
+        ContractWrapper::new(handle_payout, init_payout, query_payout).with_migrate(migrate_payout);
+    Box::new(contract)
+}
+
 pub fn contract_payout_custom<C>() -> Box<dyn Contract<C>>
 where
     C: Clone + fmt::Debug + PartialEq + JsonSchema + 'static,
@@ -112,8 +134,76 @@ where
 #[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
 #[serde(rename = "snake_case")]
 pub enum CustomMsg {
-    SetName { name: String },
-    SetAge { age: u32 },
+    SetName {
+        name: String,
+    },
+    SetAge {
+        age: u32,
+    },
+    /// Swaps the offered coins for twice the amount of ask_denom
+    Swap {
+        offer: Coin,
+        ask_denom: String,
+    },
+}
+
+/// This is just a demo place so we can test custom query handling
+#[derive(Debug, Clone, Serialize, Deserialize, JsonSchema, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CustomQueryMsg {
+    Name {},
+    Age {},
+}
+
+impl CustomQuery for CustomQueryMsg {}
+
+const NAME: Item<String> = Item::new("name");
+const AGE: Item<u32> = Item::new("age");
+
+/// Emulates a chain module that stores the name and age set with CustomMsg.
+/// Ages over 150 are rejected. Swaps burn the offer and mint the ask coins, like a market module.
+pub struct CustomEmulator {}
+
+impl CustomHandler<CustomMsg, CustomQueryMsg> for CustomEmulator {
+    fn execute(
+        &self,
+        storage: &mut dyn Storage,
+        bank: &mut BankCache,
+        _block: &BlockInfo,
+        sender: HumanAddr,
+        msg: CustomMsg,
+    ) -> Result<AppResponse, String> {
+        match msg {
+            CustomMsg::SetName { name } => NAME.save(storage, &name),
+            CustomMsg::SetAge { age } if age > 150 => return Err("Too old".to_string()),
+            CustomMsg::SetAge { age } => AGE.save(storage, &age),
+            CustomMsg::Swap { offer, ask_denom } => {
+                let ask = coin(offer.amount.u128() * 2, ask_denom);
+                bank.burn(sender.clone(), vec![offer])?;
+                bank.mint(sender, vec![ask])?;
+                Ok(())
+            }
+        }
+        .map_err(|e| e.to_string())?;
+        Ok(AppResponse {
+            attributes: vec![attr("action", "custom")],
+            events: vec![],
+            data: None,
+        })
+    }
+
+    fn query(
+        &self,
+        storage: &dyn Storage,
+        _block: &BlockInfo,
+        request: CustomQueryMsg,
+    ) -> Result<Binary, String> {
+        match request {
+            CustomQueryMsg::Name {} => to_binary(&NAME.may_load(storage).unwrap()),
+            CustomQueryMsg::Age {} => to_binary(&AGE.may_load(storage).unwrap()),
+        }
+        .map_err(|e| e.to_string())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -124,6 +214,8 @@ pub struct ReflectSudoMsg {
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct ReflectMessage {
     pub messages: Vec<CosmosMsg<CustomMsg>>,
+    #[serde(default)]
+    pub sub_msgs: Vec<SubMsg<CustomMsg>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -132,6 +224,8 @@ pub struct ReflectResponse {
 }
 
 const REFLECT: Item<u32> = Item::new("reflect");
+/// Every reply received, by id
+pub const REFLECT_REPLIES: Map<U64Key, Reply> = Map::new("reflect_replies");
 
 fn init_reflect(
     deps: DepsMut,
@@ -152,7 +246,7 @@ fn handle_reflect(
     REFLECT.update::<_, StdError>(deps.storage, |old| Ok(old + 1))?;
 
     let res = Response {
-        submessages: vec![],
+        submessages: msg.sub_msgs,
         messages: msg.messages,
         attributes: vec![],
         data: None,
@@ -169,6 +263,15 @@ fn sudo_reflect(
     Ok(Response::default())
 }
 
+fn reply_reflect(deps: DepsMut, _env: Env, msg: Reply) -> Result<Response<CustomMsg>, StdError> {
+    REFLECT_REPLIES.save(deps.storage, msg.id.into(), &msg)?;
+    let res = Response {
+        attributes: vec![attr("reply", msg.id)],
+        ..Response::default()
+    };
+    Ok(res)
+}
+
 fn query_reflect(deps: Deps, _env: Env, _msg: EmptyMsg) -> Result<Binary, StdError> {
     let count = REFLECT.load(deps.storage)?;
     let res = ReflectResponse { count };
@@ -177,6 +280,111 @@ fn query_reflect(deps: Deps, _env: Env, _msg: EmptyMsg) -> Result<Binary, StdErr
 
 pub fn contract_reflect() -> Box<dyn Contract<CustomMsg>> {
     let contract =
-        ContractWrapper::new_with_sudo(handle_reflect, init_reflect, query_reflect, sudo_reflect);
+        ContractWrapper::new_with_sudo(handle_reflect, init_reflect, query_reflect, sudo_reflect)
+            .with_reply(reply_reflect);
+    Box::new(contract)
+}
+
+thread_local! {
+    static STORAGE_READS: Cell<usize> = Cell::new(0);
+}
+
+/// The number of reads from any CountingStorage in this thread (every test runs in its own)
+pub fn storage_reads() -> usize {
+    STORAGE_READS.with(|reads| reads.get())
+}
+
+/// MockStorage counting the reads, to measure how many reads a contract makes.
+/// Use it with `App::new(api, block, bank, || Box::new(CountingStorage::default()))`
+#[derive(Default)]
+pub struct CountingStorage(MockStorage);
+
+impl Storage for CountingStorage {
+    fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+        STORAGE_READS.with(|reads| reads.set(reads.get() + 1));
+        self.0.get(key)
+    }
+
+    #[cfg(feature = "iterator")]
+    fn range<'a>(
+        &'a self,
+        start: Option<&[u8]>,
+        end: Option<&[u8]>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = KV> + 'a> {
+        self.0.range(start, end, order)
+    }
+
+    fn set(&mut self, key: &[u8], value: &[u8]) {
+        self.0.set(key, value)
+    }
+
+    fn remove(&mut self, key: &[u8]) {
+        self.0.remove(key)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LedgerMessage {
+    pub payouts: Vec<(String, u64)>,
+    /// use CachedItem and CachedMap, rather than Item and Map
+    pub cached: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct LedgerQuery {
+    pub address: String,
+}
+
+const LEDGER_FEE: Item<u64> = Item::new("fee");
+const LEDGER: Map<&str, u64> = Map::new("ledger");
+
+fn init_ledger(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    _msg: EmptyMsg,
+) -> Result<Response, StdError> {
+    LEDGER_FEE.save(deps.storage, &1)?;
+    Ok(Response::default())
+}
+
+// the fee is loaded for every payout, like a handler checking the config in several helpers
+fn handle_ledger(
+    deps: DepsMut,
+    _env: Env,
+    _info: MessageInfo,
+    msg: LedgerMessage,
+) -> Result<Response, StdError> {
+    if msg.cached {
+        CachedItem::with_cache(&LEDGER_FEE, deps.storage, |store, fee| {
+            CachedMap::with_cache(&LEDGER, store, |store, ledger| {
+                for (addr, amount) in msg.payouts.iter() {
+                    let fee = fee.load(store)?;
+                    ledger.update(store, addr, |b| -> StdResult<_> {
+                        Ok(b.unwrap_or_default() + amount - fee)
+                    })?;
+                }
+                Ok::<_, StdError>(())
+            })
+        })?;
+    } else {
+        for (addr, amount) in msg.payouts.iter() {
+            let fee = LEDGER_FEE.load(deps.storage)?;
+            LEDGER.update(deps.storage, addr, |b| -> StdResult<_> {
+                Ok(b.unwrap_or_default() + amount - fee)
+            })?;
+        }
+    }
+    Ok(Response::default())
+}
+
+fn query_ledger(deps: Deps, _env: Env, msg: LedgerQuery) -> Result<Binary, StdError> {
+    let balance = LEDGER.may_load(deps.storage, &msg.address)?;
+    to_binary(&balance.unwrap_or_default())
+}
+
+pub fn contract_ledger() -> Box<dyn Contract<Empty>> {
+    let contract = ContractWrapper::new(handle_ledger, init_ledger, query_ledger);
     Box::new(contract)
 }
@@ -6,10 +6,10 @@ use std::ops::Deref;
 
 use cosmwasm_std::{
     from_slice, Api, Binary, BlockInfo, ContractInfo, CosmosMsg, Deps, DepsMut, Empty, Env,
-    HumanAddr, MessageInfo, Querier, QuerierWrapper, Response, Storage, SubMsg, WasmQuery,
+    HumanAddr, MessageInfo, Querier, QuerierWrapper, Reply, Response, Storage, SubMsg, WasmQuery,
 };
 
-use crate::transactions::{RepLog, StorageTransaction};
+use cw_storage_plus::{RepLog, StorageTransaction};
 
 /// Interface to call into a Contract
 pub trait Contract<T>
@@ -34,6 +34,10 @@ where
 
     fn sudo(&self, deps: DepsMut, env: Env, msg: Vec<u8>) -> Result<Response<T>, String>;
 
+    fn reply(&self, deps: DepsMut, env: Env, msg: Reply) -> Result<Response<T>, String>;
+
+    fn migrate(&self, deps: DepsMut, env: Env, msg: Vec<u8>) -> Result<Response<T>, String>;
+
     fn query(&self, deps: Deps, env: Env, msg: Vec<u8>) -> Result<Binary, String>;
 }
 
@@ -41,15 +45,32 @@ type ContractFn<T, C, E> =
     fn(deps: DepsMut, env: Env, info: MessageInfo, msg: T) -> Result<Response<C>, E>;
 type SudoFn<T, C, E> = fn(deps: DepsMut, env: Env, msg: T) -> Result<Response<C>, E>;
 type QueryFn<T, E> = fn(deps: Deps, env: Env, msg: T) -> Result<Binary, E>;
+type ReplyFn<C, E> = fn(deps: DepsMut, env: Env, msg: Reply) -> Result<Response<C>, E>;
+type MigrateFn<T, C, E> = fn(deps: DepsMut, env: Env, msg: T) -> Result<Response<C>, E>;
 
 type ContractClosure<T, C, E> = Box<dyn Fn(DepsMut, Env, MessageInfo, T) -> Result<Response<C>, E>>;
 type SudoClosure<T, C, E> = Box<dyn Fn(DepsMut, Env, T) -> Result<Response<C>, E>>;
 type QueryClosure<T, E> = Box<dyn Fn(Deps, Env, T) -> Result<Binary, E>>;
+type ReplyClosure<C, E> = Box<dyn Fn(DepsMut, Env, Reply) -> Result<Response<C>, E>>;
+type MigrateClosure<T, C, E> = Box<dyn Fn(DepsMut, Env, T) -> Result<Response<C>, E>>;
 
 /// Wraps the exported functions from a contract and provides the normalized format
-/// Place T4 and E4 at the end, as we just want default placeholders for most contracts that don't have sudo
-pub struct ContractWrapper<T1, T2, T3, E1, E2, E3, C = Empty, T4 = String, E4 = String>
-where
+/// Place T4, E4, E5, T6 and E6 at the end, as we just want default placeholders for most contracts
+/// that don't have sudo, reply or migrate
+pub struct ContractWrapper<
+    T1,
+    T2,
+    T3,
+    E1,
+    E2,
+    E3,
+    C = Empty,
+    T4 = String,
+    E4 = String,
+    E5 = String,
+    T6 = String,
+    E6 = String,
+> where
     T1: DeserializeOwned,
     T2: DeserializeOwned,
     T3: DeserializeOwned,
@@ -58,12 +79,17 @@ where
     E2: std::fmt::Display,
     E3: std::fmt::Display,
     E4: std::fmt::Display,
+    E5: std::fmt::Display,
+    T6: DeserializeOwned,
+    E6: std::fmt::Display,
     C: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
     handle_fn: ContractClosure<T1, C, E1>,
     init_fn: ContractClosure<T2, C, E2>,
     query_fn: QueryClosure<T3, E3>,
     sudo_fn: Option<SudoClosure<T4, C, E4>>,
+    reply_fn: Option<ReplyClosure<C, E5>>,
+    migrate_fn: Option<MigrateClosure<T6, C, E6>>,
 }
 
 impl<T1, T2, T3, E1, E2, E3, C> ContractWrapper<T1, T2, T3, E1, E2, E3, C>
@@ -86,6 +112,8 @@ where
             init_fn: Box::new(init_fn),
             query_fn: Box::new(query_fn),
             sudo_fn: None,
+            reply_fn: None,
+            migrate_fn: None,
         }
     }
 
@@ -101,6 +129,8 @@ where
             init_fn: customize_fn(init_fn),
             query_fn: Box::new(query_fn),
             sudo_fn: None,
+            reply_fn: None,
+            migrate_fn: None,
         }
     }
 }
@@ -128,6 +158,62 @@ where
             init_fn: Box::new(init_fn),
             query_fn: Box::new(query_fn),
             sudo_fn: Some(Box::new(sudo_fn)),
+            reply_fn: None,
+            migrate_fn: None,
+        }
+    }
+}
+
+impl<T1, T2, T3, E1, E2, E3, C, T4, E4, E5, T6, E6>
+    ContractWrapper<T1, T2, T3, E1, E2, E3, C, T4, E4, E5, T6, E6>
+where
+    T1: DeserializeOwned + 'static,
+    T2: DeserializeOwned + 'static,
+    T3: DeserializeOwned + 'static,
+    T4: DeserializeOwned + 'static,
+    T6: DeserializeOwned + 'static,
+    E1: std::fmt::Display + 'static,
+    E2: std::fmt::Display + 'static,
+    E3: std::fmt::Display + 'static,
+    E4: std::fmt::Display + 'static,
+    E5: std::fmt::Display + 'static,
+    E6: std::fmt::Display + 'static,
+    C: Clone + fmt::Debug + PartialEq + JsonSchema + 'static,
+{
+    /// Adds a reply entry point, which receives the results of the submessages
+    pub fn with_reply<E5A>(
+        self,
+        reply_fn: ReplyFn<C, E5A>,
+    ) -> ContractWrapper<T1, T2, T3, E1, E2, E3, C, T4, E4, E5A, T6, E6>
+    where
+        E5A: std::fmt::Display + 'static,
+    {
+        ContractWrapper {
+            handle_fn: self.handle_fn,
+            init_fn: self.init_fn,
+            query_fn: self.query_fn,
+            sudo_fn: self.sudo_fn,
+            reply_fn: Some(Box::new(reply_fn)),
+            migrate_fn: self.migrate_fn,
+        }
+    }
+
+    /// Adds a migrate entry point, which is called on the new code when migrating a contract
+    pub fn with_migrate<T6A, E6A>(
+        self,
+        migrate_fn: MigrateFn<T6A, C, E6A>,
+    ) -> ContractWrapper<T1, T2, T3, E1, E2, E3, C, T4, E4, E5, T6A, E6A>
+    where
+        T6A: DeserializeOwned + 'static,
+        E6A: std::fmt::Display + 'static,
+    {
+        ContractWrapper {
+            handle_fn: self.handle_fn,
+            init_fn: self.init_fn,
+            query_fn: self.query_fn,
+            sudo_fn: self.sudo_fn,
+            reply_fn: self.reply_fn,
+            migrate_fn: Some(Box::new(migrate_fn)),
         }
     }
 }
@@ -182,8 +268,8 @@ where
     }
 }
 
-impl<T1, T2, T3, E1, E2, E3, C, T4, E4> Contract<C>
-    for ContractWrapper<T1, T2, T3, E1, E2, E3, C, T4, E4>
+impl<T1, T2, T3, E1, E2, E3, C, T4, E4, E5, T6, E6> Contract<C>
+    for ContractWrapper<T1, T2, T3, E1, E2, E3, C, T4, E4, E5, T6, E6>
 where
     T1: DeserializeOwned,
     T2: DeserializeOwned,
@@ -193,6 +279,9 @@ where
     E2: std::fmt::Display,
     E3: std::fmt::Display,
     E4: std::fmt::Display,
+    E5: std::fmt::Display,
+    T6: DeserializeOwned,
+    E6: std::fmt::Display,
     C: Clone + fmt::Debug + PartialEq + JsonSchema,
 {
     fn handle(
@@ -229,6 +318,25 @@ where
         res.map_err(|e| e.to_string())
     }
 
+    // this returns an error if the contract doesn't implement reply
+    fn reply(&self, deps: DepsMut, env: Env, msg: Reply) -> Result<Response<C>, String> {
+        let res = match &self.reply_fn {
+            Some(reply) => reply(deps, env, msg),
+            None => return Err("reply not implemented for contract".to_string()),
+        };
+        res.map_err(|e| e.to_string())
+    }
+
+    // this returns an error if the contract doesn't implement migrate
+    fn migrate(&self, deps: DepsMut, env: Env, msg: Vec<u8>) -> Result<Response<C>, String> {
+        let migrate = match &self.migrate_fn {
+            Some(migrate) => migrate,
+            None => return Err("migrate not implemented for contract".to_string()),
+        };
+        let msg: T6 = from_slice(&msg).map_err(|e| e.to_string())?;
+        migrate(deps, env, msg).map_err(|e| e.to_string())
+    }
+
     fn query(&self, deps: Deps, env: Env, msg: Vec<u8>) -> Result<Binary, String> {
         let msg: T3 = from_slice(&msg).map_err(|e| e.to_string())?;
         let res = (self.query_fn)(deps, env, msg);
@@ -237,16 +345,38 @@ where
 }
 
 struct ContractData {
-    code_id: usize,
+    meta: ContractMeta,
     storage: Box<dyn Storage>,
 }
 
 impl ContractData {
-    fn new(code_id: usize, storage: Box<dyn Storage>) -> Self {
-        ContractData { code_id, storage }
+    fn new(
+        code_id: usize,
+        admin: Option<HumanAddr>,
+        label: String,
+        storage: Box<dyn Storage>,
+    ) -> Self {
+        ContractData {
+            meta: ContractMeta {
+                code_id,
+                admin,
+                label,
+            },
+            storage,
+        }
     }
 }
 
+/// The code and admin of a contract, which can be changed after instantiation
+#[derive(Clone, Debug, PartialEq)]
+struct ContractMeta {
+    code_id: usize,
+    /// only the admin can migrate the contract or change the admin
+    admin: Option<HumanAddr>,
+    /// the label given at instantiation
+    label: String,
+}
+
 pub fn next_block(block: &mut BlockInfo) {
     block.time += 5;
     block.height += 1;
@@ -326,6 +456,24 @@ where
         })
     }
 
+    /// Returns the admin of the contract, who may migrate it, if any
+    pub fn contract_admin(&self, address: &HumanAddr) -> Result<Option<HumanAddr>, String> {
+        let contract = self
+            .contracts
+            .get(address)
+            .ok_or_else(|| "Unregistered contract address".to_string())?;
+        Ok(contract.meta.admin.clone())
+    }
+
+    /// Returns the label the contract was instantiated with
+    pub fn contract_label(&self, address: &HumanAddr) -> Result<String, String> {
+        let contract = self
+            .contracts
+            .get(address)
+            .ok_or_else(|| "Unregistered contract address".to_string())?;
+        Ok(contract.meta.label.clone())
+    }
+
     pub fn query_raw(&self, address: HumanAddr, key: &[u8]) -> Result<Binary, String> {
         let contract = self
             .contracts
@@ -359,7 +507,7 @@ where
             .ok_or_else(|| "Unregistered contract address".to_string())?;
         let handler = self
             .handlers
-            .get(&contract.code_id)
+            .get(&contract.meta.code_id)
             .ok_or_else(|| "Unregistered code id".to_string())?;
         let env = self.get_env(address);
 
@@ -401,6 +549,10 @@ where
 pub struct WasmCacheState<'a> {
     contracts: HashMap<HumanAddr, ContractData>,
     contract_diffs: HashMap<HumanAddr, StorageTransaction<'a>>,
+    /// new code ids and admins of contracts not created in this cache
+    contract_meta: HashMap<HumanAddr, ContractMeta>,
+    /// the cache this one was created from, if it is nested
+    parent: Option<&'a WasmCacheState<'a>>,
 }
 
 /// This is a set of data from the WasmCache with no external reference,
@@ -408,6 +560,7 @@ pub struct WasmCacheState<'a> {
 pub struct WasmOps {
     new_contracts: HashMap<HumanAddr, ContractData>,
     contract_diffs: Vec<(HumanAddr, RepLog)>,
+    contract_meta: HashMap<HumanAddr, ContractMeta>,
 }
 
 impl WasmOps {
@@ -418,11 +571,30 @@ impl WasmOps {
         self.new_contracts.into_iter().for_each(|(k, v)| {
             router.contracts.insert(k, v);
         });
+        self.contract_meta.into_iter().for_each(|(k, meta)| {
+            router.contracts.get_mut(&k).unwrap().meta = meta;
+        });
         self.contract_diffs.into_iter().for_each(|(k, ops)| {
             let storage = router.contracts.get_mut(&k).unwrap().storage.as_mut();
             ops.commit(storage);
         });
     }
+
+    /// Applies the changes of a nested cache to the cache it was created from
+    pub fn commit_cache<C>(self, cache: &mut WasmCache<C>)
+    where
+        C: Clone + fmt::Debug + PartialEq + JsonSchema,
+    {
+        let router = cache.router;
+        cache.state.contracts.extend(self.new_contracts);
+        for (addr, meta) in self.contract_meta {
+            cache.state.set_meta(&addr, meta);
+        }
+        for (addr, ops) in self.contract_diffs {
+            let (_, storage) = cache.state.get_contract(&router.contracts, &addr).unwrap();
+            ops.commit(storage);
+        }
+    }
 }
 
 impl<'a, C> WasmCache<'a, C>
@@ -435,6 +607,22 @@ where
             state: WasmCacheState {
                 contracts: HashMap::new(),
                 contract_diffs: HashMap::new(),
+                contract_meta: HashMap::new(),
+                parent: None,
+            },
+        }
+    }
+
+    /// Creates a nested cache on top of this one, whose changes can be applied to this one
+    /// with `WasmOps::commit_cache`, or dropped.
+    pub fn cache(&'_ self) -> WasmCache<'_, C> {
+        WasmCache {
+            router: self.router,
+            state: WasmCacheState {
+                contracts: HashMap::new(),
+                contract_diffs: HashMap::new(),
+                contract_meta: HashMap::new(),
+                parent: Some(&self.state),
             },
         }
     }
@@ -449,19 +637,24 @@ where
     /// This just creates an address and empty storage instance, returning the new address
     /// You must call init after this to set up the contract properly.
     /// These are separated into two steps to have cleaner return values.
-    pub fn register_contract(&mut self, code_id: usize) -> Result<HumanAddr, String> {
+    pub fn register_contract(
+        &mut self,
+        code_id: usize,
+        admin: Option<HumanAddr>,
+        label: String,
+    ) -> Result<HumanAddr, String> {
         if !self.router.handlers.contains_key(&code_id) {
             return Err("Cannot init contract with unregistered code id".to_string());
         }
         let addr = self.next_address();
-        let info = ContractData::new(code_id, (self.router.storage_factory)());
+        let info = ContractData::new(code_id, admin, label, (self.router.storage_factory)());
         self.state.contracts.insert(addr.clone(), info);
         Ok(addr)
     }
 
     // TODO: better addr generation
     fn next_address(&self) -> HumanAddr {
-        let count = self.router.contracts.len() + self.state.contracts.len();
+        let count = self.router.contracts.len() + self.state.contract_count();
         // we make this longer so it is not rejected by tests
         HumanAddr::from("Contract #".to_string() + &count.to_string())
     }
@@ -545,6 +738,94 @@ where
             },
         )
     }
+
+    /// Switches the contract to the new code, and calls migrate on it.
+    /// Only the admin of the contract may do so.
+    pub fn migrate(
+        &mut self,
+        address: HumanAddr,
+        querier: &dyn Querier,
+        sender: HumanAddr,
+        new_code_id: usize,
+        msg: Vec<u8>,
+    ) -> Result<Response<C>, String> {
+        let mut meta = self.admin_meta(&address, &sender)?;
+        if !self.router.handlers.contains_key(&new_code_id) {
+            return Err("Cannot migrate contract to unregistered code id".to_string());
+        }
+        meta.code_id = new_code_id;
+        self.state.set_meta(&address, meta);
+
+        let parent = &self.router.handlers;
+        let contracts = &self.router.contracts;
+        let env = self.router.get_env(address.clone());
+        let api = self.router.api.as_ref();
+
+        self.state.with_storage(
+            querier,
+            contracts,
+            address,
+            env,
+            api,
+            |code_id, deps, env| {
+                let handler = parent
+                    .get(&code_id)
+                    .ok_or_else(|| "Unregistered code id".to_string())?;
+                handler.migrate(deps, env, msg)
+            },
+        )
+    }
+
+    /// Sets a new admin (or none). Only the current admin of the contract may do so.
+    pub fn update_admin(
+        &mut self,
+        address: HumanAddr,
+        sender: HumanAddr,
+        new_admin: Option<HumanAddr>,
+    ) -> Result<(), String> {
+        let mut meta = self.admin_meta(&address, &sender)?;
+        meta.admin = new_admin;
+        self.state.set_meta(&address, meta);
+        Ok(())
+    }
+
+    // returns the contract meta if sender is the admin
+    fn admin_meta(&self, address: &HumanAddr, sender: &HumanAddr) -> Result<ContractMeta, String> {
+        let meta = self
+            .state
+            .get_meta(&self.router.contracts, address)
+            .ok_or_else(|| "Unregistered contract address".to_string())?;
+        if meta.admin.as_ref() != Some(sender) {
+            return Err("Only the admin can migrate the contract or change its admin".to_string());
+        }
+        Ok(meta)
+    }
+
+    pub fn reply(
+        &mut self,
+        address: HumanAddr,
+        querier: &dyn Querier,
+        reply: Reply,
+    ) -> Result<Response<C>, String> {
+        let parent = &self.router.handlers;
+        let contracts = &self.router.contracts;
+        let env = self.router.get_env(address.clone());
+        let api = self.router.api.as_ref();
+
+        self.state.with_storage(
+            querier,
+            contracts,
+            address,
+            env,
+            api,
+            |code_id, deps, env| {
+                let handler = parent
+                    .get(&code_id)
+                    .ok_or_else(|| "Unregistered code id".to_string())?;
+                handler.reply(deps, env, reply)
+            },
+        )
+    }
 }
 
 impl<'a> WasmCacheState<'a> {
@@ -558,30 +839,75 @@ impl<'a> WasmCacheState<'a> {
         WasmOps {
             new_contracts: self.contracts,
             contract_diffs: diffs,
+            contract_meta: self.contract_meta,
+        }
+    }
+
+    fn get_meta(
+        &self,
+        parent: &HashMap<HumanAddr, ContractData>,
+        addr: &HumanAddr,
+    ) -> Option<ContractMeta> {
+        if let Some(x) = self.contracts.get(addr) {
+            return Some(x.meta.clone());
+        }
+        if let Some(meta) = self.contract_meta.get(addr) {
+            return Some(meta.clone());
+        }
+        match self.parent {
+            Some(cache) => cache.get_meta(parent, addr),
+            None => parent.get(addr).map(|c| c.meta.clone()),
         }
     }
 
+    fn set_meta(&mut self, addr: &HumanAddr, meta: ContractMeta) {
+        match self.contracts.get_mut(addr) {
+            Some(x) => x.meta = meta,
+            None => {
+                self.contract_meta.insert(addr.clone(), meta);
+            }
+        }
+    }
+
+    fn contract_count(&self) -> usize {
+        self.contracts.len() + self.parent.map_or(0, |p| p.contract_count())
+    }
+
     fn get_contract<'b>(
         &'b mut self,
         parent: &'a HashMap<HumanAddr, ContractData>,
         addr: &HumanAddr,
     ) -> Option<(usize, &'b mut dyn Storage)> {
+        let code_id = self.get_meta(parent, addr)?.code_id;
+        let parent_cache = self.parent;
         // if we created this transaction
         if let Some(x) = self.contracts.get_mut(addr) {
-            return Some((x.code_id, x.storage.as_mut()));
+            return Some((code_id, x.storage.as_mut()));
         }
-        if let Some(c) = parent.get(addr) {
-            let code_id = c.code_id;
-            if self.contract_diffs.contains_key(addr) {
-                let storage = self.contract_diffs.get_mut(addr).unwrap();
-                return Some((code_id, storage));
-            }
-            // else make a new transaction
-            let wrap = StorageTransaction::new(c.storage.as_ref());
-            self.contract_diffs.insert(addr.clone(), wrap);
-            Some((code_id, self.contract_diffs.get_mut(addr).unwrap()))
-        } else {
-            None
+        let base = base_storage(parent_cache, parent, addr)?;
+        if self.contract_diffs.contains_key(addr) {
+            let storage = self.contract_diffs.get_mut(addr).unwrap();
+            return Some((code_id, storage));
+        }
+        // else make a new transaction
+        let wrap = StorageTransaction::new(base);
+        self.contract_diffs.insert(addr.clone(), wrap);
+        Some((code_id, self.contract_diffs.get_mut(addr).unwrap()))
+    }
+
+    // the storage of the contract as seen by this cache, including all uncommitted changes
+    fn get_storage(
+        &'a self,
+        parent: &'a HashMap<HumanAddr, ContractData>,
+        addr: &HumanAddr,
+    ) -> Option<&'a dyn Storage> {
+        if let Some(x) = self.contracts.get(addr) {
+            return Some(x.storage.as_ref());
+        }
+        let base = base_storage(self.parent, parent, addr)?;
+        match self.contract_diffs.get(addr) {
+            Some(storage) => Some(storage),
+            None => Some(base),
         }
     }
 
@@ -609,6 +935,18 @@ impl<'a> WasmCacheState<'a> {
     }
 }
 
+// the storage a cache builds on, which is the storage seen by the parent cache if it is nested
+fn base_storage<'a>(
+    parent_cache: Option<&'a WasmCacheState<'a>>,
+    parent: &'a HashMap<HumanAddr, ContractData>,
+    addr: &HumanAddr,
+) -> Option<&'a dyn Storage> {
+    match parent_cache {
+        Some(cache) => cache.get_storage(parent, addr),
+        None => parent.get(addr).map(|c| c.storage.as_ref()),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -630,10 +968,14 @@ mod test {
         let mut cache = router.cache();
 
         // cannot register contract with unregistered codeId
-        cache.register_contract(code_id + 1).unwrap_err();
+        cache
+            .register_contract(code_id + 1, None, "Fail".to_string())
+            .unwrap_err();
 
         // we can register a new instance of this code
-        let contract_addr = cache.register_contract(code_id).unwrap();
+        let contract_addr = cache
+            .register_contract(code_id, None, "Contract".to_string())
+            .unwrap();
 
         // now, we call this contract and see the error message from the contract
         let querier: MockQuerier<Empty> = MockQuerier::new(&[]);
@@ -674,7 +1016,9 @@ mod test {
         let code_id = router.store_code(contract_payout());
         let mut cache = router.cache();
 
-        let contract_addr = cache.register_contract(code_id).unwrap();
+        let contract_addr = cache
+            .register_contract(code_id, None, "Contract".to_string())
+            .unwrap();
 
         let querier: MockQuerier<Empty> = MockQuerier::new(&[]);
         let payout = coin(100, "TGD");
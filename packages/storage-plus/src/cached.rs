@@ -0,0 +1,460 @@
+//! CachedItem and CachedMap memoize the deserialized values of an Item or Map for one execution.
+//! A handler that checks the config in several helpers only reads and parses it once,
+//! and repeated updates of the same balance are only written once, on `flush`.
+//!
+//! They hold no reference to the storage, so reads and writes go through the `deps.storage`
+//! passed in. Writes are buffered until `flush`, which must be called before returning,
+//! otherwise they are lost. `with_cache` does that for you, by flushing once the closure
+//! succeeded. Anything written to the same keys without the cache in the meantime
+//! is not seen by it, and is overwritten by the flush.
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::type_name;
+use std::collections::BTreeMap;
+
+use cosmwasm_std::{StdError, StdResult, Storage};
+
+use crate::item::Item;
+use crate::keys::PrimaryKey;
+use crate::map::Map;
+use crate::serializer::{Json, Serializer};
+
+/// The cached value of one key. value is None if there is nothing stored (or it was removed)
+struct Cached<T> {
+    value: Option<T>,
+    dirty: bool,
+}
+
+/// CachedItem wraps an Item, eg. `let mut config = CachedItem::new(&CONFIG);`
+pub struct CachedItem<'i, 'a, T, S = Json> {
+    item: &'i Item<'a, T, S>,
+    cached: Option<Cached<T>>,
+}
+
+impl<'i, 'a, T, S> CachedItem<'i, 'a, T, S>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    S: Serializer<T>,
+{
+    pub fn new(item: &'i Item<'a, T, S>) -> Self {
+        CachedItem { item, cached: None }
+    }
+
+    /// Runs action with a new cache of the item, and flushes it if the action succeeded.
+    /// On error, the changes are dropped along with the cache.
+    pub fn with_cache<A, R, E>(
+        item: &'i Item<'a, T, S>,
+        store: &mut dyn Storage,
+        action: A,
+    ) -> Result<R, E>
+    where
+        A: FnOnce(&mut dyn Storage, &mut Self) -> Result<R, E>,
+        E: From<StdError>,
+    {
+        let mut cache = Self::new(item);
+        let res = action(store, &mut cache)?;
+        cache.flush(store)?;
+        Ok(res)
+    }
+
+    fn entry(&mut self, store: &dyn Storage) -> StdResult<&mut Cached<T>> {
+        if self.cached.is_none() {
+            let value = self.item.may_load(store)?;
+            self.cached = Some(Cached {
+                value,
+                dirty: false,
+            });
+        }
+        Ok(self.cached.as_mut().unwrap())
+    }
+
+    /// Like `Item::may_load`, but only reads the storage the first time
+    pub fn may_load(&mut self, store: &dyn Storage) -> StdResult<Option<T>> {
+        Ok(self.entry(store)?.value.clone())
+    }
+
+    /// Like `Item::load`, but only reads the storage the first time
+    pub fn load(&mut self, store: &dyn Storage) -> StdResult<T> {
+        self.may_load(store)?
+            .ok_or_else(|| StdError::not_found(type_name::<T>()))
+    }
+
+    /// Updates the cached value. This is only written to storage on `flush`
+    pub fn save(&mut self, data: &T) {
+        self.cached = Some(Cached {
+            value: Some(data.clone()),
+            dirty: true,
+        });
+    }
+
+    /// Removes the cached value. This is only removed from storage on `flush`
+    pub fn remove(&mut self) {
+        self.cached = Some(Cached {
+            value: None,
+            dirty: true,
+        });
+    }
+
+    /// See `Item::update`. The result is only written to storage on `flush`
+    pub fn update<A, E>(&mut self, store: &dyn Storage, action: A) -> Result<T, E>
+    where
+        A: FnOnce(T) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let input = self.load(store)?;
+        let output = action(input)?;
+        self.save(&output);
+        Ok(output)
+    }
+
+    /// Returns true if there are changes not yet written to storage
+    pub fn is_dirty(&self) -> bool {
+        matches!(self.cached, Some(Cached { dirty: true, .. }))
+    }
+
+    /// Writes the value back to storage, if it was changed. The cache stays valid afterwards.
+    pub fn flush(&mut self, store: &mut dyn Storage) -> StdResult<()> {
+        if let Some(cached) = self.cached.as_mut() {
+            if cached.dirty {
+                match &cached.value {
+                    Some(value) => self.item.save(store, value)?,
+                    None => self.item.remove(store),
+                }
+                cached.dirty = false;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// CachedMap wraps a Map, eg. `let mut balances = CachedMap::new(&BALANCES);`.
+/// Every key is read at most once. Ranges are not supported, as they would miss the
+/// buffered writes; flush before iterating over the map.
+pub struct CachedMap<'m, 'a, K, T, S = Json> {
+    map: &'m Map<'a, K, T, S>,
+    // indexed by the full storage key
+    entries: BTreeMap<Vec<u8>, Cached<T>>,
+}
+
+impl<'m, 'a, K, T, S> CachedMap<'m, 'a, K, T, S>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    K: PrimaryKey<'a>,
+    S: Serializer<T>,
+{
+    pub fn new(map: &'m Map<'a, K, T, S>) -> Self {
+        CachedMap {
+            map,
+            entries: BTreeMap::new(),
+        }
+    }
+
+    /// Runs action with a new cache of the map, and flushes it if the action succeeded.
+    /// On error, the changes are dropped along with the cache.
+    pub fn with_cache<A, R, E>(
+        map: &'m Map<'a, K, T, S>,
+        store: &mut dyn Storage,
+        action: A,
+    ) -> Result<R, E>
+    where
+        A: FnOnce(&mut dyn Storage, &mut Self) -> Result<R, E>,
+        E: From<StdError>,
+    {
+        let mut cache = Self::new(map);
+        let res = action(store, &mut cache)?;
+        cache.flush(store)?;
+        Ok(res)
+    }
+
+    fn entry(&mut self, store: &dyn Storage, k: K) -> StdResult<&mut Cached<T>> {
+        let path = self.map.key(k);
+        let key = path.to_vec();
+        if !self.entries.contains_key(&key) {
+            let value = path.may_load(store)?;
+            self.entries.insert(
+                key.clone(),
+                Cached {
+                    value,
+                    dirty: false,
+                },
+            );
+        }
+        Ok(self.entries.get_mut(&key).unwrap())
+    }
+
+    fn set(&mut self, k: K, value: Option<T>) {
+        let key = self.map.key(k).to_vec();
+        self.entries.insert(key, Cached { value, dirty: true });
+    }
+
+    /// Like `Map::may_load`, but only reads the storage the first time for every key
+    pub fn may_load(&mut self, store: &dyn Storage, k: K) -> StdResult<Option<T>> {
+        Ok(self.entry(store, k)?.value.clone())
+    }
+
+    /// Like `Map::load`, but only reads the storage the first time for every key
+    pub fn load(&mut self, store: &dyn Storage, k: K) -> StdResult<T> {
+        self.may_load(store, k)?
+            .ok_or_else(|| StdError::not_found(type_name::<T>()))
+    }
+
+    /// Updates the cached value. This is only written to storage on `flush`
+    pub fn save(&mut self, k: K, data: &T) {
+        self.set(k, Some(data.clone()))
+    }
+
+    /// Removes the cached value. This is only removed from storage on `flush`
+    pub fn remove(&mut self, k: K) {
+        self.set(k, None)
+    }
+
+    /// See `Map::update`. The result is only written to storage on `flush`
+    pub fn update<A, E>(&mut self, store: &dyn Storage, k: K, action: A) -> Result<T, E>
+    where
+        A: FnOnce(Option<T>) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let entry = self.entry(store, k)?;
+        let output = action(entry.value.clone())?;
+        entry.value = Some(output.clone());
+        entry.dirty = true;
+        Ok(output)
+    }
+
+    /// Returns the number of keys with changes not yet written to storage
+    pub fn dirty_count(&self) -> usize {
+        self.entries.values().filter(|e| e.dirty).count()
+    }
+
+    /// Writes all changed values back to storage. The cache stays valid afterwards.
+    pub fn flush(&mut self, store: &mut dyn Storage) -> StdResult<()> {
+        for (key, cached) in self.entries.iter_mut().filter(|(_, e)| e.dirty) {
+            match &cached.value {
+                Some(value) => store.set(key, &S::to_vec(value)?),
+                None => store.remove(key),
+            }
+            cached.dirty = false;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    #[cfg(feature = "iterator")]
+    use cosmwasm_std::{Order, KV};
+    use serde::Deserialize;
+    use std::cell::Cell;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Config {
+        owner: String,
+        fee: u64,
+    }
+
+    const CONFIG: Item<Config> = Item::new("config");
+    const BALANCES: Map<&str, u64> = Map::new("balances");
+
+    /// Counts the reads and writes, to compare the cached and uncached access
+    #[derive(Default)]
+    struct CountingStorage {
+        inner: MockStorage,
+        reads: Cell<usize>,
+        writes: usize,
+    }
+
+    impl Storage for CountingStorage {
+        fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
+            self.reads.set(self.reads.get() + 1);
+            self.inner.get(key)
+        }
+
+        #[cfg(feature = "iterator")]
+        fn range<'a>(
+            &'a self,
+            start: Option<&[u8]>,
+            end: Option<&[u8]>,
+            order: Order,
+        ) -> Box<dyn Iterator<Item = KV> + 'a> {
+            self.inner.range(start, end, order)
+        }
+
+        fn set(&mut self, key: &[u8], value: &[u8]) {
+            self.writes += 1;
+            self.inner.set(key, value)
+        }
+
+        fn remove(&mut self, key: &[u8]) {
+            self.writes += 1;
+            self.inner.remove(key)
+        }
+    }
+
+    // the fee is checked in every transfer, like a handler calling the same helpers in a loop
+    fn pay_out(store: &mut CountingStorage, payouts: &[(&str, u64)]) {
+        for (addr, amount) in payouts {
+            let fee = CONFIG.load(store).unwrap().fee;
+            BALANCES
+                .update(store, addr, |b| -> StdResult<_> {
+                    Ok(b.unwrap_or_default() + amount - fee)
+                })
+                .unwrap();
+        }
+    }
+
+    fn pay_out_cached(store: &mut CountingStorage, payouts: &[(&str, u64)]) {
+        let mut config = CachedItem::new(&CONFIG);
+        let mut balances = CachedMap::new(&BALANCES);
+        for (addr, amount) in payouts {
+            let fee = config.load(store).unwrap().fee;
+            balances
+                .update(store, addr, |b| -> StdResult<_> {
+                    Ok(b.unwrap_or_default() + amount - fee)
+                })
+                .unwrap();
+        }
+        balances.flush(store).unwrap();
+        config.flush(store).unwrap();
+    }
+
+    fn setup() -> CountingStorage {
+        let mut store = CountingStorage::default();
+        let config = Config {
+            owner: "admin".to_string(),
+            fee: 1,
+        };
+        CONFIG.save(&mut store, &config).unwrap();
+        store.writes = 0;
+        store
+    }
+
+    const PAYOUTS: &[(&str, u64)] = &[("john", 10), ("mary", 20), ("john", 30), ("john", 40)];
+
+    #[test]
+    fn cached_reads_and_writes_less() {
+        let mut plain = setup();
+        pay_out(&mut plain, PAYOUTS);
+        assert_eq!(plain.reads.get(), 8);
+        assert_eq!(plain.writes, 4);
+
+        let mut cached = setup();
+        pay_out_cached(&mut cached, PAYOUTS);
+        // config once, and every balance once
+        assert_eq!(cached.reads.get(), 3);
+        assert_eq!(cached.writes, 2);
+
+        // with the same result
+        for addr in &["john", "mary"] {
+            assert_eq!(
+                BALANCES.load(&plain.inner, addr).unwrap(),
+                BALANCES.load(&cached.inner, addr).unwrap()
+            );
+        }
+        assert_eq!(BALANCES.load(&cached.inner, "john").unwrap(), 77);
+    }
+
+    #[test]
+    fn with_cache_flushes_on_success() {
+        let mut store = MockStorage::new();
+        BALANCES.save(&mut store, "john", &100).unwrap();
+
+        let total = CachedMap::with_cache(&BALANCES, &mut store, |store, balances| {
+            balances.update(store, "john", |b| -> StdResult<_> {
+                Ok(b.unwrap_or_default() - 30)
+            })?;
+            balances.update(store, "mary", |b| -> StdResult<_> {
+                Ok(b.unwrap_or_default() + 30)
+            })?;
+            // nothing written yet
+            assert_eq!(BALANCES.load(store, "john").unwrap(), 100);
+            Ok::<_, StdError>(balances.load(store, "john")? + balances.load(store, "mary")?)
+        })
+        .unwrap();
+        assert_eq!(total, 100);
+        assert_eq!(BALANCES.load(&store, "john").unwrap(), 70);
+        assert_eq!(BALANCES.load(&store, "mary").unwrap(), 30);
+
+        // an error drops all changes
+        let err = CachedMap::with_cache(&BALANCES, &mut store, |store, balances| {
+            balances.save("john", &0);
+            balances.update(store, "anna", |b| match b {
+                Some(b) => Ok(b),
+                None => Err(StdError::generic_err("no anna")),
+            })
+        })
+        .unwrap_err();
+        assert_eq!(err, StdError::generic_err("no anna"));
+        assert_eq!(BALANCES.load(&store, "john").unwrap(), 70);
+
+        let fee = CachedItem::with_cache(&CONFIG, &mut store, |_, config| {
+            config.save(&Config {
+                owner: "admin".to_string(),
+                fee: 3,
+            });
+            Ok::<_, StdError>(3)
+        })
+        .unwrap();
+        assert_eq!(CONFIG.load(&store).unwrap().fee, fee);
+    }
+
+    #[test]
+    fn item_writes_only_on_flush() {
+        let mut store = MockStorage::new();
+        let mut config = CachedItem::new(&CONFIG);
+        assert_eq!(config.may_load(&store).unwrap(), None);
+        assert_eq!(
+            config.load(&store).unwrap_err(),
+            StdError::not_found(type_name::<Config>())
+        );
+
+        let cfg = Config {
+            owner: "admin".to_string(),
+            fee: 5,
+        };
+        config.save(&cfg);
+        assert!(config.is_dirty());
+        let updated = config
+            .update(&store, |mut c| -> StdResult<_> {
+                c.fee += 1;
+                Ok(c)
+            })
+            .unwrap();
+        assert_eq!(updated.fee, 6);
+        assert_eq!(CONFIG.may_load(&store).unwrap(), None);
+
+        config.flush(&mut store).unwrap();
+        assert!(!config.is_dirty());
+        assert_eq!(CONFIG.load(&store).unwrap(), updated);
+
+        config.remove();
+        assert_eq!(config.may_load(&store).unwrap(), None);
+        assert_eq!(CONFIG.load(&store).unwrap(), updated);
+        config.flush(&mut store).unwrap();
+        assert_eq!(CONFIG.may_load(&store).unwrap(), None);
+    }
+
+    #[test]
+    fn map_writes_only_on_flush() {
+        let mut store = MockStorage::new();
+        BALANCES.save(&mut store, "john", &100).unwrap();
+        BALANCES.save(&mut store, "mary", &50).unwrap();
+
+        let mut balances = CachedMap::new(&BALANCES);
+        assert_eq!(balances.load(&store, "john").unwrap(), 100);
+        balances.save("john", &80);
+        balances.remove("mary");
+        balances.save("anna", &20);
+        assert_eq!(balances.load(&store, "john").unwrap(), 80);
+        assert_eq!(balances.may_load(&store, "mary").unwrap(), None);
+        assert_eq!(balances.dirty_count(), 3);
+        assert_eq!(BALANCES.load(&store, "john").unwrap(), 100);
+
+        balances.flush(&mut store).unwrap();
+        assert_eq!(balances.dirty_count(), 0);
+        assert_eq!(BALANCES.load(&store, "john").unwrap(), 80);
+        assert_eq!(BALANCES.may_load(&store, "mary").unwrap(), None);
+        assert_eq!(BALANCES.load(&store, "anna").unwrap(), 20);
+    }
+}
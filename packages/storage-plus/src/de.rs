@@ -0,0 +1,176 @@
+//! KeyDeserialize turns the raw bytes of a storage key back into a typed value.
+//! This is the inverse of `PrimaryKey::joined_key`, and follows the same
+//! length-prefixed encoding for composite keys (see `helpers.rs`).
+
+use cosmwasm_std::{StdError, StdResult};
+
+use crate::helpers::decode_length;
+use crate::keys::{IntKey, PkOwned};
+use crate::Endian;
+
+pub trait KeyDeserialize {
+    type Output: Sized;
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output>;
+
+    fn from_slice(value: &[u8]) -> StdResult<Self::Output> {
+        Self::from_vec(value.to_vec())
+    }
+}
+
+impl KeyDeserialize for () {
+    type Output = ();
+
+    #[inline(always)]
+    fn from_vec(_value: Vec<u8>) -> StdResult<Self::Output> {
+        Ok(())
+    }
+}
+
+impl KeyDeserialize for Vec<u8> {
+    type Output = Vec<u8>;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        Ok(value)
+    }
+}
+
+impl KeyDeserialize for &[u8] {
+    type Output = Vec<u8>;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        Ok(value)
+    }
+}
+
+impl KeyDeserialize for PkOwned {
+    type Output = Vec<u8>;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        Ok(value)
+    }
+}
+
+impl KeyDeserialize for String {
+    type Output = String;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        String::from_utf8(value).map_err(StdError::invalid_utf8)
+    }
+}
+
+impl KeyDeserialize for &str {
+    type Output = String;
+
+    #[inline(always)]
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        String::from_vec(value)
+    }
+}
+
+impl<T: Endian> KeyDeserialize for IntKey<T> {
+    type Output = T;
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        let mut buf = T::Buf::default();
+        let expected = buf.as_ref().len();
+        if value.len() != expected {
+            return Err(StdError::invalid_data_size(expected, value.len()));
+        }
+        buf.as_mut().copy_from_slice(&value);
+        Ok(T::from_sortable_bytes(buf))
+    }
+}
+
+impl<T: KeyDeserialize, U: KeyDeserialize> KeyDeserialize for (T, U) {
+    type Output = (T::Output, U::Output);
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        let (first, rest) = split_first_key(&value)?;
+        Ok((T::from_slice(first)?, U::from_slice(rest)?))
+    }
+}
+
+impl<T: KeyDeserialize, U: KeyDeserialize, V: KeyDeserialize> KeyDeserialize for (T, U, V) {
+    type Output = (T::Output, U::Output, V::Output);
+
+    fn from_vec(value: Vec<u8>) -> StdResult<Self::Output> {
+        let (first, rest) = split_first_key(&value)?;
+        let (second, third) = split_first_key(rest)?;
+        Ok((
+            T::from_slice(first)?,
+            U::from_slice(second)?,
+            V::from_slice(third)?,
+        ))
+    }
+}
+
+/// Splits off the first length-prefixed element of a joined key,
+/// returning it along with the remaining bytes
+fn split_first_key(value: &[u8]) -> StdResult<(&[u8], &[u8])> {
+    if value.len() < 2 {
+        return Err(StdError::generic_err(
+            "Joined key too short for length prefix",
+        ));
+    }
+    let len = decode_length(&value[0..2]);
+    let rest = &value[2..];
+    if rest.len() < len {
+        return Err(StdError::generic_err(
+            "Joined key shorter than its length prefix",
+        ));
+    }
+    Ok(rest.split_at(len))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::keys::{PrimaryKey, U32Key, U64Key, U8Key};
+
+    #[test]
+    fn deserialize_simple_keys() {
+        assert_eq!(b"foo".to_vec(), <&[u8]>::from_vec(b"foo".to_vec()).unwrap());
+        assert_eq!("bar", <&str>::from_vec(b"bar".to_vec()).unwrap());
+        assert_eq!(
+            12345u64,
+            U64Key::from_vec(12345u64.to_be_bytes().to_vec()).unwrap()
+        );
+        assert_eq!(7u8, U8Key::from_slice(&[7]).unwrap());
+    }
+
+    #[test]
+    fn deserialize_invalid_keys() {
+        // not utf-8
+        assert!(matches!(
+            <&str>::from_vec(vec![0xc3, 0x28]).unwrap_err(),
+            StdError::InvalidUtf8 { .. }
+        ));
+        // wrong int length
+        assert!(matches!(
+            U32Key::from_vec(vec![1, 2, 3]).unwrap_err(),
+            StdError::InvalidDataSize {
+                expected: 4,
+                actual: 3,
+                ..
+            }
+        ));
+        // length prefix longer than data
+        assert!(<(&str, &str)>::from_vec(vec![0, 9, b'a']).is_err());
+    }
+
+    #[test]
+    fn deserialize_joined_keys() {
+        let key: (&str, U64Key) = ("owner", 42.into());
+        let parsed = <(&str, U64Key)>::from_vec(key.joined_key()).unwrap();
+        assert_eq!(("owner".to_string(), 42u64), parsed);
+
+        let key: (&[u8], U32Key, &str) = (b"begin", 15.into(), "end");
+        let parsed = <(&[u8], U32Key, &str)>::from_vec(key.joined_key()).unwrap();
+        assert_eq!((b"begin".to_vec(), 15u32, "end".to_string()), parsed);
+    }
+}
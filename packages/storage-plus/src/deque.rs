@@ -0,0 +1,406 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::convert::TryInto;
+use std::marker::PhantomData;
+
+use cosmwasm_std::{to_vec, StdError, StdResult, Storage};
+
+use crate::helpers::{may_deserialize, namespaces_with_key};
+use crate::registry::{DescribeStorage, StorageKey};
+
+// metadata keys need to have different length than the position type (4 bytes) to prevent collisions
+const TAIL_KEY: &[u8] = b"t";
+const HEAD_KEY: &[u8] = b"h";
+
+/// A deque stores multiple items at the given key. It provides efficient FIFO and LIFO access,
+/// as well as direct index access.
+///
+/// It has a maximum capacity of `u32::MAX - 1`. Make sure to never exceed that number when using this type.
+/// If you do, the methods won't work as intended anymore.
+pub struct Deque<'a, T> {
+    // prefix of the deque items
+    namespace: &'a [u8],
+    // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
+    item_type: PhantomData<T>,
+}
+
+impl<'a, T> Deque<'a, T> {
+    pub const fn new(prefix: &'a str) -> Self {
+        Self {
+            namespace: prefix.as_bytes(),
+            item_type: PhantomData,
+        }
+    }
+}
+
+impl<'a, T> DescribeStorage for Deque<'a, T> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        vec![StorageKey::namespace(self.namespace)]
+    }
+}
+
+impl<'a, T: Serialize + DeserializeOwned> Deque<'a, T> {
+    /// Adds the given value to the end of the deque
+    pub fn push_back(&self, storage: &mut dyn Storage, value: &T) -> StdResult<()> {
+        // save value
+        let pos = self.tail(storage)?;
+        self.set_unchecked(storage, pos, value)?;
+        // update tail
+        self.set_tail(storage, pos.wrapping_add(1));
+
+        Ok(())
+    }
+
+    /// Adds the given value to the front of the deque
+    pub fn push_front(&self, storage: &mut dyn Storage, value: &T) -> StdResult<()> {
+        // need to subtract first, because head potentially points to existing value
+        let pos = self.head(storage)?.wrapping_sub(1);
+        self.set_unchecked(storage, pos, value)?;
+        // update head
+        self.set_head(storage, pos);
+
+        Ok(())
+    }
+
+    /// Removes the last element of the deque and returns it
+    pub fn pop_back(&self, storage: &mut dyn Storage) -> StdResult<Option<T>> {
+        if self.is_empty(storage)? {
+            return Ok(None);
+        }
+        // get position
+        let pos = self.tail(storage)?.wrapping_sub(1);
+        let value = self.get_unchecked(storage, pos)?;
+        if value.is_some() {
+            self.remove_unchecked(storage, pos);
+            // only update tail if a value was popped
+            self.set_tail(storage, pos);
+        }
+        Ok(value)
+    }
+
+    /// Removes the first element of the deque and returns it
+    pub fn pop_front(&self, storage: &mut dyn Storage) -> StdResult<Option<T>> {
+        if self.is_empty(storage)? {
+            return Ok(None);
+        }
+        // get position
+        let pos = self.head(storage)?;
+        let value = self.get_unchecked(storage, pos)?;
+        if value.is_some() {
+            self.remove_unchecked(storage, pos);
+            // only update head if a value was popped
+            self.set_head(storage, pos.wrapping_add(1));
+        }
+        Ok(value)
+    }
+
+    /// Returns the first element of the deque without removing it
+    pub fn front(&self, storage: &dyn Storage) -> StdResult<Option<T>> {
+        if self.is_empty(storage)? {
+            return Ok(None);
+        }
+        let pos = self.head(storage)?;
+        self.get_unchecked(storage, pos)
+    }
+
+    /// Returns the last element of the deque without removing it
+    pub fn back(&self, storage: &dyn Storage) -> StdResult<Option<T>> {
+        if self.is_empty(storage)? {
+            return Ok(None);
+        }
+        let pos = self.tail(storage)?.wrapping_sub(1);
+        self.get_unchecked(storage, pos)
+    }
+
+    /// Gets the length of the deque.
+    pub fn len(&self, storage: &dyn Storage) -> StdResult<u32> {
+        Ok(calc_len(self.head(storage)?, self.tail(storage)?))
+    }
+
+    /// Returns `true` if the deque contains no elements.
+    pub fn is_empty(&self, storage: &dyn Storage) -> StdResult<bool> {
+        Ok(self.len(storage)? == 0)
+    }
+
+    /// Returns the value at the given position in the queue or `None` if the index is out of bounds
+    pub fn get(&self, storage: &dyn Storage, pos: u32) -> StdResult<Option<T>> {
+        let head = self.head(storage)?;
+        let tail = self.tail(storage)?;
+
+        if pos >= calc_len(head, tail) {
+            // out of bounds
+            return Ok(None);
+        }
+
+        let pos = head.wrapping_add(pos);
+        self.get_unchecked(storage, pos)
+            .and_then(|v| v.ok_or_else(|| StdError::not_found("deque item")))
+            .map(Some)
+    }
+
+    /// Returns an iterator over the deque, starting at the front
+    pub fn iter<'b>(&self, storage: &'b dyn Storage) -> StdResult<DequeIter<'a, 'b, T>> {
+        Ok(DequeIter {
+            deque: Deque {
+                namespace: self.namespace,
+                item_type: PhantomData,
+            },
+            storage,
+            start: self.head(storage)?,
+            end: self.tail(storage)?,
+        })
+    }
+
+    /// Gets the head position from storage.
+    ///
+    /// Unless the deque is empty, this points to the first element.
+    fn head(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.read_meta_key(storage, HEAD_KEY)
+    }
+
+    /// Gets the tail position from storage.
+    ///
+    /// This points to the first empty position after the last element.
+    fn tail(&self, storage: &dyn Storage) -> StdResult<u32> {
+        self.read_meta_key(storage, TAIL_KEY)
+    }
+
+    fn set_head(&self, storage: &mut dyn Storage, value: u32) {
+        self.set_meta_key(storage, HEAD_KEY, value);
+    }
+
+    fn set_tail(&self, storage: &mut dyn Storage, value: u32) {
+        self.set_meta_key(storage, TAIL_KEY, value);
+    }
+
+    /// Helper method for `tail` and `head` methods to handle reading the value from storage
+    fn read_meta_key(&self, storage: &dyn Storage, key: &[u8]) -> StdResult<u32> {
+        let full_key = namespaces_with_key(&[self.namespace], key);
+        storage
+            .get(&full_key)
+            .map(|vec| {
+                Ok(u32::from_be_bytes(
+                    vec.as_slice()
+                        .try_into()
+                        .map_err(|e| StdError::parse_err("u32", e))?,
+                ))
+            })
+            .unwrap_or(Ok(0))
+    }
+
+    /// Helper method for `set_tail` and `set_head` methods to write to storage
+    #[inline]
+    fn set_meta_key(&self, storage: &mut dyn Storage, key: &[u8], value: u32) {
+        let full_key = namespaces_with_key(&[self.namespace], key);
+        storage.set(&full_key, &value.to_be_bytes());
+    }
+
+    /// Tries to get the value at the given position
+    /// Used internally
+    fn get_unchecked(&self, storage: &dyn Storage, pos: u32) -> StdResult<Option<T>> {
+        let prefixed_key = namespaces_with_key(&[self.namespace], &pos.to_be_bytes());
+        may_deserialize(&storage.get(&prefixed_key))
+    }
+
+    /// Removes the value at the given position
+    /// Used internally
+    fn remove_unchecked(&self, storage: &mut dyn Storage, pos: u32) {
+        let prefixed_key = namespaces_with_key(&[self.namespace], &pos.to_be_bytes());
+        storage.remove(&prefixed_key);
+    }
+
+    /// Tries to set the value at the given position
+    /// Used internally when pushing
+    fn set_unchecked(&self, storage: &mut dyn Storage, pos: u32, value: &T) -> StdResult<()> {
+        let prefixed_key = namespaces_with_key(&[self.namespace], &pos.to_be_bytes());
+        storage.set(&prefixed_key, &to_vec(value)?);
+
+        Ok(())
+    }
+}
+
+// calculates the number of elements between head and tail, taking wrapping into account
+#[inline]
+fn calc_len(head: u32, tail: u32) -> u32 {
+    tail.wrapping_sub(head)
+}
+
+pub struct DequeIter<'a, 'b, T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    deque: Deque<'a, T>,
+    storage: &'b dyn Storage,
+    start: u32,
+    end: u32,
+}
+
+impl<'a, 'b, T> Iterator for DequeIter<'a, 'b, T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    type Item = StdResult<T>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.start == self.end {
+            return None;
+        }
+
+        let item = self
+            .deque
+            .get_unchecked(self.storage, self.start)
+            .and_then(|item| item.ok_or_else(|| StdError::not_found("deque item")));
+        self.start = self.start.wrapping_add(1);
+
+        Some(item)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let len = calc_len(self.start, self.end) as usize;
+        (len, Some(len))
+    }
+}
+
+impl<'a, 'b, T> DoubleEndedIterator for DequeIter<'a, 'b, T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.start == self.end {
+            return None;
+        }
+
+        let item = self
+            .deque
+            .get_unchecked(self.storage, self.end.wrapping_sub(1)) // end points to position after last element
+            .and_then(|item| item.ok_or_else(|| StdError::not_found("deque item")));
+        self.end = self.end.wrapping_sub(1);
+
+        Some(item)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct Data {
+        pub name: String,
+        pub validator: String,
+    }
+
+    const DATA: Deque<Data> = Deque::new("data");
+
+    #[test]
+    fn push_and_pop() {
+        let mut store = MockStorage::new();
+        let deque: Deque<u32> = Deque::new("test");
+
+        deque.push_back(&mut store, &1).unwrap();
+        deque.push_back(&mut store, &2).unwrap();
+
+        assert_eq!(deque.pop_back(&mut store).unwrap(), Some(2));
+        assert_eq!(deque.pop_back(&mut store).unwrap(), Some(1));
+        assert_eq!(deque.pop_back(&mut store).unwrap(), None);
+
+        deque.push_back(&mut store, &1).unwrap();
+        deque.push_back(&mut store, &2).unwrap();
+        deque.push_front(&mut store, &0).unwrap();
+
+        assert_eq!(deque.len(&store).unwrap(), 3);
+        assert_eq!(deque.front(&store).unwrap(), Some(0));
+        assert_eq!(deque.back(&store).unwrap(), Some(2));
+
+        assert_eq!(deque.pop_front(&mut store).unwrap(), Some(0));
+        assert_eq!(deque.pop_front(&mut store).unwrap(), Some(1));
+        assert_eq!(deque.pop_front(&mut store).unwrap(), Some(2));
+        assert_eq!(deque.pop_front(&mut store).unwrap(), None);
+        assert!(deque.is_empty(&store).unwrap());
+    }
+
+    #[test]
+    fn wrapping() {
+        let mut store = MockStorage::new();
+        let deque: Deque<u32> = Deque::new("test");
+
+        // push to the front, so the head wraps around
+        deque.push_front(&mut store, &2).unwrap();
+        deque.push_front(&mut store, &1).unwrap();
+        deque.push_back(&mut store, &3).unwrap();
+        assert_eq!(deque.len(&store).unwrap(), 3);
+
+        let all: StdResult<Vec<_>> = deque.iter(&store).unwrap().collect();
+        assert_eq!(all.unwrap(), vec![1, 2, 3]);
+
+        // pop everything from the back, crossing the wrap again
+        assert_eq!(deque.pop_back(&mut store).unwrap(), Some(3));
+        assert_eq!(deque.pop_back(&mut store).unwrap(), Some(2));
+        assert_eq!(deque.pop_back(&mut store).unwrap(), Some(1));
+        assert_eq!(deque.pop_back(&mut store).unwrap(), None);
+        assert_eq!(deque.len(&store).unwrap(), 0);
+    }
+
+    #[test]
+    fn index_access() {
+        let mut store = MockStorage::new();
+
+        let alice = Data {
+            name: "Alice".to_string(),
+            validator: "val1".to_string(),
+        };
+        let bob = Data {
+            name: "Bob".to_string(),
+            validator: "val2".to_string(),
+        };
+        DATA.push_back(&mut store, &alice).unwrap();
+        DATA.push_front(&mut store, &bob).unwrap();
+
+        assert_eq!(DATA.get(&store, 0).unwrap(), Some(bob));
+        assert_eq!(DATA.get(&store, 1).unwrap(), Some(alice));
+        // out of bounds
+        assert_eq!(DATA.get(&store, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn iterator() {
+        let mut store = MockStorage::new();
+        let deque: Deque<u32> = Deque::new("test");
+
+        for i in 1..=4 {
+            deque.push_back(&mut store, &i).unwrap();
+        }
+
+        let mut iter = deque.iter(&store).unwrap();
+        assert_eq!(iter.size_hint(), (4, Some(4)));
+        assert_eq!(iter.next().unwrap().unwrap(), 1);
+        assert_eq!(iter.next_back().unwrap().unwrap(), 4);
+        assert_eq!(iter.next().unwrap().unwrap(), 2);
+        assert_eq!(iter.next_back().unwrap().unwrap(), 3);
+        assert!(iter.next().is_none());
+        assert!(iter.next_back().is_none());
+
+        let reversed: StdResult<Vec<_>> = deque.iter(&store).unwrap().rev().collect();
+        assert_eq!(reversed.unwrap(), vec![4, 3, 2, 1]);
+    }
+
+    #[test]
+    fn iterator_errors_on_missing_item() {
+        let mut store = MockStorage::new();
+        let deque: Deque<u32> = Deque::new("test");
+
+        deque.push_back(&mut store, &1).unwrap();
+        deque.push_back(&mut store, &2).unwrap();
+        // corrupt the deque by removing the first item directly
+        deque.remove_unchecked(&mut store, 0);
+
+        let mut iter = deque.iter(&store).unwrap();
+        assert!(matches!(
+            iter.next().unwrap().unwrap_err(),
+            StdError::NotFound { .. }
+        ));
+        assert_eq!(iter.next().unwrap().unwrap(), 2);
+    }
+}
@@ -17,6 +17,18 @@ pub trait Endian: Sized + Copy {
 
     fn from_le_bytes(bytes: Self::Buf) -> Self;
     fn from_be_bytes(bytes: Self::Buf) -> Self;
+
+    /// Big endian bytes that sort in the same order as the numbers themselves.
+    /// This is `to_be_bytes` for unsigned ints, signed ints also flip the sign bit,
+    /// so negative numbers sort before positive ones. Used to encode int keys.
+    fn to_sortable_bytes(self) -> Self::Buf {
+        self.to_be_bytes()
+    }
+
+    /// Inverse of `to_sortable_bytes`
+    fn from_sortable_bytes(bytes: Self::Buf) -> Self {
+        Self::from_be_bytes(bytes)
+    }
 }
 
 macro_rules! delegate {
@@ -51,8 +63,67 @@ macro_rules! impl_primitives {
     };
 }
 
+macro_rules! impl_signed_primitives {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl Endian for $ty {
+                type Buf = [u8; mem::size_of::<$ty>()];
+
+                delegate!($ty, [
+                    to_le_bytes,
+                    to_be_bytes,
+                ], (self: Self) -> Self::Buf);
+
+                delegate!($ty, [
+                    from_le_bytes,
+                    from_be_bytes,
+                ], (bytes: Self::Buf) -> Self);
+
+                // MIN only has the sign bit set, so xor flips it
+                #[inline]
+                fn to_sortable_bytes(self) -> Self::Buf {
+                    (self ^ <$ty>::MIN).to_be_bytes()
+                }
+
+                #[inline]
+                fn from_sortable_bytes(bytes: Self::Buf) -> Self {
+                    <$ty>::from_be_bytes(bytes) ^ <$ty>::MIN
+                }
+            }
+        )*
+    };
+}
+
 #[rustfmt::skip]
 impl_primitives![
-    i8, i16, i32, i64, i128,
     u8, u16, u32, u64, u128,
 ];
+
+#[rustfmt::skip]
+impl_signed_primitives![
+    i8, i16, i32, i64, i128,
+];
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn unsigned_sortable_bytes_are_big_endian() {
+        assert_eq!(258u16.to_sortable_bytes(), 258u16.to_be_bytes());
+        assert_eq!(u64::from_sortable_bytes(77u64.to_be_bytes()), 77);
+    }
+
+    #[test]
+    fn signed_sortable_bytes_flip_sign_bit() {
+        assert_eq!(i8::MIN.to_sortable_bytes(), [0x00]);
+        assert_eq!((-1i8).to_sortable_bytes(), [0x7f]);
+        assert_eq!(0i8.to_sortable_bytes(), [0x80]);
+        assert_eq!(i8::MAX.to_sortable_bytes(), [0xff]);
+        assert_eq!((-2i32).to_sortable_bytes(), [0x7f, 0xff, 0xff, 0xfe]);
+
+        for v in &[i64::MIN, -300, -1, 0, 1, 300, i64::MAX] {
+            assert_eq!(*v, i64::from_sortable_bytes(v.to_sortable_bytes()));
+        }
+    }
+}
@@ -0,0 +1,450 @@
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{BlockInfo, StdError, StdResult, Storage};
+#[cfg(feature = "iterator")]
+use cosmwasm_std::{Order, KV};
+
+use crate::helpers::namespaces_with_key;
+#[cfg(feature = "iterator")]
+use crate::keys::EmptyPrefix;
+use crate::keys::PrimaryKey;
+use crate::map::Map;
+#[cfg(feature = "iterator")]
+use crate::prefix::{range_with_prefix, Bound, Prefix};
+use crate::registry::{DescribeStorage, StorageKey};
+
+/// The kinds of expiration in the index, `Never` is not indexed
+const AT_HEIGHT: u8 = 0;
+const AT_TIME: u8 = 1;
+
+/// Expiry is the expiration of an entry in an ExpiringMap. It is stored just like
+/// `cw0::Expiration`, so either one can be used to read the other.
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, JsonSchema, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Expiry {
+    /// AtHeight will expire when `env.block.height` >= height
+    AtHeight(u64),
+    /// AtTime will expire when `env.block.time` >= time
+    AtTime(u64),
+    /// Never will never expire
+    Never {},
+}
+
+impl Expiry {
+    pub fn is_expired(&self, block: &BlockInfo) -> bool {
+        match self {
+            Expiry::AtHeight(height) => block.height >= *height,
+            Expiry::AtTime(time) => block.time >= *time,
+            Expiry::Never {} => false,
+        }
+    }
+}
+
+/// Expiring is the value stored in an ExpiringMap, along with its expiration
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct Expiring<T> {
+    pub value: T,
+    pub expires: Expiry,
+}
+
+/// ExpiringMap stores an `Expiry` with every entry, eg. for allowances or approvals.
+/// Expired entries are hidden from `load` and `range` given the current block, and are
+/// removed in bounded batches by `evict_expired`, using an index ordered by expiration.
+pub struct ExpiringMap<'a, K, T> {
+    namespace: &'a [u8],
+    map: Map<'a, K, Expiring<T>>,
+    // (kind, at, pk) -> pk
+    expirations_namespace: &'a [u8],
+}
+
+impl<'a, K, T> ExpiringMap<'a, K, T> {
+    pub const fn new(namespace: &'a str, expirations_namespace: &'a str) -> Self {
+        ExpiringMap {
+            namespace: namespace.as_bytes(),
+            map: Map::new(namespace),
+            expirations_namespace: expirations_namespace.as_bytes(),
+        }
+    }
+}
+
+impl<'a, K, T> ExpiringMap<'a, K, T>
+where
+    T: Serialize + DeserializeOwned,
+    K: PrimaryKey<'a>,
+{
+    /// save stores the value until it expires. This replaces any previous value and expiration.
+    pub fn save(&self, store: &mut dyn Storage, k: K, data: &T, expires: Expiry) -> StdResult<()>
+    where
+        T: Clone,
+    {
+        let entry = Expiring {
+            value: data.clone(),
+            expires,
+        };
+        self.save_entry(store, k, &entry)
+    }
+
+    fn save_entry(&self, store: &mut dyn Storage, k: K, entry: &Expiring<T>) -> StdResult<()> {
+        let pk = k.joined_key();
+        if let Some(old) = self.map.may_load(store, k.clone())? {
+            self.remove_expiration(store, &old.expires, &pk);
+        }
+        if let Some(key) = self.expiration_key(&entry.expires, &pk) {
+            store.set(&key, &pk);
+        }
+        self.map.save(store, k, entry)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, k: K) -> StdResult<()> {
+        if let Some(old) = self.map.may_load(store, k.clone())? {
+            self.remove_expiration(store, &old.expires, &k.joined_key());
+        }
+        self.map.remove(store, k);
+        Ok(())
+    }
+
+    /// load will return an error if no data is set at the given key, or if it is expired
+    pub fn load(&self, store: &dyn Storage, block: &BlockInfo, k: K) -> StdResult<T> {
+        self.may_load(store, block, k)?
+            .ok_or_else(|| StdError::not_found(std::any::type_name::<T>()))
+    }
+
+    /// may_load returns Ok(None) if no data is set at the given key, or if it is expired
+    pub fn may_load(&self, store: &dyn Storage, block: &BlockInfo, k: K) -> StdResult<Option<T>> {
+        Ok(self.may_load_entry(store, block, k)?.map(|e| e.value))
+    }
+
+    /// Like `may_load`, but also returns the expiration of the value
+    pub fn may_load_entry(
+        &self,
+        store: &dyn Storage,
+        block: &BlockInfo,
+        k: K,
+    ) -> StdResult<Option<Expiring<T>>> {
+        let entry = self.map.may_load(store, k)?;
+        Ok(entry.filter(|e| !e.expires.is_expired(block)))
+    }
+
+    /// Loads the entry (None if missing or expired), performs the specified action,
+    /// and stores the result, which may change the expiration
+    pub fn update<A, E>(
+        &self,
+        store: &mut dyn Storage,
+        block: &BlockInfo,
+        k: K,
+        action: A,
+    ) -> Result<Expiring<T>, E>
+    where
+        A: FnOnce(Option<Expiring<T>>) -> Result<Expiring<T>, E>,
+        E: From<StdError>,
+    {
+        let input = self.may_load_entry(store, block, k.clone())?;
+        let output = action(input)?;
+        self.save_entry(store, k, &output)?;
+        Ok(output)
+    }
+
+    fn expiration_key(&self, expires: &Expiry, pk: &[u8]) -> Option<Vec<u8>> {
+        let (kind, at) = match expires {
+            Expiry::AtHeight(height) => (AT_HEIGHT, *height),
+            Expiry::AtTime(time) => (AT_TIME, *time),
+            Expiry::Never {} => return None,
+        };
+        let mut key = vec![kind];
+        key.extend_from_slice(&at.to_be_bytes());
+        key.extend_from_slice(pk);
+        Some(namespaces_with_key(&[self.expirations_namespace], &key))
+    }
+
+    fn remove_expiration(&self, store: &mut dyn Storage, expires: &Expiry, pk: &[u8]) {
+        if let Some(key) = self.expiration_key(expires, pk) {
+            store.remove(&key);
+        }
+    }
+}
+
+#[cfg(feature = "iterator")]
+impl<'a, K, T> ExpiringMap<'a, K, T>
+where
+    T: Serialize + DeserializeOwned,
+    K: PrimaryKey<'a>,
+{
+    /// Iterates over the entries under the prefix which are not expired at the given block
+    pub fn range_prefix<'c>(
+        &self,
+        store: &'c dyn Storage,
+        block: &BlockInfo,
+        p: K::Prefix,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<KV<Expiring<T>>>> + 'c>
+    where
+        T: 'c,
+    {
+        unexpired(self.map.prefix(p), store, block, min, max, order)
+    }
+
+    /// Removes up to limit entries which are expired at the given block. Heights and times can't
+    /// be compared, so this is not in the order they expired: all entries expired by height are
+    /// removed first (lowest height first), then all entries expired by time (earliest first).
+    /// Returns true if there may be more to remove, call it again (eg. in the next block)
+    /// until it returns false. A limit of 0 removes nothing and returns false.
+    pub fn evict_expired(&self, store: &mut dyn Storage, block: &BlockInfo, limit: usize) -> bool {
+        if limit == 0 {
+            return false;
+        }
+        let mut evicted = 0;
+        for (kind, now) in &[(AT_HEIGHT, block.height), (AT_TIME, block.time)] {
+            let prefix = namespaces_with_key(&[self.expirations_namespace], &[*kind]);
+            // entries expire when now >= at
+            let end = now
+                .checked_add(1)
+                .map(|end| Bound::Exclusive(end.to_be_bytes().to_vec()));
+            let expired: Vec<_> = range_with_prefix(store, &prefix, None, end, Order::Ascending)
+                .take(limit - evicted)
+                .collect();
+            for (key, pk) in expired {
+                store.remove(&[prefix.as_slice(), &key].concat());
+                store.remove(&namespaces_with_key(&[self.namespace], &pk));
+                evicted += 1;
+            }
+            if evicted == limit {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+// short-cut for simple keys, rather than .range_prefix(store, block, (), ...)
+#[cfg(feature = "iterator")]
+impl<'a, K, T> ExpiringMap<'a, K, T>
+where
+    T: Serialize + DeserializeOwned,
+    K: PrimaryKey<'a>,
+    K::Prefix: EmptyPrefix,
+{
+    /// Iterates over all entries which are not expired at the given block
+    pub fn range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        block: &BlockInfo,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<KV<Expiring<T>>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.range_prefix(store, block, K::Prefix::new(), min, max, order)
+    }
+}
+
+#[cfg(feature = "iterator")]
+fn unexpired<'c, T, S>(
+    prefix: Prefix<Expiring<T>, S>,
+    store: &'c dyn Storage,
+    block: &BlockInfo,
+    min: Option<Bound>,
+    max: Option<Bound>,
+    order: Order,
+) -> Box<dyn Iterator<Item = StdResult<KV<Expiring<T>>>> + 'c>
+where
+    T: Serialize + DeserializeOwned + 'c,
+{
+    let block = block.clone();
+    let iter = prefix
+        .range(store, min, max, order)
+        .filter(move |item| match item {
+            Ok((_, entry)) => !entry.expires.is_expired(&block),
+            Err(_) => true,
+        });
+    Box::new(iter)
+}
+
+impl<'a, K, T> DescribeStorage for ExpiringMap<'a, K, T> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        vec![
+            StorageKey::namespace(self.namespace),
+            StorageKey::namespace(self.expirations_namespace),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::{mock_env, MockStorage};
+    use cosmwasm_std::to_vec;
+
+    const APPROVALS: ExpiringMap<(&[u8], &[u8]), u64> =
+        ExpiringMap::new("approvals", "approvals__expirations");
+
+    fn block(height: u64, time: u64) -> BlockInfo {
+        let mut block = mock_env().block;
+        block.height = height;
+        block.time = time;
+        block
+    }
+
+    #[test]
+    fn expired_values_are_hidden() {
+        let mut store = MockStorage::new();
+        let now = block(100, 1000);
+        APPROVALS
+            .save(&mut store, (b"owner", b"alice"), &5, Expiry::AtHeight(110))
+            .unwrap();
+        APPROVALS
+            .save(&mut store, (b"owner", b"bob"), &7, Expiry::Never {})
+            .unwrap();
+
+        assert_eq!(
+            APPROVALS.load(&store, &now, (b"owner", b"alice")).unwrap(),
+            5
+        );
+        let later = block(110, 1050);
+        assert_eq!(
+            APPROVALS
+                .may_load(&store, &later, (b"owner", b"alice"))
+                .unwrap(),
+            None
+        );
+        APPROVALS
+            .load(&store, &later, (b"owner", b"alice"))
+            .unwrap_err();
+        assert_eq!(
+            APPROVALS
+                .may_load_entry(&store, &later, (b"owner", b"bob"))
+                .unwrap(),
+            Some(Expiring {
+                value: 7,
+                expires: Expiry::Never {}
+            })
+        );
+
+        // an expired entry is updated like a missing one
+        let renewed = APPROVALS
+            .update(
+                &mut store,
+                &later,
+                (b"owner", b"alice"),
+                |e| -> StdResult<_> {
+                    assert_eq!(e, None);
+                    Ok(Expiring {
+                        value: 1,
+                        expires: Expiry::AtTime(2000),
+                    })
+                },
+            )
+            .unwrap();
+        assert_eq!(renewed.value, 1);
+        assert_eq!(
+            APPROVALS
+                .load(&store, &later, (b"owner", b"alice"))
+                .unwrap(),
+            1
+        );
+
+        APPROVALS.remove(&mut store, (b"owner", b"alice")).unwrap();
+        assert_eq!(
+            APPROVALS
+                .may_load(&store, &now, (b"owner", b"alice"))
+                .unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn expiry_is_stored_like_cw0_expiration() {
+        assert_eq!(to_vec(&Expiry::AtHeight(5)).unwrap(), br#"{"at_height":5}"#);
+        assert_eq!(to_vec(&Expiry::AtTime(7)).unwrap(), br#"{"at_time":7}"#);
+        assert_eq!(to_vec(&Expiry::Never {}).unwrap(), br#"{"never":{}}"#);
+
+        let now = block(100, 1000);
+        assert!(Expiry::AtHeight(100).is_expired(&now));
+        assert!(!Expiry::AtHeight(101).is_expired(&now));
+        assert!(Expiry::AtTime(1000).is_expired(&now));
+        assert!(!Expiry::AtTime(1001).is_expired(&now));
+        assert!(!Expiry::Never {}.is_expired(&now));
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_skips_expired() {
+        let mut store = MockStorage::new();
+        let spenders: &[(&[u8], Expiry)] = &[
+            (b"anna", Expiry::AtHeight(105)),
+            (b"bert", Expiry::AtTime(1020)),
+            (b"carl", Expiry::Never {}),
+            (b"dora", Expiry::AtHeight(200)),
+        ];
+        for (i, (spender, expires)) in spenders.iter().enumerate() {
+            APPROVALS
+                .save(&mut store, (b"owner", spender), &(i as u64), *expires)
+                .unwrap();
+        }
+        APPROVALS
+            .save(&mut store, (b"other", b"anna"), &9, Expiry::Never {})
+            .unwrap();
+
+        let spenders_at = |now: &BlockInfo| -> Vec<Vec<u8>> {
+            APPROVALS
+                .range_prefix(&store, now, b"owner", None, None, Order::Ascending)
+                .map(|item| item.unwrap().0)
+                .collect()
+        };
+        assert_eq!(spenders_at(&block(100, 1000)).len(), 4);
+        assert_eq!(
+            spenders_at(&block(105, 1010)),
+            vec![b"bert".to_vec(), b"carl".to_vec(), b"dora".to_vec()]
+        );
+        assert_eq!(
+            spenders_at(&block(106, 1020)),
+            vec![b"carl".to_vec(), b"dora".to_vec()]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn evict_in_batches() {
+        let mut store = MockStorage::new();
+        let owners: &[&[u8]] = &[b"a", b"b", b"c", b"d", b"e"];
+        for (i, owner) in owners.iter().enumerate() {
+            let expires = match i % 2 {
+                0 => Expiry::AtHeight(100 + i as u64),
+                _ => Expiry::AtTime(1000 + i as u64),
+            };
+            APPROVALS
+                .save(&mut store, (owner, b"spender"), &1, expires)
+                .unwrap();
+        }
+        // this one was expiring, but got extended
+        APPROVALS
+            .save(&mut store, (b"a", b"spender"), &2, Expiry::Never {})
+            .unwrap();
+
+        // nothing expired yet
+        let now = block(100, 1000);
+        assert!(!APPROVALS.evict_expired(&mut store, &now, 10));
+        assert!(!APPROVALS.evict_expired(&mut store, &now, 0));
+
+        // c (height 102), e (height 104), b (time 1001) and d (time 1003) expired
+        let now = block(104, 1003);
+        assert!(APPROVALS.evict_expired(&mut store, &now, 3));
+        assert!(!APPROVALS.evict_expired(&mut store, &now, 3));
+        let left: Vec<_> = APPROVALS
+            .map
+            .no_prefix()
+            .keys(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(left.len(), 1);
+        assert_eq!(APPROVALS.load(&store, &now, (b"a", b"spender")).unwrap(), 2);
+
+        // the index is empty after all expiring entries are removed
+        APPROVALS.remove(&mut store, (b"a", b"spender")).unwrap();
+        assert_eq!(store.range(None, None, Order::Ascending).count(), 0);
+    }
+}
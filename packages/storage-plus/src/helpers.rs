@@ -9,6 +9,8 @@ use std::any::type_name;
 
 use cosmwasm_std::{from_slice, StdError, StdResult};
 
+use crate::serializer::Serializer;
+
 /// may_deserialize parses json bytes from storage (Option), returning Ok(None) if no data present
 ///
 /// value is an odd type, but this is meant to be easy to use with output from storage.get (Option<Vec<u8>>)
@@ -22,10 +24,21 @@ pub(crate) fn may_deserialize<T: DeserializeOwned>(
     }
 }
 
-/// must_deserialize parses json bytes from storage (Option), returning NotFound error if no data present
-pub(crate) fn must_deserialize<T: DeserializeOwned>(value: &Option<Vec<u8>>) -> StdResult<T> {
+/// may_deserialize_with works like may_deserialize, but parses the bytes with the given Serializer
+pub(crate) fn may_deserialize_with<T, S: Serializer<T>>(
+    value: &Option<Vec<u8>>,
+) -> StdResult<Option<T>> {
+    match value {
+        Some(vec) => Ok(Some(S::from_slice(vec)?)),
+        None => Ok(None),
+    }
+}
+
+/// must_deserialize_with parses the bytes from storage (Option) with the given Serializer,
+/// returning NotFound error if no data present
+pub(crate) fn must_deserialize_with<T, S: Serializer<T>>(value: &Option<Vec<u8>>) -> StdResult<T> {
     match value {
-        Some(vec) => from_slice(&vec),
+        Some(vec) => S::from_slice(vec),
         None => Err(StdError::not_found(type_name::<T>())),
     }
 }
@@ -93,6 +106,7 @@ pub(crate) fn decode_length(prefix: &[u8]) -> usize {
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::serializer::Json;
     use cosmwasm_std::{to_vec, StdError};
     use serde::{Deserialize, Serialize};
 
@@ -147,13 +161,13 @@ mod test {
         let value = to_vec(&person).unwrap();
         let loaded = Some(value);
 
-        let parsed: Person = must_deserialize(&loaded).unwrap();
+        let parsed: Person = must_deserialize_with::<_, Json>(&loaded).unwrap();
         assert_eq!(parsed, person);
     }
 
     #[test]
     fn must_deserialize_handles_none() {
-        let parsed = must_deserialize::<Person>(&None);
+        let parsed = must_deserialize_with::<Person, Json>(&None);
         match parsed.unwrap_err() {
             StdError::NotFound { kind, .. } => {
                 assert_eq!(kind, "cw_storage_plus::helpers::test::Person")
@@ -1,14 +1,18 @@
 // this module requires iterator to be useful at all
 #![cfg(feature = "iterator")]
 
-use cosmwasm_std::{StdError, StdResult, Storage};
+use cosmwasm_std::{Order, StdError, StdResult, Storage};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 
+use crate::de::KeyDeserialize;
+use crate::helpers::{namespaces_with_key, nested_namespaces_with_key};
 use crate::indexes::Index;
+use crate::iter_helpers::concat;
 use crate::keys::{EmptyPrefix, Prefixer, PrimaryKey};
 use crate::map::Map;
-use crate::prefix::{Bound, Prefix};
+use crate::prefix::{Bound, DeserializedKV, Prefix};
+use crate::registry::{DescribeStorage, StorageKey};
 
 pub trait IndexList<T> {
     fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<T>> + '_>;
@@ -122,13 +126,59 @@ where
         self.primary.may_load(store, key)
     }
 
+    /// Returns true if the map doesn't contain any entries
+    pub fn is_empty(&self, store: &dyn Storage) -> bool {
+        self.primary.is_empty(store)
+    }
+
+    /// Removes all entries of the map, at most limit of them if given,
+    /// along with their index entries. Returns true if there are entries left to remove.
+    pub fn clear(&self, store: &mut dyn Storage, limit: Option<usize>) -> StdResult<bool> {
+        self.remove_under(store, &[], limit)
+    }
+
+    /// Removes the entries under the given prefix, at most limit of them if given,
+    /// along with their index entries. This allows to bound the work done in one call.
+    /// Returns true if there are entries left to remove.
+    pub fn clear_prefix(
+        &self,
+        store: &mut dyn Storage,
+        p: K::Prefix,
+        limit: Option<usize>,
+    ) -> StdResult<bool> {
+        self.remove_under(store, &p.prefix(), limit)
+    }
+
+    fn remove_under(
+        &self,
+        store: &mut dyn Storage,
+        prefix: &[&[u8]],
+        limit: Option<usize>,
+    ) -> StdResult<bool> {
+        let entries: Vec<_> = Prefix::<T>::new(self.pk_namespace, prefix)
+            .range(store, None, None, Order::Ascending)
+            .take(limit.unwrap_or(usize::MAX))
+            .collect::<StdResult<_>>()?;
+        // the keys are relative to the prefix, while indexes refer to the full primary key
+        let pk_prefix = nested_namespaces_with_key(&[], prefix, b"");
+        for (k, old) in entries {
+            let pk = concat(&pk_prefix, &k);
+            for index in self.idx.get_indexes() {
+                index.remove(store, &pk, &old)?;
+            }
+            store.remove(&namespaces_with_key(&[self.pk_namespace], &pk));
+        }
+        Ok(!Prefix::<T>::new(self.pk_namespace, prefix).is_empty(store))
+    }
+
     // use prefix to scan -> range
-    pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
+    // to remove the entries under a prefix, use clear_prefix, which also removes the index entries
+    pub fn prefix(&self, p: K::Prefix) -> Prefix<T, K::Suffix> {
         Prefix::new(self.pk_namespace, &p.prefix())
     }
 
     // use sub_prefix to scan -> range
-    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T> {
+    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T, K::SuperSuffix> {
         Prefix::new(self.pk_namespace, &p.prefix())
     }
 }
@@ -155,14 +205,61 @@ where
     {
         self.prefix(K::Prefix::new()).range(store, min, max, order)
     }
+
+    /// Like `range`, but deserializes the keys into the key type
+    pub fn range_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<DeserializedKV<K::Suffix, T>>> + 'c>
+    where
+        T: 'c,
+        <K::Suffix as KeyDeserialize>::Output: 'c,
+    {
+        self.primary.range_de(store, min, max, order)
+    }
+
+    /// Deserialized keys of the primary map, without loading the values
+    pub fn keys_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<<K::Suffix as KeyDeserialize>::Output>> + 'c>
+    where
+        T: 'c,
+        <K::Suffix as KeyDeserialize>::Output: 'c,
+    {
+        self.primary.keys_de(store, min, max, order)
+    }
+}
+
+impl<'a, K, T, I> DescribeStorage for IndexedMap<'a, K, T, I>
+where
+    K: PrimaryKey<'a>,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    /// the primary namespace, followed by those of all indexes
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        let mut keys = self.primary.storage_keys();
+        for index in self.idx.get_indexes() {
+            keys.extend(index.storage_keys());
+        }
+        keys
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
-    use crate::indexes::{index_string_tuple, index_triple, MultiIndex, UniqueIndex};
-    use crate::{PkOwned, U32Key};
+    use crate::indexes::{index_string, index_string_tuple, MultiIndex, UniqueIndex};
+    use crate::prefix::PrefixBound;
+    use crate::{PkOwned, StorageRegistry, U32Key};
     use cosmwasm_std::testing::MockStorage;
     use cosmwasm_std::{MemoryStorage, Order};
     use serde::{Deserialize, Serialize};
@@ -175,8 +272,7 @@ mod test {
     }
 
     struct DataIndexes<'a> {
-        // Second arg is for storing pk
-        pub name: MultiIndex<'a, (PkOwned, PkOwned), Data>,
+        pub name: MultiIndex<'a, PkOwned, Data>,
         pub age: UniqueIndex<'a, U32Key, Data>,
         pub name_lastname: UniqueIndex<'a, (PkOwned, PkOwned), Data>,
     }
@@ -191,8 +287,7 @@ mod test {
 
     // For composite multi index tests
     struct DataCompositeMultiIndex<'a> {
-        // Third arg needed for storing pk
-        pub name_age: MultiIndex<'a, (PkOwned, U32Key, PkOwned), Data>,
+        pub name_age: MultiIndex<'a, (PkOwned, U32Key), Data>,
     }
 
     // Future Note: this can likely be macro-derived
@@ -203,14 +298,24 @@ mod test {
         }
     }
 
+    // For typed primary key tests
+    struct DataTypedPkIndexes<'a> {
+        pub name: MultiIndex<'a, PkOwned, Data, String>,
+        pub age: UniqueIndex<'a, U32Key, Data, String>,
+    }
+
+    // Future Note: this can likely be macro-derived
+    impl<'a> IndexList<Data> for DataTypedPkIndexes<'a> {
+        fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Data>> + '_> {
+            let v: Vec<&dyn Index<Data>> = vec![&self.name, &self.age];
+            Box::new(v.into_iter())
+        }
+    }
+
     // Can we make it easier to define this? (less wordy generic)
     fn build_map<'a>() -> IndexedMap<'a, &'a [u8], Data, DataIndexes<'a>> {
         let indexes = DataIndexes {
-            name: MultiIndex::new(
-                |d, k| (PkOwned(d.name.as_bytes().to_vec()), PkOwned(k)),
-                "data",
-                "data__name",
-            ),
+            name: MultiIndex::new(|d| index_string(&d.name), "data", "data__name"),
             age: UniqueIndex::new(|d| U32Key::new(d.age), "data__age"),
             name_lastname: UniqueIndex::new(
                 |d| index_string_tuple(&d.name, &d.last_name),
@@ -414,7 +519,7 @@ mod test {
 
         let indexes = DataCompositeMultiIndex {
             name_age: MultiIndex::new(
-                |d, k| index_triple(&d.name, d.age, k),
+                |d| (index_string(&d.name), U32Key::new(d.age)),
                 "data",
                 "data__name_age",
             ),
@@ -473,6 +578,250 @@ mod test {
         assert_eq!(data3, marias[1].1);
     }
 
+    #[test]
+    fn range_de_by_indexes() {
+        let mut store = MockStorage::new();
+
+        let indexes = DataTypedPkIndexes {
+            name: MultiIndex::new(|d| index_string(&d.name), "data", "data__name"),
+            age: UniqueIndex::new(|d| U32Key::new(d.age), "data__age"),
+        };
+        let map: IndexedMap<&str, Data, DataTypedPkIndexes> = IndexedMap::new("data", indexes);
+
+        let maria = Data {
+            name: "Maria".to_string(),
+            last_name: "Doe".to_string(),
+            age: 42,
+        };
+        map.save(&mut store, "maria", &maria).unwrap();
+        let john = Data {
+            name: "John".to_string(),
+            last_name: "Wayne".to_string(),
+            age: 32,
+        };
+        map.save(&mut store, "john", &john).unwrap();
+        let maria2 = Data {
+            name: "Maria".to_string(),
+            last_name: "Williams".to_string(),
+            age: 23,
+        };
+        map.save(&mut store, "maria2", &maria2).unwrap();
+
+        // primary keys come back as strings from the main map
+        let keys: Vec<_> = map
+            .keys_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(keys, vec!["john", "maria", "maria2"]);
+        let all: Vec<_> = map
+            .range_de(&store, None, None, Order::Descending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(all[0], ("maria2".to_string(), maria2.clone()));
+
+        // as well as from the multi index
+        let marias: Vec<_> = map
+            .idx
+            .name
+            .prefix(PkOwned(b"Maria".to_vec()))
+            .range_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(
+            marias,
+            vec![
+                ("maria".to_string(), maria.clone()),
+                ("maria2".to_string(), maria2.clone())
+            ]
+        );
+
+        // and the unique index
+        let by_age: Vec<_> = map
+            .idx
+            .age
+            .range_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(
+            by_age,
+            vec![
+                ("maria2".to_string(), maria2),
+                ("john".to_string(), john),
+                ("maria".to_string(), maria)
+            ]
+        );
+    }
+
+    #[test]
+    fn prefix_range_by_multi_index() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+        save_data(&mut store, &map);
+
+        let pks = |min, max| -> Vec<Vec<u8>> {
+            map.idx
+                .name
+                .prefix_range(&store, min, max, Order::Ascending)
+                .map(|r| r.map(|(pk, _)| pk))
+                .collect::<StdResult<_>>()
+                .unwrap()
+        };
+
+        // names are length-prefixed: "John" < "Maria" < "Maria Luisa"
+        assert_eq!(
+            pks(None, None),
+            vec![b"3".to_vec(), b"1".to_vec(), b"2".to_vec(), b"4".to_vec()]
+        );
+        assert_eq!(
+            pks(
+                Some(PrefixBound::inclusive(index_string("John"))),
+                Some(PrefixBound::inclusive(index_string("Maria")))
+            ),
+            vec![b"3".to_vec(), b"1".to_vec(), b"2".to_vec()]
+        );
+        assert_eq!(
+            pks(
+                Some(PrefixBound::exclusive(index_string("John"))),
+                Some(PrefixBound::exclusive(index_string("Maria Luisa")))
+            ),
+            vec![b"1".to_vec(), b"2".to_vec()]
+        );
+    }
+
+    #[test]
+    fn clear_removes_index_entries() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+        assert!(map.is_empty(&store));
+
+        let (pks, datas) = save_data(&mut store, &map);
+        assert!(!map.is_empty(&store));
+
+        // bounded removal
+        assert!(map.clear_prefix(&mut store, (), Some(3)).unwrap());
+        assert_eq!(1, map.idx.name.count(&store, index_string("Maria Luisa")));
+        assert_eq!(0, map.idx.name.count(&store, index_string("Maria")));
+        assert_eq!(None, map.idx.age.item(&store, U32Key::new(42)).unwrap());
+        assert!(!map.clear_prefix(&mut store, (), Some(3)).unwrap());
+        assert!(map.is_empty(&store));
+        assert_eq!(0, map.idx.name.count(&store, index_string("Maria Luisa")));
+
+        // the unique indexes are free again
+        save_data(&mut store, &map);
+        assert!(map.clear(&mut store, Some(2)).unwrap());
+        assert!(!map.clear(&mut store, None).unwrap());
+        assert!(map.is_empty(&store));
+        for (pk, data) in pks.iter().zip(datas.iter()) {
+            assert_eq!(None, map.may_load(&store, pk).unwrap());
+            assert_eq!(
+                None,
+                map.idx.age.item(&store, U32Key::new(data.age)).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn clear_prefix_of_composite_key() {
+        let mut store = MockStorage::new();
+
+        let indexes = DataCompositeMultiIndex {
+            name_age: MultiIndex::new(
+                |d| (index_string(&d.name), U32Key::new(d.age)),
+                "data",
+                "data__name_age",
+            ),
+        };
+        let map: IndexedMap<(&[u8], &[u8]), Data, _> = IndexedMap::new("data", indexes);
+
+        let data = Data {
+            name: "Maria".to_string(),
+            last_name: "Doe".to_string(),
+            age: 42,
+        };
+        map.save(&mut store, (b"1", b"a"), &data).unwrap();
+        map.save(&mut store, (b"1", b"b"), &data).unwrap();
+        map.save(&mut store, (b"2", b"a"), &data).unwrap();
+        assert_eq!(
+            3,
+            map.idx
+                .name_age
+                .count(&store, (index_string("Maria"), U32Key::new(42)))
+        );
+
+        assert!(!map.clear_prefix(&mut store, b"1", None).unwrap());
+        assert!(map.prefix(b"1").is_empty(&store));
+        assert_eq!(
+            map.idx
+                .name_age
+                .all_pks(&store, (index_string("Maria"), U32Key::new(42))),
+            vec![(b"2" as &[u8], b"a" as &[u8]).joined_key()]
+        );
+    }
+
+    #[test]
+    fn multi_index_appends_pk() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+        save_data(&mut store, &map);
+
+        // stored as (index value, pk), just like a manually appended pk
+        let raw = namespaces_with_key(&[b"data__name", b"Maria"], b"2");
+        assert_eq!(store.get(&raw), Some(b"1".to_vec()));
+
+        // the whole index, ordered by index value and pk
+        let pks: Vec<_> = map
+            .idx
+            .name
+            .range(&store, None, None, Order::Ascending)
+            .map(|r| r.unwrap().0)
+            .collect();
+        assert_eq!(
+            pks,
+            vec![b"3".to_vec(), b"1".to_vec(), b"2".to_vec(), b"4".to_vec()]
+        );
+    }
+
+    #[test]
+    fn multi_index_on_computed_value() {
+        struct DecadeIndex<'a> {
+            decade: MultiIndex<'a, U32Key, Data>,
+        }
+
+        impl<'a> IndexList<Data> for DecadeIndex<'a> {
+            fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Data>> + '_> {
+                let v: Vec<&dyn Index<Data>> = vec![&self.decade];
+                Box::new(v.into_iter())
+            }
+        }
+
+        let mut store = MockStorage::new();
+        let indexes = DecadeIndex {
+            decade: MultiIndex::new(|d| U32Key::new(d.age / 10), "data", "data__decade"),
+        };
+        let map: IndexedMap<&[u8], Data, _> = IndexedMap::new("data", indexes);
+        for (pk, age) in &[(b"1", 42), (b"2", 47), (b"3", 32)] {
+            let data = Data {
+                name: "Maria".to_string(),
+                last_name: "Doe".to_string(),
+                age: *age,
+            };
+            map.save(&mut store, *pk, &data).unwrap();
+        }
+
+        let forties = map.idx.decade.all_pks(&store, U32Key::new(4));
+        assert_eq!(forties, vec![b"1".to_vec(), b"2".to_vec()]);
+
+        // moving to another decade updates the index
+        map.update(&mut store, b"2", |d| -> StdResult<_> {
+            let mut d = d.unwrap();
+            d.age = 51;
+            Ok(d)
+        })
+        .unwrap();
+        assert_eq!(map.idx.decade.count(&store, U32Key::new(4)), 1);
+        assert_eq!(map.idx.decade.count(&store, U32Key::new(5)), 1);
+    }
+
     #[test]
     fn unique_index_enforced() {
         let mut store = MockStorage::new();
@@ -644,4 +993,26 @@ mod test {
         assert_eq!(datas[0], marias[0].1);
         assert_eq!(datas[1], marias[1].1);
     }
+
+    #[test]
+    fn describe_storage_includes_indexes() {
+        let map = build_map();
+        let names: Vec<_> = map.storage_keys().into_iter().map(|k| k.name).collect();
+        assert_eq!(
+            names,
+            vec!["data", "data__name", "data__age", "data__name_lastname"]
+        );
+
+        // an index sharing the namespace of the primary map is caught
+        let indexes = DataCompositeMultiIndex {
+            name_age: MultiIndex::new(
+                |d| (index_string(&d.name), U32Key::new(d.age)),
+                "data",
+                "data",
+            ),
+        };
+        let bad_map: IndexedMap<&[u8], Data, _> = IndexedMap::new("data", indexes);
+        let registry = StorageRegistry::new().register("data", &bad_map);
+        assert_eq!(registry.collisions().len(), 1);
+    }
 }
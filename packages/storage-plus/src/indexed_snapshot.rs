@@ -0,0 +1,400 @@
+// this module requires iterator to be useful at all
+#![cfg(feature = "iterator")]
+
+use cosmwasm_std::{StdError, StdResult, Storage};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use crate::de::KeyDeserialize;
+use crate::indexed_map::IndexList;
+use crate::keys::{EmptyPrefix, Prefixer, PrimaryKey};
+use crate::prefix::{Bound, DeserializedKV, Prefix};
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::snapshot::{SnapshotMap, Strategy};
+
+/// IndexedSnapshotMap works like a SnapshotMap but has a secondary index.
+/// Only the primary data is snapshotted, the indexes always reflect the current state.
+pub struct IndexedSnapshotMap<'a, K, T, I>
+where
+    K: PrimaryKey<'a>,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    pk_namespace: &'a [u8],
+    primary: SnapshotMap<'a, K, T>,
+    /// This is meant to be read directly to get the proper types, like:
+    /// map.idx.owner.items(...)
+    pub idx: I,
+}
+
+impl<'a, K, T, I> IndexedSnapshotMap<'a, K, T, I>
+where
+    K: PrimaryKey<'a>,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    /// Usage: IndexedSnapshotMap::new("data", "data__check", "data__change", Strategy::EveryBlock, indexes)
+    pub fn new(
+        pk_namespace: &'a str,
+        checkpoints: &'a str,
+        changelog: &'a str,
+        strategy: Strategy,
+        indexes: I,
+    ) -> Self {
+        IndexedSnapshotMap {
+            pk_namespace: pk_namespace.as_bytes(),
+            primary: SnapshotMap::new(pk_namespace, checkpoints, changelog, strategy),
+            idx: indexes,
+        }
+    }
+
+    pub fn add_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.primary.add_checkpoint(store, height)
+    }
+
+    pub fn remove_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.primary.remove_checkpoint(store, height)
+    }
+}
+
+impl<'a, K, T, I> IndexedSnapshotMap<'a, K, T, I>
+where
+    K: PrimaryKey<'a> + Prefixer<'a>,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    /// save will serialize the model and store, returns an error on serialization issues.
+    /// this must load the old value to update the indexes properly
+    /// if you loaded the old value earlier in the same function, use replace to avoid needless db reads
+    pub fn save(&self, store: &mut dyn Storage, key: K, data: &T, height: u64) -> StdResult<()> {
+        let old_data = self.may_load(store, key.clone())?;
+        self.replace(store, key, Some(data), old_data.as_ref(), height)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, key: K, height: u64) -> StdResult<()> {
+        let old_data = self.may_load(store, key.clone())?;
+        self.replace(store, key, None, old_data.as_ref(), height)
+    }
+
+    /// replace writes data to key. old_data must be the current stored value (from a previous load)
+    /// and is used to properly update the index. This is used by save, replace, and update
+    /// and can be called directly if you want to optimize
+    pub fn replace(
+        &self,
+        store: &mut dyn Storage,
+        key: K,
+        data: Option<&T>,
+        old_data: Option<&T>,
+        height: u64,
+    ) -> StdResult<()> {
+        // this is the key *relative* to the primary map namespace
+        let pk = key.joined_key();
+        if let Some(old) = old_data {
+            for index in self.idx.get_indexes() {
+                index.remove(store, &pk, old)?;
+            }
+        }
+        if let Some(updated) = data {
+            for index in self.idx.get_indexes() {
+                index.save(store, &pk, updated)?;
+            }
+            self.primary.save(store, key, updated, height)?;
+        } else {
+            self.primary.remove(store, key, height)?;
+        }
+        Ok(())
+    }
+
+    /// Loads the data, perform the specified action, and store the result
+    /// in the database. This is shorthand for some common sequences, which may be useful.
+    ///
+    /// If the data exists, `action(Some(value))` is called. Otherwise `action(None)` is called.
+    pub fn update<A, E>(
+        &self,
+        store: &mut dyn Storage,
+        key: K,
+        height: u64,
+        action: A,
+    ) -> Result<T, E>
+    where
+        A: FnOnce(Option<T>) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let input = self.may_load(store, key.clone())?;
+        let old_val = input.clone();
+        let output = action(input)?;
+        self.replace(store, key, Some(&output), old_val.as_ref(), height)?;
+        Ok(output)
+    }
+
+    // Everything else, that doesn't touch indexers, is just pass-through from self.primary,
+    // thus can be used from while iterating over indexes
+
+    /// load will return an error if no data is set at the given key, or on parse error
+    pub fn load(&self, store: &dyn Storage, key: K) -> StdResult<T> {
+        self.primary.load(store, key)
+    }
+
+    /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
+    /// returns an error on issues parsing
+    pub fn may_load(&self, store: &dyn Storage, key: K) -> StdResult<Option<T>> {
+        self.primary.may_load(store, key)
+    }
+
+    // may_load_at_height reads historical data from given checkpoints.
+    // Only returns `Ok` if we have the data to be able to give the correct answer
+    // (Strategy::EveryBlock or Strategy::Selected and h is registered as checkpoint)
+    //
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn may_load_at_height(
+        &self,
+        store: &dyn Storage,
+        key: K,
+        height: u64,
+    ) -> StdResult<Option<T>> {
+        self.primary.may_load_at_height(store, key, height)
+    }
+
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn assert_checkpointed(&self, store: &dyn Storage, height: u64) -> StdResult<()> {
+        self.primary.assert_checkpointed(store, height)
+    }
+
+    /// Removes the history below before_height, see `SnapshotMap::prune`.
+    /// The indexes only reflect the current state, so they are not affected.
+    pub fn prune(
+        &self,
+        store: &mut dyn Storage,
+        before_height: u64,
+        limit: usize,
+    ) -> StdResult<bool> {
+        self.primary.prune(store, before_height, limit)
+    }
+
+    // use prefix to scan -> range
+    pub fn prefix(&self, p: K::Prefix) -> Prefix<T, K::Suffix> {
+        Prefix::new(self.pk_namespace, &p.prefix())
+    }
+
+    // use sub_prefix to scan -> range
+    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T, K::SuperSuffix> {
+        Prefix::new(self.pk_namespace, &p.prefix())
+    }
+}
+
+// short-cut for simple keys, rather than .prefix(()).range(...)
+impl<'a, K, T, I> IndexedSnapshotMap<'a, K, T, I>
+where
+    K: PrimaryKey<'a> + Prefixer<'a>,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+    K::Prefix: EmptyPrefix,
+{
+    // I would prefer not to copy code from Prefix, but no other way
+    // with lifetimes (create Prefix inside function and return ref = no no)
+    pub fn range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<cosmwasm_std::KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.prefix(K::Prefix::new()).range(store, min, max, order)
+    }
+
+    /// Like `range`, but deserializes the keys into the key type
+    pub fn range_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<DeserializedKV<K::Suffix, T>>> + 'c>
+    where
+        T: 'c,
+        <K::Suffix as KeyDeserialize>::Output: 'c,
+    {
+        self.primary.range_de(store, min, max, order)
+    }
+
+    /// Deserialized keys of the current state, without loading the values
+    pub fn keys_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<<K::Suffix as KeyDeserialize>::Output>> + 'c>
+    where
+        T: 'c,
+        <K::Suffix as KeyDeserialize>::Output: 'c,
+    {
+        self.primary.keys_de(store, min, max, order)
+    }
+}
+
+impl<'a, K, T, I> DescribeStorage for IndexedSnapshotMap<'a, K, T, I>
+where
+    K: PrimaryKey<'a>,
+    T: Serialize + DeserializeOwned + Clone,
+    I: IndexList<T>,
+{
+    /// the namespaces of the snapshotted primary data, followed by those of all indexes
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        let mut keys = self.primary.storage_keys();
+        for index in self.idx.get_indexes() {
+            keys.extend(index.storage_keys());
+        }
+        keys
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    use crate::indexes::{index_string, Index, MultiIndex, UniqueIndex};
+    use crate::{PkOwned, U32Key};
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::Order;
+    use serde::{Deserialize, Serialize};
+
+    #[derive(Serialize, Deserialize, PartialEq, Debug, Clone)]
+    struct Data {
+        pub name: String,
+        pub age: u32,
+    }
+
+    struct DataIndexes<'a> {
+        pub name: MultiIndex<'a, PkOwned, Data, String>,
+        pub age: UniqueIndex<'a, U32Key, Data, String>,
+    }
+
+    // Future Note: this can likely be macro-derived
+    impl<'a> IndexList<Data> for DataIndexes<'a> {
+        fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<Data>> + '_> {
+            let v: Vec<&dyn Index<Data>> = vec![&self.name, &self.age];
+            Box::new(v.into_iter())
+        }
+    }
+
+    fn build_map<'a>() -> IndexedSnapshotMap<'a, &'a str, Data, DataIndexes<'a>> {
+        let indexes = DataIndexes {
+            name: MultiIndex::new(|d| index_string(&d.name), "data", "data__name"),
+            age: UniqueIndex::new(|d| U32Key::new(d.age), "data__age"),
+        };
+        IndexedSnapshotMap::new(
+            "data",
+            "data__check",
+            "data__change",
+            Strategy::EveryBlock,
+            indexes,
+        )
+    }
+
+    fn data(name: &str, age: u32) -> Data {
+        Data {
+            name: name.to_string(),
+            age,
+        }
+    }
+
+    fn names<'a>(
+        map: &IndexedSnapshotMap<'a, &'a str, Data, DataIndexes<'a>>,
+        store: &dyn Storage,
+        name: &str,
+    ) -> Vec<String> {
+        map.idx
+            .name
+            .prefix(PkOwned(name.as_bytes().to_vec()))
+            .keys_de(store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn save_updates_indexes_and_changelog() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        map.save(&mut store, "1", &data("Maria", 42), 1).unwrap();
+        map.save(&mut store, "2", &data("Maria", 23), 2).unwrap();
+        map.save(&mut store, "3", &data("John", 32), 2).unwrap();
+
+        // current state is indexed
+        assert_eq!(names(&map, &store, "Maria"), vec!["1", "2"]);
+        assert_eq!(names(&map, &store, "John"), vec!["3"]);
+        let (pk, _) = map.idx.age.item(&store, U32Key::new(23)).unwrap().unwrap();
+        assert_eq!(b"2".to_vec(), pk);
+
+        // change the name and age in a later block
+        map.update(&mut store, "2", 3, |d| -> StdResult<_> {
+            let d = d.unwrap();
+            Ok(Data {
+                name: "John".to_string(),
+                age: d.age + 1,
+            })
+        })
+        .unwrap();
+
+        // indexes follow the current value
+        assert_eq!(names(&map, &store, "Maria"), vec!["1"]);
+        assert_eq!(names(&map, &store, "John"), vec!["2", "3"]);
+        assert_eq!(None, map.idx.age.item(&store, U32Key::new(23)).unwrap());
+        let (pk, _) = map.idx.age.item(&store, U32Key::new(24)).unwrap().unwrap();
+        assert_eq!(b"2".to_vec(), pk);
+
+        // history is available from the primary map
+        assert_eq!(None, map.may_load_at_height(&store, "2", 2).unwrap());
+        assert_eq!(
+            Some(data("Maria", 23)),
+            map.may_load_at_height(&store, "2", 3).unwrap()
+        );
+        assert_eq!(
+            Some(data("John", 24)),
+            map.may_load_at_height(&store, "2", 4).unwrap()
+        );
+    }
+
+    #[test]
+    fn remove_clears_indexes_and_keeps_history() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        map.save(&mut store, "1", &data("Maria", 42), 1).unwrap();
+        map.save(&mut store, "2", &data("Maria", 23), 1).unwrap();
+        map.remove(&mut store, "1", 5).unwrap();
+
+        assert_eq!(None, map.may_load(&store, "1").unwrap());
+        assert_eq!(names(&map, &store, "Maria"), vec!["2"]);
+        assert_eq!(None, map.idx.age.item(&store, U32Key::new(42)).unwrap());
+
+        // unique index slot is free again
+        map.save(&mut store, "3", &data("John", 42), 6).unwrap();
+
+        assert_eq!(
+            Some(data("Maria", 42)),
+            map.may_load_at_height(&store, "1", 5).unwrap()
+        );
+        assert_eq!(None, map.may_load_at_height(&store, "1", 6).unwrap());
+
+        let keys: Vec<_> = map
+            .keys_de(&store, None, None, Order::Ascending)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(keys, vec!["2".to_string(), "3".to_string()]);
+    }
+
+    #[test]
+    fn unique_index_violation_is_rejected() {
+        let mut store = MockStorage::new();
+        let map = build_map();
+
+        map.save(&mut store, "1", &data("Maria", 42), 1).unwrap();
+        let err = map.save(&mut store, "2", &data("John", 42), 2).unwrap_err();
+        assert!(matches!(err, StdError::GenericErr { .. }));
+    }
+}
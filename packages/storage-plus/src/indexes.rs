@@ -3,33 +3,29 @@
 
 use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::marker::PhantomData;
 
 use cosmwasm_std::{from_slice, Binary, Order, StdError, StdResult, Storage, KV};
 
+use crate::de::KeyDeserialize;
 use crate::helpers::namespaces_with_key;
 use crate::keys::EmptyPrefix;
 use crate::map::Map;
-use crate::{Bound, PkOwned, Prefix, Prefixer, PrimaryKey, U32Key};
+use crate::prefix::{prefixed_range, DeserializedKV, PrefixBound};
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::{Bound, PkOwned, Prefix, Prefixer, PrimaryKey};
 
 pub fn index_string(data: &str) -> PkOwned {
     PkOwned(data.as_bytes().to_vec())
 }
 
-pub fn index_tuple(name: &str, age: u32) -> (PkOwned, U32Key) {
-    (index_string(name), U32Key::new(age))
-}
-
-pub fn index_triple(name: &str, age: u32, pk: Vec<u8>) -> (PkOwned, U32Key, PkOwned) {
-    (index_string(name), U32Key::new(age), PkOwned(pk))
-}
-
 pub fn index_string_tuple(data1: &str, data2: &str) -> (PkOwned, PkOwned) {
     (index_string(data1), index_string(data2))
 }
 
 // Note: we cannot store traits with generic functions inside `Box<dyn Index>`,
 // so I pull S: Storage to a top-level
-pub trait Index<T>
+pub trait Index<T>: DescribeStorage
 where
     T: Serialize + DeserializeOwned + Clone,
 {
@@ -45,31 +41,32 @@ where
 /// The stored pk_len is used to recover the pk from the index namespace, and perform
 /// the secondary load of the associated value from the main map.
 ///
-/// The MultiIndex definition must include a field for the pk. That is, the MultiIndex K value
-/// is always a n-tuple (n >= 2) and its last element must be the pk.
-/// The index function must therefore put the pk as last element, when generating the index.
-pub struct MultiIndex<'a, K, T> {
-    index: fn(&T, Vec<u8>) -> K,
+/// The index function only returns the index value IK, which can be any (composite) key,
+/// eg. `|d| (index_string(&d.name), U32Key::new(d.age))`. The pk is appended to it on save,
+/// so `prefix` takes a full IK, and `sub_prefix` the first part of a composite IK.
+///
+/// PK is the type of the primary key of the main map, used to deserialize the keys
+/// returned by `range_de`. It defaults to the raw bytes.
+pub struct MultiIndex<'a, IK, T, PK = Vec<u8>> {
+    index: fn(&T) -> IK,
     idx_namespace: &'a [u8],
-    idx_map: Map<'a, K, u32>,
+    idx_map: Map<'a, (IK, PkOwned), u32>,
     pk_namespace: &'a [u8],
+    pk_type: PhantomData<PK>,
 }
 
-impl<'a, K, T> MultiIndex<'a, K, T>
+impl<'a, IK, T, PK> MultiIndex<'a, IK, T, PK>
 where
     T: Serialize + DeserializeOwned + Clone,
 {
     // TODO: make this a const fn
-    pub fn new(
-        idx_fn: fn(&T, Vec<u8>) -> K,
-        pk_namespace: &'a str,
-        idx_namespace: &'a str,
-    ) -> Self {
+    pub fn new(idx_fn: fn(&T) -> IK, pk_namespace: &'a str, idx_namespace: &'a str) -> Self {
         MultiIndex {
             index: idx_fn,
             idx_namespace: idx_namespace.as_bytes(),
             idx_map: Map::new(idx_namespace),
             pk_namespace: pk_namespace.as_bytes(),
+            pk_type: PhantomData,
         }
     }
 }
@@ -98,29 +95,36 @@ fn deserialize_multi_kv<T: DeserializeOwned>(
     Ok((pk.into(), v))
 }
 
-impl<'a, K, T> Index<T> for MultiIndex<'a, K, T>
+impl<'a, IK, T, PK> DescribeStorage for MultiIndex<'a, IK, T, PK> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        self.idx_map.storage_keys()
+    }
+}
+
+impl<'a, IK, T, PK> Index<T> for MultiIndex<'a, IK, T, PK>
 where
     T: Serialize + DeserializeOwned + Clone,
-    K: PrimaryKey<'a>,
+    IK: PrimaryKey<'a> + Prefixer<'a>,
 {
     fn save(&self, store: &mut dyn Storage, pk: &[u8], data: &T) -> StdResult<()> {
-        let idx = (self.index)(data, pk.to_vec());
+        let idx = ((self.index)(data), PkOwned(pk.to_vec()));
         self.idx_map.save(store, idx, &(pk.len() as u32))
     }
 
     fn remove(&self, store: &mut dyn Storage, pk: &[u8], old_data: &T) -> StdResult<()> {
-        let idx = (self.index)(old_data, pk.to_vec());
+        let idx = ((self.index)(old_data), PkOwned(pk.to_vec()));
         self.idx_map.remove(store, idx);
         Ok(())
     }
 }
 
-impl<'a, K, T> MultiIndex<'a, K, T>
+impl<'a, IK, T, PK> MultiIndex<'a, IK, T, PK>
 where
     T: Serialize + DeserializeOwned + Clone,
-    K: PrimaryKey<'a>,
+    IK: PrimaryKey<'a> + Prefixer<'a>,
 {
-    pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
+    /// All items with the given index value
+    pub fn prefix(&self, p: IK) -> Prefix<T, PK> {
         Prefix::with_deserialization_function(
             self.idx_namespace,
             &p.prefix(),
@@ -129,7 +133,8 @@ where
         )
     }
 
-    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T> {
+    /// All items whose (composite) index value starts with the given prefix
+    pub fn sub_prefix(&self, p: IK::Prefix) -> Prefix<T, PK> {
         Prefix::with_deserialization_function(
             self.idx_namespace,
             &p.prefix(),
@@ -138,11 +143,30 @@ where
         )
     }
 
+    /// Iterates over all items whose index value lies between the given bounds,
+    /// e.g. all tokens of the owners from A to M. See `Map::prefix_range`
+    pub fn prefix_range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<PrefixBound<'a, IK>>,
+        max: Option<PrefixBound<'a, IK>>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        let namespace = namespaces_with_key(&[self.idx_namespace], b"");
+        let pk_namespace = self.pk_namespace.to_vec();
+        let mapped = prefixed_range(store, &namespace, min, max, order)
+            .map(move |kv| deserialize_multi_kv(store, &pk_namespace, kv));
+        Box::new(mapped)
+    }
+
     // FIXME?: Move to Prefix<T> for ergonomics
     pub fn pks<'c>(
         &self,
         store: &'c dyn Storage,
-        p: K::Prefix,
+        p: IK,
         min: Option<Bound>,
         max: Option<Bound>,
         order: Order,
@@ -159,38 +183,34 @@ where
     }
 
     #[cfg(test)]
-    pub fn count<'c>(&self, store: &'c dyn Storage, p: K::Prefix) -> usize {
+    pub fn count<'c>(&self, store: &'c dyn Storage, p: IK) -> usize {
         self.pks(store, p, None, None, Order::Ascending).count()
     }
 
     #[cfg(test)]
-    pub fn all_pks<'c>(&self, store: &'c dyn Storage, p: K::Prefix) -> Vec<Vec<u8>> {
+    pub fn all_pks<'c>(&self, store: &'c dyn Storage, p: IK) -> Vec<Vec<u8>> {
         self.pks(store, p, None, None, Order::Ascending)
             .collect::<StdResult<Vec<Vec<u8>>>>()
             .unwrap()
     }
 
     #[cfg(test)]
-    pub fn all_items<'c>(
-        &self,
-        store: &'c dyn Storage,
-        prefix: K::Prefix,
-    ) -> StdResult<Vec<KV<T>>> {
+    pub fn all_items<'c>(&self, store: &'c dyn Storage, prefix: IK) -> StdResult<Vec<KV<T>>> {
         self.prefix(prefix)
             .range(store, None, None, Order::Ascending)
             .collect()
     }
 }
 
-// short-cut for simple keys, rather than .prefix(()).range(...)
-impl<'a, K, T> MultiIndex<'a, K, T>
+// short-cut for simple index values, rather than .sub_prefix(()).range(...)
+impl<'a, IK, T, PK> MultiIndex<'a, IK, T, PK>
 where
     T: Serialize + DeserializeOwned + Clone,
-    K: PrimaryKey<'a>,
-    K::Prefix: EmptyPrefix,
+    IK: PrimaryKey<'a> + Prefixer<'a>,
+    IK::Prefix: EmptyPrefix,
 {
-    // I would prefer not to copy code from Prefix, but no other way
-    // with lifetimes (create Prefix inside function and return ref = no no)
+    /// Iterates over the whole index, ordered by index value and then pk.
+    /// Note the bounds apply to the raw (length-prefixed) index value followed by the pk.
     pub fn range<'c>(
         &'c self,
         store: &'c dyn Storage,
@@ -201,7 +221,25 @@ where
     where
         T: 'c,
     {
-        self.prefix(K::Prefix::new()).range(store, min, max, order)
+        self.sub_prefix(IK::Prefix::new())
+            .range(store, min, max, order)
+    }
+
+    /// Like `range`, but deserializes the primary keys into `PK::Output`
+    pub fn range_de<'c>(
+        &'c self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<DeserializedKV<PK, T>>> + 'c>
+    where
+        T: 'c,
+        PK: KeyDeserialize,
+        PK::Output: 'c,
+    {
+        self.sub_prefix(IK::Prefix::new())
+            .range_de(store, min, max, order)
     }
 }
 
@@ -214,24 +252,35 @@ pub(crate) struct UniqueRef<T> {
 
 /// UniqueIndex stores (namespace, index_name, idx_value) -> {key, value}
 /// Allows one value per index (i.e. unique) and copies pk and data
-pub struct UniqueIndex<'a, K, T> {
+///
+/// PK is the type of the primary key of the main map, used to deserialize the keys
+/// returned by `range_de`. It defaults to the raw bytes.
+pub struct UniqueIndex<'a, K, T, PK = Vec<u8>> {
     index: fn(&T) -> K,
     idx_map: Map<'a, K, UniqueRef<T>>,
     idx_namespace: &'a [u8],
+    pk_type: PhantomData<PK>,
 }
 
-impl<'a, K, T> UniqueIndex<'a, K, T> {
+impl<'a, K, T, PK> UniqueIndex<'a, K, T, PK> {
     // TODO: make this a const fn
     pub fn new(idx_fn: fn(&T) -> K, idx_namespace: &'a str) -> Self {
         UniqueIndex {
             index: idx_fn,
             idx_map: Map::new(idx_namespace),
             idx_namespace: idx_namespace.as_bytes(),
+            pk_type: PhantomData,
         }
     }
 }
 
-impl<'a, K, T> Index<T> for UniqueIndex<'a, K, T>
+impl<'a, K, T, PK> DescribeStorage for UniqueIndex<'a, K, T, PK> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        self.idx_map.storage_keys()
+    }
+}
+
+impl<'a, K, T, PK> Index<T> for UniqueIndex<'a, K, T, PK>
 where
     T: Serialize + DeserializeOwned + Clone,
     K: PrimaryKey<'a>,
@@ -265,18 +314,18 @@ fn deserialize_unique_kv<T: DeserializeOwned>(kv: KV) -> StdResult<KV<T>> {
     Ok((t.pk.into(), t.value))
 }
 
-impl<'a, K, T> UniqueIndex<'a, K, T>
+impl<'a, K, T, PK> UniqueIndex<'a, K, T, PK>
 where
     T: Serialize + DeserializeOwned + Clone,
     K: PrimaryKey<'a>,
 {
-    pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
+    pub fn prefix(&self, p: K::Prefix) -> Prefix<T, PK> {
         Prefix::with_deserialization_function(self.idx_namespace, &p.prefix(), &[], |_, _, kv| {
             deserialize_unique_kv(kv)
         })
     }
 
-    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T> {
+    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T, PK> {
         Prefix::with_deserialization_function(self.idx_namespace, &p.prefix(), &[], |_, _, kv| {
             deserialize_unique_kv(kv)
         })
@@ -293,7 +342,7 @@ where
 }
 
 // short-cut for simple keys, rather than .prefix(()).range(...)
-impl<'a, K, T> UniqueIndex<'a, K, T>
+impl<'a, K, T, PK> UniqueIndex<'a, K, T, PK>
 where
     T: Serialize + DeserializeOwned + Clone,
     K: PrimaryKey<'a>,
@@ -313,4 +362,21 @@ where
     {
         self.prefix(K::Prefix::new()).range(store, min, max, order)
     }
+
+    /// Like `range`, but deserializes the primary keys into `PK::Output`
+    pub fn range_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<DeserializedKV<PK, T>>> + 'c>
+    where
+        T: 'c,
+        PK: KeyDeserialize,
+        PK::Output: 'c,
+    {
+        self.prefix(K::Prefix::new())
+            .range_de(store, min, max, order)
+    }
 }
@@ -2,32 +2,38 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::marker::PhantomData;
 
-use cosmwasm_std::{to_vec, StdError, StdResult, Storage};
+use cosmwasm_std::{StdError, StdResult, Storage};
 
-use crate::helpers::{may_deserialize, must_deserialize};
+use crate::helpers::{may_deserialize_with, must_deserialize_with};
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::serializer::{Json, Serializer};
 
 /// Item stores one typed item at the given key.
 /// This is an analog of Singleton.
 /// It functions just as Path but doesn't ue a Vec and thus has a const fn constructor.
-pub struct Item<'a, T> {
+/// S is the Serializer used for the value, defaulting to Json.
+pub struct Item<'a, T, S = Json> {
     // this is full key - no need to length-prefix it, we only store one item
     storage_key: &'a [u8],
     // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
     data_type: PhantomData<T>,
+    serializer: PhantomData<S>,
 }
 
-impl<'a, T> Item<'a, T> {
+impl<'a, T, S> Item<'a, T, S> {
     pub const fn new(storage_key: &'a str) -> Self {
         Item {
             storage_key: storage_key.as_bytes(),
             data_type: PhantomData,
+            serializer: PhantomData,
         }
     }
 }
 
-impl<'a, T> Item<'a, T>
+impl<'a, T, S> Item<'a, T, S>
 where
     T: Serialize + DeserializeOwned,
+    S: Serializer<T>,
 {
     // this gets the path of the data to use elsewhere
     pub fn as_slice(&self) -> &[u8] {
@@ -36,7 +42,7 @@ where
 
     /// save will serialize the model and store, returns an error on serialization issues
     pub fn save(&self, store: &mut dyn Storage, data: &T) -> StdResult<()> {
-        store.set(self.storage_key, &to_vec(data)?);
+        store.set(self.storage_key, &S::to_vec(data)?);
         Ok(())
     }
 
@@ -47,14 +53,14 @@ where
     /// load will return an error if no data is set at the given key, or on parse error
     pub fn load(&self, store: &dyn Storage) -> StdResult<T> {
         let value = store.get(self.storage_key);
-        must_deserialize(&value)
+        must_deserialize_with::<T, S>(&value)
     }
 
     /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
     /// returns an error on issues parsing
     pub fn may_load(&self, store: &dyn Storage) -> StdResult<Option<T>> {
         let value = store.get(self.storage_key);
-        may_deserialize(&value)
+        may_deserialize_with::<T, S>(&value)
     }
 
     /// Loads the data, perform the specified action, and store the result
@@ -73,13 +79,20 @@ where
     }
 }
 
+impl<'a, T, S> DescribeStorage for Item<'a, T, S> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        vec![StorageKey::single(self.storage_key)]
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
     use cosmwasm_std::testing::MockStorage;
     use serde::{Deserialize, Serialize};
 
-    use cosmwasm_std::StdError;
+    use crate::{Compact, Raw};
+    use cosmwasm_std::{to_vec, Binary, StdError};
 
     #[derive(Serialize, Deserialize, PartialEq, Debug)]
     struct Config {
@@ -127,6 +140,33 @@ mod test {
         assert_eq!(None, CONFIG.may_load(&store).unwrap());
     }
 
+    #[test]
+    fn custom_serializers() {
+        let mut store = MockStorage::new();
+
+        let counter: Item<u64, Compact> = Item::new("counter");
+        assert_eq!(counter.may_load(&store).unwrap(), None);
+        counter.save(&mut store, &1234).unwrap();
+        assert_eq!(counter.load(&store).unwrap(), 1234);
+        assert_eq!(
+            store.get(b"counter").unwrap(),
+            1234u64.to_be_bytes().to_vec()
+        );
+
+        let blob: Item<Binary, Raw> = Item::new("blob");
+        let data = Binary::from(b"\x00raw\xff".as_ref());
+        blob.save(&mut store, &data).unwrap();
+        assert_eq!(blob.load(&store).unwrap(), data);
+        assert_eq!(store.get(b"blob").unwrap(), data.to_vec());
+
+        // not found error still names the type
+        let missing: Item<u64, Compact> = Item::new("missing");
+        match missing.load(&store).unwrap_err() {
+            StdError::NotFound { kind, .. } => assert_eq!(kind, "u64"),
+            e => panic!("Unexpected error {}", e),
+        }
+    }
+
     #[test]
     fn isolated_reads() {
         let mut store = MockStorage::new();
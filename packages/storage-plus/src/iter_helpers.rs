@@ -5,7 +5,9 @@ use serde::de::DeserializeOwned;
 use cosmwasm_std::KV;
 use cosmwasm_std::{from_slice, StdResult};
 
+use crate::de::KeyDeserialize;
 use crate::helpers::encode_length;
+use crate::serializer::Serializer;
 
 pub(crate) fn deserialize_kv<T: DeserializeOwned>(kv: KV) -> StdResult<KV<T>> {
     let (k, v) = kv;
@@ -13,6 +15,19 @@ pub(crate) fn deserialize_kv<T: DeserializeOwned>(kv: KV) -> StdResult<KV<T>> {
     Ok((k, t))
 }
 
+pub(crate) fn deserialize_kv_with<T, S: Serializer<T>>(kv: KV) -> StdResult<KV<T>> {
+    let (k, v) = kv;
+    let t = S::from_slice(&v)?;
+    Ok((k, t))
+}
+
+pub(crate) fn deserialize_key<K: KeyDeserialize, T>(
+    kv: StdResult<KV<T>>,
+) -> StdResult<(K::Output, T)> {
+    let (k, v) = kv?;
+    Ok((K::from_vec(k)?, v))
+}
+
 /// Calculates the raw key prefix for a given namespace as documented
 /// in https://github.com/webmaster128/key-namespacing#length-prefixed-keys
 #[allow(dead_code)]
@@ -1,14 +1,20 @@
 use std::marker::PhantomData;
 use std::str::from_utf8;
 
+use crate::de::KeyDeserialize;
 use crate::helpers::{decode_length, namespaces_with_key};
 use crate::Endian;
 
 // pub trait PrimaryKey<'a>: Copy {
-pub trait PrimaryKey<'a>: Clone {
+pub trait PrimaryKey<'a>: Clone + KeyDeserialize {
     type Prefix: Prefixer<'a>;
     type SubPrefix: Prefixer<'a>;
 
+    /// the remaining key after removing Prefix, used to deserialize keys in `range_de`
+    type Suffix: KeyDeserialize;
+    /// the remaining key after removing SubPrefix, used to deserialize keys in `range_de`
+    type SuperSuffix: KeyDeserialize;
+
     /// returns a slice of key steps, which can be optionally combined
     fn key(&self) -> Vec<&[u8]>;
 
@@ -26,6 +32,8 @@ pub trait PrimaryKey<'a>: Clone {
 impl<'a> PrimaryKey<'a> for &'a [u8] {
     type Prefix = ();
     type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         // this is simple, we don't add more prefixes
@@ -41,6 +49,8 @@ impl<'a> PrimaryKey<'a> for &'a [u8] {
 impl<'a> PrimaryKey<'a> for &'a str {
     type Prefix = ();
     type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         // this is simple, we don't add more prefixes
@@ -56,6 +66,8 @@ impl<'a> PrimaryKey<'a> for &'a str {
 impl<'a, T: PrimaryKey<'a> + Prefixer<'a>, U: PrimaryKey<'a>> PrimaryKey<'a> for (T, U) {
     type Prefix = T;
     type SubPrefix = ();
+    type Suffix = U;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         let mut keys = self.0.key();
@@ -77,6 +89,8 @@ impl<'a, T: PrimaryKey<'a> + Prefixer<'a>, U: PrimaryKey<'a> + Prefixer<'a>, V:
 {
     type Prefix = (T, U);
     type SubPrefix = T;
+    type Suffix = V;
+    type SuperSuffix = (U, V);
 
     fn key(&self) -> Vec<&[u8]> {
         let mut keys = self.0.key();
@@ -157,6 +171,8 @@ pub struct PkOwned(pub Vec<u8>);
 impl<'a> PrimaryKey<'a> for PkOwned {
     type Prefix = ();
     type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         vec![&self.0]
@@ -174,9 +190,11 @@ impl<'a> Prefixer<'a> for PkOwned {
 }
 
 // this auto-implements PrimaryKey for all the IntKey types (and more!)
-impl<'a, T: AsRef<PkOwned> + From<PkOwned> + Clone> PrimaryKey<'a> for T {
+impl<'a, T: AsRef<PkOwned> + From<PkOwned> + Clone + KeyDeserialize> PrimaryKey<'a> for T {
     type Prefix = ();
     type SubPrefix = ();
+    type Suffix = Self;
+    type SuperSuffix = Self;
 
     fn key(&self) -> Vec<&[u8]> {
         self.as_ref().key()
@@ -207,6 +225,10 @@ pub type I64Key = IntKey<i64>;
 pub type I128Key = IntKey<i128>;
 
 /// It will cast one-particular int type into a Key via PkOwned, ensuring you don't mix up u32 and u64
+/// The ints are stored big endian, with the sign bit flipped for signed ints,
+/// so that the keys sort in numerical order (see `Endian::to_sortable_bytes`).
+/// Note this changed the stored bytes of signed keys, data saved with plain big endian
+/// signed keys must be migrated (see the README).
 /// You can use new or the from/into pair to build a key from an int:
 ///
 ///   let k = U64Key::new(12345);
@@ -221,7 +243,7 @@ pub struct IntKey<T: Endian> {
 impl<T: Endian> IntKey<T> {
     pub fn new(val: T) -> Self {
         IntKey {
-            wrapped: PkOwned(val.to_be_bytes().into()),
+            wrapped: PkOwned(val.to_sortable_bytes().into()),
             data: PhantomData,
         }
     }
@@ -427,4 +449,109 @@ mod test {
             vec![one.as_slice(), two.as_slice(), three.as_slice()]
         );
     }
+
+    // A small xorshift generator, so the property tests below are reproducible
+    // without pulling in another dependency
+    struct Rng(u64);
+
+    impl Rng {
+        fn next(&mut self) -> u64 {
+            let mut x = self.0;
+            x ^= x << 13;
+            x ^= x >> 7;
+            x ^= x << 17;
+            self.0 = x;
+            x
+        }
+    }
+
+    #[test]
+    fn signed_key_bytes_preserve_order() {
+        let mut rng = Rng(0x2545_f491_4f6c_dd1d);
+        for _ in 0..1000 {
+            let a = rng.next() as i64;
+            let b = rng.next() as i64;
+            let (ka, kb) = (I64Key::from(a), I64Key::from(b));
+            assert_eq!(a.cmp(&b), ka.joined_key().cmp(&kb.joined_key()));
+
+            let (a, b) = (a as i32, b as i32);
+            let (ka, kb) = (I32Key::from(a), I32Key::from(b));
+            assert_eq!(a.cmp(&b), ka.joined_key().cmp(&kb.joined_key()));
+        }
+
+        // all i8 values are sorted
+        let keys: Vec<_> = (i8::MIN..=i8::MAX)
+            .map(|v| I8Key::from(v).joined_key())
+            .collect();
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(keys, sorted);
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn signed_keys_range_in_numerical_order() {
+        use crate::{Bound, Map};
+        use cosmwasm_std::testing::MockStorage;
+        use cosmwasm_std::{Order, StdResult};
+
+        const NUMBERS: Map<I64Key, i64> = Map::new("numbers");
+
+        for seed in 1..=10u64 {
+            let mut rng = Rng(seed.wrapping_mul(0x9e37_79b9_7f4a_7c15));
+            let mut store = MockStorage::new();
+
+            // mixed sign data, with small numbers to cover both sides of zero densely
+            let mut values: Vec<i64> = (0..100)
+                .map(|i| {
+                    let v = rng.next() as i64;
+                    if i % 2 == 0 {
+                        v % 1000
+                    } else {
+                        v
+                    }
+                })
+                .chain(vec![i64::MIN, -1, 0, 1, i64::MAX])
+                .collect();
+            for v in &values {
+                NUMBERS.save(&mut store, (*v).into(), v).unwrap();
+            }
+            values.sort_unstable();
+            values.dedup();
+
+            // keys and values come back numerically sorted
+            let all: Vec<(i64, i64)> = NUMBERS
+                .range_de(&store, None, None, Order::Ascending)
+                .collect::<StdResult<_>>()
+                .unwrap();
+            let expected: Vec<_> = values.iter().map(|v| (*v, *v)).collect();
+            assert_eq!(all, expected);
+
+            let keys: Vec<i64> = NUMBERS
+                .keys_de(&store, None, None, Order::Descending)
+                .collect::<StdResult<_>>()
+                .unwrap();
+            let reversed: Vec<_> = values.iter().rev().cloned().collect();
+            assert_eq!(keys, reversed);
+
+            // int bounds use the same encoding
+            let low = rng.next() as i64 % 1000;
+            let high = low + (rng.next() % 1000) as i64;
+            let bounded: Vec<i64> = NUMBERS
+                .keys_de(
+                    &store,
+                    Some(Bound::inclusive_int(low)),
+                    Some(Bound::exclusive_int(high)),
+                    Order::Ascending,
+                )
+                .collect::<StdResult<_>>()
+                .unwrap();
+            let expected: Vec<_> = values
+                .iter()
+                .filter(|v| **v >= low && **v < high)
+                .cloned()
+                .collect();
+            assert_eq!(bounded, expected);
+        }
+    }
 }
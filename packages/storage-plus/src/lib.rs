@@ -1,28 +1,58 @@
+mod cached;
+mod de;
+mod deque;
 mod endian;
+mod expiring_map;
 mod helpers;
 mod indexed_map;
+mod indexed_snapshot;
 mod indexes;
 mod item;
 mod iter_helpers;
 mod keys;
 mod map;
+mod merkle_map;
+mod migration;
+mod nested_map;
+mod pagination;
 mod path;
 mod prefix;
+mod priority_queue;
+mod registry;
+mod sequence;
+mod serializer;
 mod snapshot;
+mod transaction;
 
+pub use cached::{CachedItem, CachedMap};
+pub use de::KeyDeserialize;
+pub use deque::{Deque, DequeIter};
 pub use endian::Endian;
+pub use expiring_map::{Expiring, ExpiringMap, Expiry};
 #[cfg(feature = "iterator")]
 pub use indexed_map::{IndexList, IndexedMap};
 #[cfg(feature = "iterator")]
-pub use indexes::{
-    index_string, index_string_tuple, index_triple, index_tuple, Index, MultiIndex, UniqueIndex,
-};
+pub use indexed_snapshot::IndexedSnapshotMap;
+#[cfg(feature = "iterator")]
+pub use indexes::{index_string, index_string_tuple, Index, MultiIndex, UniqueIndex};
 pub use item::Item;
 pub use keys::{I128Key, I16Key, I32Key, I64Key, I8Key};
 pub use keys::{PkOwned, Prefixer, PrimaryKey, U128Key, U16Key, U32Key, U64Key, U8Key};
 pub use map::Map;
+pub use merkle_map::{MerkleMap, MerkleProof, ProofLeaf};
+#[cfg(feature = "iterator")]
+pub use migration::{MapMigration, MigrationProgress};
+pub use nested_map::NestedMap;
+#[cfg(feature = "iterator")]
+pub use pagination::{paginate, paginate_de, Page, DEFAULT_LIMIT, MAX_LIMIT};
 pub use path::Path;
 #[cfg(feature = "iterator")]
-pub use prefix::{range_with_prefix, Bound, Prefix};
+pub use prefix::{prefixed_range, range_with_prefix, Bound, Prefix, PrefixBound};
+#[cfg(feature = "iterator")]
+pub use priority_queue::PriorityQueue;
+pub use registry::{DescribeStorage, KeyKind, RegisteredKey, StorageKey, StorageRegistry};
+pub use sequence::{Sequence, SequenceNumber};
+pub use serializer::{Compact, CompactEncoding, Json, Raw, Serializer};
 #[cfg(feature = "iterator")]
-pub use snapshot::{SnapshotMap, Strategy};
+pub use snapshot::{Retention, SnapshotItem, SnapshotMap, Strategy};
+pub use transaction::{transactional, RepLog, StorageTransaction};
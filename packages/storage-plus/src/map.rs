@@ -2,49 +2,70 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::marker::PhantomData;
 
+#[cfg(feature = "iterator")]
+use crate::de::KeyDeserialize;
+#[cfg(feature = "iterator")]
+use crate::helpers::namespaces_with_key;
+#[cfg(feature = "iterator")]
+use crate::iter_helpers::deserialize_kv_with;
 use crate::keys::PrimaryKey;
 #[cfg(feature = "iterator")]
 use crate::keys::{EmptyPrefix, Prefixer};
 use crate::path::Path;
 #[cfg(feature = "iterator")]
-use crate::prefix::{Bound, Prefix};
+use crate::prefix::{prefixed_range, Bound, DeserializedKV, Prefix, PrefixBound};
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::serializer::{Json, Serializer};
 use cosmwasm_std::{StdError, StdResult, Storage};
 
+/// S is the Serializer used for the values, defaulting to Json.
+/// Eg. `Map<&[u8], Uint128, Compact>` stores balances as 16 bytes rather than a json string.
 #[derive(Debug, Clone)]
-pub struct Map<'a, K, T> {
+pub struct Map<'a, K, T, S = Json> {
     namespace: &'a [u8],
     // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
     key_type: PhantomData<K>,
     data_type: PhantomData<T>,
+    serializer: PhantomData<S>,
 }
 
-impl<'a, K, T> Map<'a, K, T> {
+impl<'a, K, T, S> Map<'a, K, T, S> {
     pub const fn new(namespace: &'a str) -> Self {
         Map {
             namespace: namespace.as_bytes(),
             data_type: PhantomData,
             key_type: PhantomData,
+            serializer: PhantomData,
         }
     }
 }
 
-impl<'a, K, T> Map<'a, K, T>
+impl<'a, K, T, S> Map<'a, K, T, S>
 where
     T: Serialize + DeserializeOwned,
     K: PrimaryKey<'a>,
+    S: Serializer<T>,
 {
-    pub fn key(&self, k: K) -> Path<T> {
+    pub fn key(&self, k: K) -> Path<T, S> {
         Path::new(self.namespace, &k.key())
     }
 
     #[cfg(feature = "iterator")]
-    pub fn prefix(&self, p: K::Prefix) -> Prefix<T> {
-        Prefix::new(self.namespace, &p.prefix())
+    pub fn prefix(&self, p: K::Prefix) -> Prefix<T, K::Suffix> {
+        self.prefix_with(&p.prefix())
     }
 
     #[cfg(feature = "iterator")]
-    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T> {
-        Prefix::new(self.namespace, &p.prefix())
+    pub fn sub_prefix(&self, p: K::SubPrefix) -> Prefix<T, K::SuperSuffix> {
+        self.prefix_with(&p.prefix())
+    }
+
+    // a prefix deserializing the values with S
+    #[cfg(feature = "iterator")]
+    fn prefix_with<X>(&self, sub_names: &[&[u8]]) -> Prefix<T, X> {
+        Prefix::with_deserialization_function(self.namespace, sub_names, &[], |_, _, kv| {
+            deserialize_kv_with::<T, S>(kv)
+        })
     }
 
     pub fn save(&self, store: &mut dyn Storage, k: K, data: &T) -> StdResult<()> {
@@ -79,13 +100,77 @@ where
     }
 }
 
+#[cfg(feature = "iterator")]
+impl<'a, K, T, S> Map<'a, K, T, S>
+where
+    T: Serialize + DeserializeOwned,
+    K: PrimaryKey<'a>,
+    S: Serializer<T>,
+{
+    /// While `range` over a `prefix` fixes the prefix to one element and iterates over the remaining,
+    /// `prefix_range` accepts bounds for the lowest and highest prefix to include, and iterates
+    /// over all items under any prefix in that range. Keys are returned in their full (joined) form.
+    pub fn prefix_range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<PrefixBound<'a, K::Prefix>>,
+        max: Option<PrefixBound<'a, K::Prefix>>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<cosmwasm_std::KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        let namespace = namespaces_with_key(&[self.namespace], b"");
+        // use a fn pointer, so the iterator type doesn't depend on S
+        let de_fn: fn(cosmwasm_std::KV) -> StdResult<cosmwasm_std::KV<T>> =
+            deserialize_kv_with::<T, S>;
+        let mapped = prefixed_range(store, &namespace, min, max, order).map(de_fn);
+        Box::new(mapped)
+    }
+
+    /// Returns true if the map doesn't contain any entries
+    pub fn is_empty(&self, store: &dyn Storage) -> bool {
+        self.no_prefix().is_empty(store)
+    }
+
+    /// Removes all entries of the map, at most limit of them if given.
+    /// This allows to bound the work done in one call, for large maps.
+    /// Returns true if there are entries left to remove.
+    pub fn clear(&self, store: &mut dyn Storage, limit: Option<usize>) -> bool {
+        self.no_prefix().clear(store, limit)
+    }
+
+    /// Removes the entries under the given prefix, at most limit of them if given.
+    /// Returns true if there are entries left to remove.
+    pub fn clear_prefix(
+        &self,
+        store: &mut dyn Storage,
+        p: K::Prefix,
+        limit: Option<usize>,
+    ) -> bool {
+        self.prefix(p).clear(store, limit)
+    }
+
+    // a prefix covering the whole map, regardless of the key type
+    pub(crate) fn no_prefix(&self) -> Prefix<T> {
+        self.prefix_with(&[])
+    }
+}
+
+impl<'a, K, T, S> DescribeStorage for Map<'a, K, T, S> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        vec![StorageKey::namespace(self.namespace)]
+    }
+}
+
 // short-cut for simple keys, rather than .prefix(()).range(...)
 #[cfg(feature = "iterator")]
-impl<'a, K, T> Map<'a, K, T>
+impl<'a, K, T, S> Map<'a, K, T, S>
 where
     T: Serialize + DeserializeOwned,
     K: PrimaryKey<'a>,
     K::Prefix: EmptyPrefix,
+    S: Serializer<T>,
 {
     pub fn range<'c>(
         &self,
@@ -112,6 +197,40 @@ where
     {
         self.prefix(K::Prefix::new()).keys(store, min, max, order)
     }
+
+    /// Like `range`, but deserializes the keys into the key type.
+    /// For example, `&str` keys come back as `String` and `U64Key` as `u64`
+    pub fn range_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<DeserializedKV<K::Suffix, T>>> + 'c>
+    where
+        T: 'c,
+        <K::Suffix as KeyDeserialize>::Output: 'c,
+    {
+        self.prefix(K::Prefix::new())
+            .range_de(store, min, max, order)
+    }
+
+    /// Like `keys`, but deserializes the keys into the key type.
+    pub fn keys_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<<K::Suffix as KeyDeserialize>::Output>> + 'c>
+    where
+        T: 'c,
+        <K::Suffix as KeyDeserialize>::Output: 'c,
+    {
+        // use a fn pointer, so the iterator type doesn't depend on K
+        let de_fn: fn(Vec<u8>) -> StdResult<_> = <K::Suffix as KeyDeserialize>::from_vec;
+        Box::new(self.keys(store, min, max, order).map(de_fn))
+    }
 }
 
 #[cfg(test)]
@@ -122,8 +241,14 @@ mod test {
 
     #[cfg(feature = "iterator")]
     use crate::iter_helpers::to_length_prefixed;
+    use crate::Compact;
+    #[cfg(feature = "iterator")]
+    use crate::Raw;
+    #[cfg(feature = "iterator")]
+    use crate::U64Key;
     use crate::U8Key;
     use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::Uint128;
     #[cfg(feature = "iterator")]
     use cosmwasm_std::{Order, StdResult};
 
@@ -389,6 +514,301 @@ mod test {
         );
     }
 
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_de_simple_key() {
+        let mut store = MockStorage::new();
+
+        const NAMES: Map<&str, u64> = Map::new("names");
+        NAMES.save(&mut store, "john", &32).unwrap();
+        NAMES.save(&mut store, "jim", &44).unwrap();
+
+        let all: StdResult<Vec<_>> = NAMES
+            .range_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![("jim".to_string(), 44), ("john".to_string(), 32)]
+        );
+
+        let keys: StdResult<Vec<_>> = NAMES
+            .keys_de(&store, None, None, Order::Descending)
+            .collect();
+        assert_eq!(keys.unwrap(), vec!["john".to_string(), "jim".to_string()]);
+
+        // int keys come back as ints
+        const IDS: Map<U64Key, Data> = Map::new("ids");
+        let data = Data {
+            name: "John".to_string(),
+            age: 32,
+        };
+        IDS.save(&mut store, 300.into(), &data).unwrap();
+        IDS.save(&mut store, 2.into(), &data).unwrap();
+        let ids: StdResult<Vec<_>> = IDS
+            .keys_de(
+                &store,
+                Some(Bound::exclusive_int(2u64)),
+                None,
+                Order::Ascending,
+            )
+            .collect();
+        assert_eq!(ids.unwrap(), vec![300u64]);
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_de_composite_key() {
+        let mut store = MockStorage::new();
+
+        TRIPLE
+            .save(&mut store, (b"owner", 9u8.into(), "recipient"), &1000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner", 10u8.into(), "recipient2"), &3000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner2", 9u8.into(), "recipient"), &5000)
+            .unwrap();
+
+        // the last element of the key is returned from a prefix
+        let all: StdResult<Vec<_>> = TRIPLE
+            .prefix((b"owner", 9u8.into()))
+            .range_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(all.unwrap(), vec![("recipient".to_string(), 1000)]);
+
+        // the remaining (length-prefixed) key is decoded from a sub prefix
+        let all: StdResult<Vec<_>> = TRIPLE
+            .sub_prefix(b"owner")
+            .range_de(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                ((9u8, "recipient".to_string()), 1000),
+                ((10u8, "recipient2".to_string()), 3000)
+            ]
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_with_typed_bounds() {
+        let mut store = MockStorage::new();
+
+        TRIPLE
+            .save(&mut store, (b"owner", 9u8.into(), "recipient"), &1000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner", 10u8.into(), "recipient2"), &3000)
+            .unwrap();
+        TRIPLE
+            .save(&mut store, (b"owner", 10u8.into(), "recipient3"), &4000)
+            .unwrap();
+
+        // simple bound on the last element
+        let all: StdResult<Vec<_>> = TRIPLE
+            .prefix((b"owner", 10u8.into()))
+            .range_de(
+                &store,
+                Some(Bound::exclusive("recipient2")),
+                None,
+                Order::Ascending,
+            )
+            .collect();
+        assert_eq!(all.unwrap(), vec![("recipient3".to_string(), 4000)]);
+
+        // composite bound is encoded like the stored key
+        let all: StdResult<Vec<_>> = TRIPLE
+            .sub_prefix(b"owner")
+            .range_de(
+                &store,
+                Some(Bound::exclusive((U8Key::from(9u8), "recipient"))),
+                Some(Bound::inclusive((U8Key::from(10u8), "recipient2"))),
+                Order::Ascending,
+            )
+            .collect();
+        assert_eq!(all.unwrap(), vec![((10u8, "recipient2".to_string()), 3000)]);
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn prefix_range_composite_key() {
+        let mut store = MockStorage::new();
+
+        // owners of the same length sort lexicographically
+        for (owner, spender, amount) in &[
+            (b"ownerA", b"spender1", 10u64),
+            (b"ownerB", b"spender1", 20),
+            (b"ownerB", b"spender2", 30),
+            (b"ownerC", b"spender1", 40),
+            (b"ownerD", b"spender1", 50),
+        ] {
+            ALLOWANCE
+                .save(&mut store, (*owner, *spender), amount)
+                .unwrap();
+        }
+
+        let amounts = |min, max, order| -> Vec<u64> {
+            ALLOWANCE
+                .prefix_range(&store, min, max, order)
+                .map(|r| r.map(|(_, v)| v))
+                .collect::<StdResult<_>>()
+                .unwrap()
+        };
+
+        // everything
+        assert_eq!(
+            amounts(None, None, Order::Ascending),
+            vec![10, 20, 30, 40, 50]
+        );
+
+        // inclusive bounds take all keys under the prefix
+        assert_eq!(
+            amounts(
+                Some(PrefixBound::inclusive(b"ownerB")),
+                Some(PrefixBound::inclusive(b"ownerC")),
+                Order::Ascending
+            ),
+            vec![20, 30, 40]
+        );
+
+        // exclusive bounds skip all keys under the prefix
+        assert_eq!(
+            amounts(
+                Some(PrefixBound::exclusive(b"ownerA")),
+                Some(PrefixBound::exclusive(b"ownerD")),
+                Order::Descending
+            ),
+            vec![40, 30, 20]
+        );
+
+        // keys are returned in full
+        let first = ALLOWANCE
+            .prefix_range(
+                &store,
+                Some(PrefixBound::exclusive(b"ownerC")),
+                None,
+                Order::Ascending,
+            )
+            .next()
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            first.0,
+            (b"ownerD" as &[u8], b"spender1" as &[u8]).joined_key()
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn clear_and_is_empty() {
+        let mut store = MockStorage::new();
+        assert!(ALLOWANCE.is_empty(&store));
+
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender1"), &1000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender2"), &2000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender3"), &3000)
+            .unwrap();
+        ALLOWANCE
+            .save(&mut store, (b"owner2", b"spender1"), &5000)
+            .unwrap();
+        // same namespace prefix, but a different map
+        PEOPLE
+            .save(
+                &mut store,
+                b"john",
+                &Data {
+                    name: "John".to_string(),
+                    age: 32,
+                },
+            )
+            .unwrap();
+        assert!(!ALLOWANCE.is_empty(&store));
+        assert!(!ALLOWANCE.prefix(b"owner").is_empty(&store));
+
+        // bounded removal under one prefix
+        assert!(ALLOWANCE.clear_prefix(&mut store, b"owner", Some(2)));
+        assert_eq!(
+            ALLOWANCE.may_load(&store, (b"owner", b"spender3")).unwrap(),
+            Some(3000)
+        );
+        assert!(!ALLOWANCE.clear_prefix(&mut store, b"owner", Some(2)));
+        assert!(ALLOWANCE.prefix(b"owner").is_empty(&store));
+        assert!(!ALLOWANCE.prefix(b"owner2").is_empty(&store));
+
+        // bounded removal of everything doesn't touch other maps
+        ALLOWANCE
+            .save(&mut store, (b"owner", b"spender1"), &1000)
+            .unwrap();
+        assert!(ALLOWANCE.clear(&mut store, Some(1)));
+        assert!(!ALLOWANCE.clear(&mut store, Some(1)));
+        assert!(ALLOWANCE.is_empty(&store));
+        assert!(!PEOPLE.is_empty(&store));
+    }
+
+    #[test]
+    fn compact_serializer() {
+        const BALANCES: Map<&[u8], Uint128, Compact> = Map::new("balance");
+        let mut store = MockStorage::new();
+
+        BALANCES.save(&mut store, b"john", &Uint128(1234)).unwrap();
+        BALANCES
+            .update(&mut store, b"john", |b| -> StdResult<_> {
+                Ok(b.unwrap_or_default() + Uint128(66))
+            })
+            .unwrap();
+        assert_eq!(BALANCES.load(&store, b"john").unwrap(), Uint128(1300));
+        assert_eq!(BALANCES.may_load(&store, b"jim").unwrap(), None);
+
+        // stored as a fixed size big endian u128, rather than a json string
+        let raw = store.get(&BALANCES.key(b"john")).unwrap();
+        assert_eq!(raw, 1300u128.to_be_bytes().to_vec());
+
+        // json data under the same key doesn't parse
+        let json: Map<&[u8], Uint128> = Map::new("balance");
+        json.save(&mut store, b"jim", &Uint128(5)).unwrap();
+        BALANCES.load(&store, b"jim").unwrap_err();
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn range_with_custom_serializer() {
+        const BLOBS: Map<&str, Vec<u8>, Raw> = Map::new("blobs");
+        let mut store = MockStorage::new();
+
+        BLOBS.save(&mut store, "a", &b"first".to_vec()).unwrap();
+        BLOBS.save(&mut store, "b", &vec![0]).unwrap();
+        // empty values can't be stored
+        BLOBS.save(&mut store, "c", &vec![]).unwrap_err();
+        assert_eq!(store.get(&BLOBS.key("a")).unwrap(), b"first".to_vec());
+
+        let all: StdResult<Vec<_>> = BLOBS.range(&store, None, None, Order::Ascending).collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![(b"a".to_vec(), b"first".to_vec()), (b"b".to_vec(), vec![0])]
+        );
+
+        let all: StdResult<Vec<_>> = BLOBS
+            .range_de(&store, None, None, Order::Descending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                ("b".to_string(), vec![0]),
+                ("a".to_string(), b"first".to_vec())
+            ]
+        );
+
+        assert!(!BLOBS.clear(&mut store, None));
+        assert!(BLOBS.is_empty(&store));
+    }
+
     #[test]
     fn basic_update() {
         let mut store = MockStorage::new();
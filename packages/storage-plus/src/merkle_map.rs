@@ -0,0 +1,579 @@
+//! MerkleMap keeps a sparse Merkle tree over all entries of a Map, so a contract can publish
+//! one root and prove any entry (or its absence) to off-chain systems or other chains.
+//!
+//! Every key is placed on the path `sha256(joined_key)` of a binary tree of depth 256, which is
+//! compacted: a subtree with a single entry is replaced by its leaf, and an empty subtree hashes as
+//! 32 zero bytes. So with n entries, the leaves are about log2(n) levels deep, and a write
+//! only reads and writes the nodes on that path.
+//!
+//! A leaf hashes as `sha256(0x00 || path || sha256(value))`, with the value in its stored (json)
+//! encoding, and an inner node as `sha256(0x01 || left || right)`. The hash of a subtree only
+//! depends on its entries, not on the order of the writes.
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use cosmwasm_std::{to_vec, Binary, StdError, StdResult, Storage};
+
+use crate::helpers::namespaces_with_key;
+use crate::keys::PrimaryKey;
+use crate::map::Map;
+#[cfg(feature = "iterator")]
+use crate::prefix::Prefix;
+use crate::registry::{DescribeStorage, StorageKey};
+
+/// Maximal depth of the tree, one level per bit of the hashed key
+const DEPTH: usize = 256;
+const EMPTY: [u8; 32] = [0; 32];
+
+type Hash = [u8; 32];
+
+/// MerkleProof holds the siblings on the path of one key, from the deepest one up to the root.
+/// The path ends at the leaf of the key, or at the place where it would be if there is no entry.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct MerkleProof {
+    /// 32 bytes each, empty siblings are 32 zero bytes
+    pub siblings: Vec<Binary>,
+    /// For a proof of absence, the entry of another key at the end of the path, if any
+    pub other: Option<ProofLeaf>,
+}
+
+/// ProofLeaf describes the leaf of an entry, without revealing its key or value
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, JsonSchema)]
+pub struct ProofLeaf {
+    /// sha256 of the joined key
+    pub path: Binary,
+    /// sha256 of the (json) value
+    pub value_hash: Binary,
+}
+
+/// The stored nodes of the tree. Leaves keep their full path, as they can sit at any depth.
+#[derive(Clone, Debug, PartialEq)]
+enum Node {
+    Leaf { path: Hash, value_hash: Hash },
+    Inner(Hash),
+}
+
+impl Node {
+    fn hash(&self) -> Hash {
+        match self {
+            Node::Leaf { path, value_hash } => leaf_hash(path, value_hash),
+            Node::Inner(hash) => *hash,
+        }
+    }
+
+    fn to_vec(&self) -> Vec<u8> {
+        match self {
+            Node::Leaf { path, value_hash } => [&[0u8][..], path, value_hash].concat(),
+            Node::Inner(hash) => [&[1u8][..], hash].concat(),
+        }
+    }
+
+    fn from_slice(data: &[u8]) -> StdResult<Self> {
+        match (data.first(), data.len()) {
+            (Some(0), 65) => Ok(Node::Leaf {
+                path: to_hash(&data[1..33])?,
+                value_hash: to_hash(&data[33..])?,
+            }),
+            (Some(1), 33) => Ok(Node::Inner(to_hash(&data[1..])?)),
+            _ => Err(StdError::generic_err("Invalid merkle node")),
+        }
+    }
+}
+
+// the hash of an optional node, EMPTY if there is none
+fn hash_of(node: &Option<Node>) -> Hash {
+    node.as_ref().map(Node::hash).unwrap_or(EMPTY)
+}
+
+/// MerkleMap works like a Map, but every write also updates the tree, which costs
+/// one read and one write for every level above the leaf, about log2(n) with n entries.
+pub struct MerkleMap<'a, K, T> {
+    map: Map<'a, K, T>,
+    nodes_namespace: &'a [u8],
+}
+
+impl<'a, K, T> MerkleMap<'a, K, T> {
+    pub const fn new(namespace: &'a str, nodes_namespace: &'a str) -> Self {
+        MerkleMap {
+            map: Map::new(namespace),
+            nodes_namespace: nodes_namespace.as_bytes(),
+        }
+    }
+}
+
+impl<'a, K, T> MerkleMap<'a, K, T>
+where
+    T: Serialize + DeserializeOwned,
+    K: PrimaryKey<'a>,
+{
+    pub fn save(&self, store: &mut dyn Storage, k: K, data: &T) -> StdResult<()> {
+        let value_hash = Sha256::digest(&to_vec(data)?).into();
+        self.insert_leaf(store, key_path(&k), value_hash)?;
+        self.map.save(store, k, data)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, k: K) -> StdResult<()> {
+        self.remove_leaf(store, &key_path(&k))?;
+        self.map.remove(store, k);
+        Ok(())
+    }
+
+    /// load will return an error if no data is set at the given key, or on parse error
+    pub fn load(&self, store: &dyn Storage, k: K) -> StdResult<T> {
+        self.map.load(store, k)
+    }
+
+    /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
+    pub fn may_load(&self, store: &dyn Storage, k: K) -> StdResult<Option<T>> {
+        self.map.may_load(store, k)
+    }
+
+    /// See `Map::update`
+    pub fn update<A, E>(&self, store: &mut dyn Storage, k: K, action: A) -> Result<T, E>
+    where
+        A: FnOnce(Option<T>) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let input = self.may_load(store, k.clone())?;
+        let output = action(input)?;
+        self.save(store, k, &output)?;
+        Ok(output)
+    }
+
+    /// Iterates over the entries like `Map::prefix`. Only reads are possible this way,
+    /// so the tree can't get out of sync.
+    #[cfg(feature = "iterator")]
+    pub fn prefix(&self, p: K::Prefix) -> Prefix<T, K::Suffix> {
+        self.map.prefix(p)
+    }
+
+    /// The root of the tree, 32 zero bytes if the map is empty
+    pub fn root(&self, store: &dyn Storage) -> StdResult<Binary> {
+        let root = self.node(store, 0, &EMPTY)?;
+        Ok(Binary::from(hash_of(&root).to_vec()))
+    }
+
+    /// Returns the proof for the entry of k, or for its absence if there is none
+    pub fn prove(&self, store: &dyn Storage, k: K) -> StdResult<MerkleProof> {
+        let path = key_path(&k);
+        let mut siblings = vec![];
+        let mut other = None;
+        for depth in 0..=DEPTH {
+            match self.node(store, depth, &path)? {
+                Some(Node::Inner(_)) => {
+                    let sibling = self.node(store, depth + 1, &flip_bit(&path, depth))?;
+                    siblings.push(Binary::from(hash_of(&sibling).to_vec()));
+                }
+                Some(Node::Leaf {
+                    path: leaf_path,
+                    value_hash,
+                }) => {
+                    if leaf_path != path {
+                        other = Some(ProofLeaf {
+                            path: Binary::from(leaf_path.to_vec()),
+                            value_hash: Binary::from(value_hash.to_vec()),
+                        });
+                    }
+                    break;
+                }
+                None => break,
+            }
+        }
+        siblings.reverse();
+        Ok(MerkleProof { siblings, other })
+    }
+
+    // Places the leaf at the end of its path. If another leaf is there, both move down
+    // below the first bit where their paths differ.
+    fn insert_leaf(&self, store: &mut dyn Storage, path: Hash, value_hash: Hash) -> StdResult<()> {
+        let mut depth = 0;
+        while let Some(node) = self.node(store, depth, &path)? {
+            match node {
+                Node::Inner(_) => depth += 1,
+                Node::Leaf {
+                    path: other_path, ..
+                } if other_path != path => {
+                    depth = first_different_bit(&path, &other_path) + 1;
+                    self.set_node(store, depth, &other_path, Some(&node));
+                    break;
+                }
+                Node::Leaf { .. } => break,
+            }
+        }
+        let leaf = Node::Leaf { path, value_hash };
+        self.set_node(store, depth, &path, Some(&leaf));
+        self.rehash(store, &path, depth)
+    }
+
+    // Removes the leaf of path, if there is one
+    fn remove_leaf(&self, store: &mut dyn Storage, path: &Hash) -> StdResult<()> {
+        for depth in 0..=DEPTH {
+            match self.node(store, depth, path)? {
+                Some(Node::Inner(_)) => {}
+                Some(Node::Leaf {
+                    path: leaf_path, ..
+                }) if &leaf_path == path => {
+                    self.set_node(store, depth, path, None);
+                    return self.rehash(store, path, depth);
+                }
+                _ => return Ok(()),
+            }
+        }
+        Ok(())
+    }
+
+    // Recalculates the nodes on the path above depth, up to the root. A leaf that is left
+    // without a sibling moves up, so every inner node has at least two entries below it.
+    fn rehash(&self, store: &mut dyn Storage, path: &Hash, depth: usize) -> StdResult<()> {
+        for depth in (0..depth).rev() {
+            let sibling_path = flip_bit(path, depth);
+            let mine = self.node(store, depth + 1, path)?;
+            let sibling = self.node(store, depth + 1, &sibling_path)?;
+            match (&mine, &sibling) {
+                (None, None) => self.set_node(store, depth, path, None),
+                (Some(leaf @ Node::Leaf { .. }), None) => {
+                    self.set_node(store, depth + 1, path, None);
+                    self.set_node(store, depth, path, Some(leaf));
+                }
+                (None, Some(leaf @ Node::Leaf { .. })) => {
+                    self.set_node(store, depth + 1, &sibling_path, None);
+                    self.set_node(store, depth, path, Some(leaf));
+                }
+                _ => {
+                    let hash = match get_bit(path, depth) {
+                        false => node_hash(&hash_of(&mine), &hash_of(&sibling)),
+                        true => node_hash(&hash_of(&sibling), &hash_of(&mine)),
+                    };
+                    self.set_node(store, depth, path, Some(&Node::Inner(hash)));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // nodes are stored under their depth and the first depth bits of the path
+    fn node_key(&self, depth: usize, path: &Hash) -> Vec<u8> {
+        let mut key = (depth as u16).to_be_bytes().to_vec();
+        let (bytes, bits) = (depth / 8, depth % 8);
+        key.extend_from_slice(&path[..bytes]);
+        if bits > 0 {
+            key.push(path[bytes] & (0xff << (8 - bits)));
+        }
+        namespaces_with_key(&[self.nodes_namespace], &key)
+    }
+
+    fn node(&self, store: &dyn Storage, depth: usize, path: &Hash) -> StdResult<Option<Node>> {
+        if depth > DEPTH {
+            return Ok(None);
+        }
+        store
+            .get(&self.node_key(depth, path))
+            .map(|data| Node::from_slice(&data))
+            .transpose()
+    }
+
+    fn set_node(&self, store: &mut dyn Storage, depth: usize, path: &Hash, node: Option<&Node>) {
+        let key = self.node_key(depth, path);
+        match node {
+            Some(node) => store.set(&key, &node.to_vec()),
+            None => store.remove(&key),
+        }
+    }
+}
+
+impl MerkleProof {
+    /// Verifies the proof against a root returned by `MerkleMap::root`. value is the expected
+    /// value of key, or None to prove that there is no entry. This doesn't need any storage,
+    /// so it can run in another contract, or be ported to other systems.
+    pub fn verify<'a, K, T>(&self, root: &[u8], key: K, value: Option<&T>) -> StdResult<bool>
+    where
+        K: PrimaryKey<'a>,
+        T: Serialize,
+    {
+        let depth = self.siblings.len();
+        if depth > DEPTH {
+            return Err(StdError::generic_err("Too many proof siblings"));
+        }
+        let path = key_path(&key);
+        let mut hash = match (value, &self.other) {
+            (Some(value), None) => leaf_hash(&path, &Sha256::digest(&to_vec(value)?).into()),
+            (None, None) => EMPTY,
+            (None, Some(other)) => {
+                let other_path = to_hash(&other.path)?;
+                // the other entry must be where the key would be
+                if other_path == path || first_different_bit(&path, &other_path) < depth {
+                    return Ok(false);
+                }
+                leaf_hash(&other_path, &to_hash(&other.value_hash)?)
+            }
+            // this is a proof of absence
+            (Some(_), Some(_)) => return Ok(false),
+        };
+        for (i, sibling) in self.siblings.iter().enumerate() {
+            let sibling = to_hash(sibling)?;
+            hash = match get_bit(&path, depth - 1 - i) {
+                false => node_hash(&hash, &sibling),
+                true => node_hash(&sibling, &hash),
+            };
+        }
+        Ok(hash[..] == *root)
+    }
+}
+
+fn key_path<'a, K: PrimaryKey<'a>>(k: &K) -> Hash {
+    Sha256::digest(&k.joined_key()).into()
+}
+
+fn leaf_hash(path: &Hash, value_hash: &Hash) -> Hash {
+    let mut hasher = Sha256::new();
+    hasher.input([0u8]);
+    hasher.input(path);
+    hasher.input(value_hash);
+    hasher.result().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    if left == &EMPTY && right == &EMPTY {
+        return EMPTY;
+    }
+    let mut hasher = Sha256::new();
+    hasher.input([1u8]);
+    hasher.input(left);
+    hasher.input(right);
+    hasher.result().into()
+}
+
+fn to_hash(data: &[u8]) -> StdResult<Hash> {
+    if data.len() != 32 {
+        return Err(StdError::generic_err("Proof hashes must be 32 bytes"));
+    }
+    let mut hash = EMPTY;
+    hash.copy_from_slice(data);
+    Ok(hash)
+}
+
+// bits are counted from the most significant bit of the first byte
+fn get_bit(data: &[u8], index: usize) -> bool {
+    data[index / 8] & (0x80 >> (index % 8)) != 0
+}
+
+fn flip_bit(path: &Hash, index: usize) -> Hash {
+    let mut flipped = *path;
+    flipped[index / 8] ^= 0x80 >> (index % 8);
+    flipped
+}
+
+// the index of the first bit where a and b differ, DEPTH if they are equal
+fn first_different_bit(a: &Hash, b: &Hash) -> usize {
+    (0..DEPTH)
+        .find(|&i| get_bit(a, i) != get_bit(b, i))
+        .unwrap_or(DEPTH)
+}
+
+impl<'a, K, T> DescribeStorage for MerkleMap<'a, K, T> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        let mut keys = self.map.storage_keys();
+        keys.push(StorageKey::namespace(self.nodes_namespace));
+        keys
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+    #[cfg(feature = "iterator")]
+    use cosmwasm_std::Order;
+
+    const BALANCES: MerkleMap<&str, u64> = MerkleMap::new("balances", "balances__nodes");
+
+    #[test]
+    fn root_follows_entries() {
+        let mut store = MockStorage::new();
+        let empty = BALANCES.root(&store).unwrap();
+        assert_eq!(empty, Binary::from(EMPTY.to_vec()));
+
+        BALANCES.save(&mut store, "john", &100).unwrap();
+        let one = BALANCES.root(&store).unwrap();
+        assert_ne!(one, empty);
+        BALANCES.save(&mut store, "mary", &50).unwrap();
+        let two = BALANCES.root(&store).unwrap();
+        assert_ne!(two, one);
+
+        // the root only depends on the entries, not on the order of the writes
+        let mut other = MockStorage::new();
+        BALANCES.save(&mut other, "mary", &20).unwrap();
+        BALANCES.save(&mut other, "john", &100).unwrap();
+        BALANCES
+            .update(&mut other, "mary", |b| -> StdResult<_> {
+                Ok(b.unwrap_or_default() + 30)
+            })
+            .unwrap();
+        assert_eq!(BALANCES.root(&other).unwrap(), two);
+        assert_eq!(BALANCES.load(&other, "mary").unwrap(), 50);
+
+        // removing all entries removes all nodes
+        BALANCES.remove(&mut store, "mary").unwrap();
+        assert_eq!(BALANCES.root(&store).unwrap(), one);
+        BALANCES.remove(&mut store, "john").unwrap();
+        assert_eq!(BALANCES.root(&store).unwrap(), empty);
+        assert_eq!(BALANCES.may_load(&store, "john").unwrap(), None);
+        // removing a missing entry is fine
+        BALANCES.remove(&mut store, "john").unwrap();
+        #[cfg(feature = "iterator")]
+        assert_eq!(store.range(None, None, Order::Ascending).count(), 0);
+    }
+
+    fn user(i: usize) -> String {
+        format!("user{}", i)
+    }
+
+    #[test]
+    fn tree_is_compact() {
+        let mut store = MockStorage::new();
+        let users: Vec<_> = (0..200).map(user).collect();
+        for (i, user) in users.iter().enumerate() {
+            BALANCES.save(&mut store, user, &(i as u64)).unwrap();
+        }
+        let root = BALANCES.root(&store).unwrap();
+
+        // the proofs are about log2(200) = 8 levels deep, rather than 256
+        let depths: Vec<_> = users
+            .iter()
+            .map(|user| BALANCES.prove(&store, user).unwrap().siblings.len())
+            .collect();
+        let average = depths.iter().sum::<usize>() / depths.len();
+        assert!((6..=12).contains(&average), "average depth {}", average);
+        assert!(depths.iter().all(|d| *d < 32));
+
+        // a single leaf is the root itself
+        let mut single = MockStorage::new();
+        BALANCES.save(&mut single, "john", &100).unwrap();
+        let proof = BALANCES.prove(&single, "john").unwrap();
+        assert_eq!(proof.siblings.len(), 0);
+        assert!(proof
+            .verify(&BALANCES.root(&single).unwrap(), "john", Some(&100u64))
+            .unwrap());
+
+        // the same entries written in another order, with removed ones in between,
+        // give the same root and store the same nodes
+        let mut other = MockStorage::new();
+        for (i, user) in users.iter().enumerate().rev() {
+            BALANCES.save(&mut other, user, &(i as u64 + 1)).unwrap();
+            BALANCES
+                .save(&mut other, &format!("gone{}", i), &7)
+                .unwrap();
+        }
+        for (i, user) in users.iter().enumerate() {
+            BALANCES.save(&mut other, user, &(i as u64)).unwrap();
+            BALANCES.remove(&mut other, &format!("gone{}", i)).unwrap();
+        }
+        assert_eq!(BALANCES.root(&other).unwrap(), root);
+        #[cfg(feature = "iterator")]
+        assert!(store.range(None, None, Order::Ascending).eq(other.range(
+            None,
+            None,
+            Order::Ascending
+        )));
+
+        // and removing all of them leaves nothing behind
+        for user in users.iter() {
+            BALANCES.remove(&mut other, user).unwrap();
+        }
+        assert_eq!(BALANCES.root(&other).unwrap(), Binary::from(EMPTY.to_vec()));
+        #[cfg(feature = "iterator")]
+        assert_eq!(other.range(None, None, Order::Ascending).count(), 0);
+    }
+
+    #[test]
+    fn prove_and_verify() {
+        let mut store = MockStorage::new();
+        let users = ["anna", "bert", "carl", "dora", "emil"];
+        for (i, user) in users.iter().enumerate() {
+            BALANCES.save(&mut store, user, &(i as u64 * 10)).unwrap();
+        }
+        let root = BALANCES.root(&store).unwrap();
+
+        for (i, user) in users.iter().enumerate() {
+            let proof = BALANCES.prove(&store, user).unwrap();
+            assert!(!proof.siblings.is_empty());
+            assert_eq!(proof.other, None);
+            let value = i as u64 * 10;
+            assert!(proof.verify(&root, *user, Some(&value)).unwrap());
+            // wrong value, wrong key, or claiming absence all fail
+            assert!(!proof.verify(&root, *user, Some(&(value + 1))).unwrap());
+            assert!(!proof.verify(&root, "zeno", Some(&value)).unwrap());
+            assert!(!proof.verify::<_, u64>(&root, *user, None).unwrap());
+        }
+
+        // proofs of absence, ending at an empty place or at the leaf of another entry
+        let mut others = 0;
+        for i in 0..20 {
+            let missing = user(i);
+            let proof = BALANCES.prove(&store, &missing).unwrap();
+            if proof.other.is_some() {
+                others += 1;
+            }
+            assert!(proof
+                .verify::<_, u64>(&root, missing.as_str(), None)
+                .unwrap());
+            assert!(!proof.verify(&root, missing.as_str(), Some(&0u64)).unwrap());
+        }
+        assert!(others > 0);
+
+        // a proof doesn't hold for a later root
+        let proof = BALANCES.prove(&store, "anna").unwrap();
+        BALANCES.save(&mut store, "fred", &60).unwrap();
+        let new_root = BALANCES.root(&store).unwrap();
+        assert!(!proof.verify(&new_root, "anna", Some(&0u64)).unwrap());
+        let proof = BALANCES.prove(&store, "anna").unwrap();
+        assert!(proof.verify(&new_root, "anna", Some(&0u64)).unwrap());
+    }
+
+    #[test]
+    fn malformed_proofs() {
+        let mut store = MockStorage::new();
+        for i in 0..10 {
+            BALANCES.save(&mut store, &user(i), &100).unwrap();
+        }
+        let root = BALANCES.root(&store).unwrap();
+        let proof = BALANCES.prove(&store, "user1").unwrap();
+        assert!(proof.verify(&root, "user1", Some(&100u64)).unwrap());
+
+        let mut short = proof.clone();
+        short.siblings.pop();
+        assert!(!short.verify(&root, "user1", Some(&100u64)).unwrap());
+
+        let mut long = proof.clone();
+        long.siblings.push(Binary::from(vec![1; 32]));
+        assert!(!long.verify(&root, "user1", Some(&100u64)).unwrap());
+
+        let mut bad_sibling = proof.clone();
+        bad_sibling.siblings[0] = Binary::from(vec![0; 4]);
+        bad_sibling
+            .verify(&root, "user1", Some(&100u64))
+            .unwrap_err();
+
+        let mut too_long = proof.clone();
+        too_long.siblings = vec![Binary::from(EMPTY.to_vec()); DEPTH + 1];
+        too_long.verify(&root, "user1", Some(&100u64)).unwrap_err();
+
+        // another entry can't be passed off as a proof of absence
+        let mut fake = proof;
+        fake.other = Some(ProofLeaf {
+            path: Binary::from(key_path(&"user1").to_vec()),
+            value_hash: Binary::from(Sha256::digest(&to_vec(&100u64).unwrap()).to_vec()),
+        });
+        assert!(!fake.verify::<_, u64>(&root, "user1", None).unwrap());
+        let proof = BALANCES.prove(&store, "user2").unwrap();
+        let mut moved = BALANCES.prove(&store, "user1").unwrap();
+        moved.other = Some(ProofLeaf {
+            path: Binary::from(key_path(&"user2").to_vec()),
+            value_hash: Binary::from(Sha256::digest(&to_vec(&100u64).unwrap()).to_vec()),
+        });
+        moved.siblings = proof.siblings;
+        assert!(!moved.verify::<_, u64>(&root, "user1", None).unwrap());
+    }
+}
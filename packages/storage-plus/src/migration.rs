@@ -0,0 +1,368 @@
+// this module requires iterator to be useful at all
+#![cfg(feature = "iterator")]
+
+use schemars::JsonSchema;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Order, StdResult, Storage};
+
+use crate::keys::PrimaryKey;
+use crate::path::Path;
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::{Item, Map, Prefix};
+
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq, JsonSchema)]
+pub struct MigrationProgress {
+    /// number of entries moved by `migrate` (or `migrate_with`) so far
+    pub migrated: u64,
+    /// set once the old map is empty
+    pub done: bool,
+}
+
+/// MapMigration moves a Map to a new namespace, converting every value from T to U.
+///
+/// Entries can be moved in bounded batches with `migrate`, called once in the `migrate`
+/// entry point and then from any later transaction (eg. in every `execute`) until it is done.
+/// Until then, reads go through the migration: `load` returns the new value if it was moved,
+/// and otherwise upgrades the old one, and `upgrade` does the same but also stores the result.
+///
+/// Old and new namespace must be different, so we always know which format an entry is in.
+pub struct MapMigration<'a, K, T, U> {
+    old_namespace: &'a [u8],
+    old: Map<'a, K, T>,
+    new_namespace: &'a [u8],
+    new: Map<'a, K, U>,
+    progress: Item<'a, MigrationProgress>,
+    upgrade_fn: fn(T) -> StdResult<U>,
+}
+
+impl<'a, K, T, U> MapMigration<'a, K, T, U> {
+    /// Usage: MapMigration::new("tokens", "tokens_v2", "tokens__migration", |old| Ok(old.into()))
+    pub fn new(
+        old_namespace: &'a str,
+        new_namespace: &'a str,
+        progress_key: &'a str,
+        upgrade_fn: fn(T) -> StdResult<U>,
+    ) -> Self {
+        MapMigration {
+            old_namespace: old_namespace.as_bytes(),
+            old: Map::new(old_namespace),
+            new_namespace: new_namespace.as_bytes(),
+            new: Map::new(new_namespace),
+            progress: Item::new(progress_key),
+            upgrade_fn,
+        }
+    }
+}
+
+impl<'a, K, T, U> MapMigration<'a, K, T, U>
+where
+    K: PrimaryKey<'a> + Clone,
+    T: Serialize + DeserializeOwned,
+    U: Serialize + DeserializeOwned,
+{
+    /// Returns the new value, upgrading the old one if it was not moved yet.
+    /// This doesn't write anything, so it can be used in queries.
+    pub fn may_load(&self, store: &dyn Storage, k: K) -> StdResult<Option<U>> {
+        match self.new.may_load(store, k.clone())? {
+            Some(v) => Ok(Some(v)),
+            None => self
+                .old
+                .may_load(store, k)?
+                .map(self.upgrade_fn)
+                .transpose(),
+        }
+    }
+
+    /// load will return an error if no data is set at the given key (in either map), or on parse error
+    pub fn load(&self, store: &dyn Storage, k: K) -> StdResult<U> {
+        match self.new.may_load(store, k.clone())? {
+            Some(v) => Ok(v),
+            None => (self.upgrade_fn)(self.old.load(store, k)?),
+        }
+    }
+
+    /// Like may_load, but also moves the entry to the new map on first access (read-through)
+    pub fn upgrade(&self, store: &mut dyn Storage, k: K) -> StdResult<Option<U>> {
+        if let Some(v) = self.new.may_load(store, k.clone())? {
+            return Ok(Some(v));
+        }
+        match self.old.may_load(store, k.clone())? {
+            Some(old) => {
+                let v = (self.upgrade_fn)(old)?;
+                self.save(store, k, &v)?;
+                Ok(Some(v))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Saves to the new map, and removes any old entry under the same key
+    pub fn save(&self, store: &mut dyn Storage, k: K, data: &U) -> StdResult<()> {
+        self.new.save(store, k.clone(), data)?;
+        self.old.remove(store, k);
+        Ok(())
+    }
+
+    /// Removes the entry from both maps
+    pub fn remove(&self, store: &mut dyn Storage, k: K) {
+        self.new.remove(store, k.clone());
+        self.old.remove(store, k);
+    }
+
+    /// Moves up to limit entries to the new map.
+    /// Returns true once all entries have been moved.
+    pub fn migrate(&self, store: &mut dyn Storage, limit: usize) -> StdResult<bool> {
+        let new_namespace = self.new_namespace;
+        let upgrade_fn = self.upgrade_fn;
+        self.migrate_with(store, limit, |store, key, old| {
+            Path::<U>::new(new_namespace, &[&key]).save(store, &upgrade_fn(old)?)
+        })
+    }
+
+    /// Like migrate, but calls migrate_fn(store, key, old_value) for each entry rather than
+    /// storing the upgraded value under the same key. Use this to change the layout, eg. to split
+    /// one entry into many. The key is the raw (joined) key, and the old entry is already removed
+    /// when migrate_fn is called. Do not write to the old namespace from migrate_fn.
+    pub fn migrate_with<F>(
+        &self,
+        store: &mut dyn Storage,
+        limit: usize,
+        mut migrate_fn: F,
+    ) -> StdResult<bool>
+    where
+        F: FnMut(&mut dyn Storage, Vec<u8>, T) -> StdResult<()>,
+    {
+        let mut progress = self.progress(store)?;
+        if progress.done {
+            return Ok(true);
+        }
+
+        // moved entries are removed, so we always start from the beginning
+        let batch: Vec<_> = self
+            .old_entries()
+            .range(store, None, None, Order::Ascending)
+            .take(limit)
+            .collect::<StdResult<_>>()?;
+        for (key, old) in batch {
+            Path::<T>::new(self.old_namespace, &[&key]).remove(store);
+            migrate_fn(store, key, old)?;
+            progress.migrated += 1;
+        }
+
+        progress.done = self.old_entries().is_empty(store);
+        self.progress.save(store, &progress)?;
+        Ok(progress.done)
+    }
+
+    /// Returns how many entries were moved so far, and if the migration is done
+    pub fn progress(&self, store: &dyn Storage) -> StdResult<MigrationProgress> {
+        Ok(self.progress.may_load(store)?.unwrap_or_default())
+    }
+
+    /// Returns true once all entries have been moved by migrate
+    pub fn is_done(&self, store: &dyn Storage) -> StdResult<bool> {
+        Ok(self.progress(store)?.done)
+    }
+
+    fn old_entries(&self) -> Prefix<T> {
+        Prefix::new(self.old_namespace, &[])
+    }
+}
+
+impl<'a, K, T, U> DescribeStorage for MapMigration<'a, K, T, U> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        let mut keys = self.old.storage_keys();
+        keys.extend(self.new.storage_keys());
+        keys.extend(self.progress.storage_keys());
+        keys
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{StorageRegistry, U64Key};
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::StdError;
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct OldToken {
+        owner: String,
+    }
+
+    #[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+    struct Token {
+        owner: String,
+        description: String,
+    }
+
+    const OLD_TOKENS: Map<&str, OldToken> = Map::new("tokens");
+
+    fn upgrade_token(old: OldToken) -> StdResult<Token> {
+        Ok(Token {
+            owner: old.owner,
+            description: "".to_string(),
+        })
+    }
+
+    fn migration<'a>() -> MapMigration<'a, &'a str, OldToken, Token> {
+        MapMigration::new("tokens", "tokens_v2", "tokens__migration", upgrade_token)
+    }
+
+    fn token(owner: &str) -> Token {
+        Token {
+            owner: owner.to_string(),
+            description: "".to_string(),
+        }
+    }
+
+    fn save_old(store: &mut dyn Storage, ids: &[&str]) {
+        for id in ids {
+            let old = OldToken {
+                owner: format!("owner-{}", id),
+            };
+            OLD_TOKENS.save(store, id, &old).unwrap();
+        }
+    }
+
+    #[test]
+    fn read_through() {
+        let mut store = MockStorage::new();
+        save_old(&mut store, &["1", "2"]);
+        let tokens = migration();
+
+        // loads upgrade, but don't write
+        assert_eq!(tokens.load(&store, "1").unwrap(), token("owner-1"));
+        assert_eq!(tokens.may_load(&store, "3").unwrap(), None);
+        match tokens.load(&store, "3").unwrap_err() {
+            StdError::NotFound { .. } => {}
+            e => panic!("Unexpected error {}", e),
+        }
+        assert!(OLD_TOKENS.may_load(&store, "1").unwrap().is_some());
+
+        // upgrade moves the entry
+        assert_eq!(
+            tokens.upgrade(&mut store, "1").unwrap(),
+            Some(token("owner-1"))
+        );
+        assert_eq!(OLD_TOKENS.may_load(&store, "1").unwrap(), None);
+        assert_eq!(tokens.upgrade(&mut store, "3").unwrap(), None);
+
+        // save overrides any old entry
+        let mut updated = token("owner-2");
+        updated.description = "updated".to_string();
+        tokens.save(&mut store, "2", &updated).unwrap();
+        assert_eq!(tokens.load(&store, "2").unwrap(), updated);
+        assert!(OLD_TOKENS.is_empty(&store));
+
+        tokens.remove(&mut store, "2");
+        assert_eq!(tokens.may_load(&store, "2").unwrap(), None);
+        // nothing left to migrate
+        assert!(tokens.migrate(&mut store, 10).unwrap());
+        assert_eq!(tokens.progress(&store).unwrap().migrated, 0);
+    }
+
+    #[test]
+    fn migrate_in_batches() {
+        let mut store = MockStorage::new();
+        save_old(&mut store, &["1", "2", "3", "4", "5"]);
+        let tokens = migration();
+
+        // read through one entry first
+        tokens.upgrade(&mut store, "4").unwrap();
+
+        assert!(!tokens.migrate(&mut store, 2).unwrap());
+        assert_eq!(
+            tokens.progress(&store).unwrap(),
+            MigrationProgress {
+                migrated: 2,
+                done: false
+            }
+        );
+        assert!(!tokens.is_done(&store).unwrap());
+        // the last batch might be smaller than the limit
+        assert!(tokens.migrate(&mut store, 2).unwrap());
+        assert!(tokens.is_done(&store).unwrap());
+        assert_eq!(tokens.progress(&store).unwrap().migrated, 4);
+        assert!(OLD_TOKENS.is_empty(&store));
+
+        // all entries are in the new map
+        let new: Map<&str, Token> = Map::new("tokens_v2");
+        let all: StdResult<Vec<_>> = new.range(&store, None, None, Order::Ascending).collect();
+        let ids: Vec<_> = all.unwrap().into_iter().map(|(k, _)| k).collect();
+        assert_eq!(
+            ids,
+            vec![
+                b"1".to_vec(),
+                b"2".to_vec(),
+                b"3".to_vec(),
+                b"4".to_vec(),
+                b"5".to_vec()
+            ]
+        );
+        assert_eq!(new.load(&store, "5").unwrap(), token("owner-5"));
+
+        // done is done
+        assert!(tokens.migrate(&mut store, 2).unwrap());
+        assert_eq!(tokens.progress(&store).unwrap().migrated, 4);
+    }
+
+    #[test]
+    fn migrate_with_new_layout() {
+        // one list of claims per address becomes one entry per claim
+        const OLD_CLAIMS: Map<&[u8], Vec<u64>> = Map::new("claims");
+        const CLAIMS: Map<(&[u8], U64Key), u64> = Map::new("claims_v2");
+        let claims: MapMigration<&[u8], Vec<u64>, u64> =
+            MapMigration::new("claims", "claims_v2", "claims__migration", |_| {
+                Err(StdError::generic_err("not supported"))
+            });
+
+        let mut store = MockStorage::new();
+        OLD_CLAIMS.save(&mut store, b"john", &vec![10, 20]).unwrap();
+        OLD_CLAIMS.save(&mut store, b"mary", &vec![30]).unwrap();
+
+        let done = claims
+            .migrate_with(&mut store, 10, |store, addr, amounts| {
+                for (i, amount) in amounts.into_iter().enumerate() {
+                    CLAIMS.save(store, (&addr, U64Key::new(i as u64)), &amount)?;
+                }
+                Ok(())
+            })
+            .unwrap();
+        assert!(done);
+        assert!(OLD_CLAIMS.is_empty(&store));
+
+        let john: StdResult<Vec<_>> = CLAIMS
+            .prefix(b"john")
+            .range(&store, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(john.unwrap().len(), 2);
+        assert_eq!(CLAIMS.load(&store, (b"mary", U64Key::new(0))).unwrap(), 30);
+    }
+
+    #[test]
+    fn upgrade_errors_are_returned() {
+        let mut store = MockStorage::new();
+        save_old(&mut store, &["1"]);
+        let broken: MapMigration<&str, OldToken, Token> =
+            MapMigration::new("tokens", "tokens_v2", "tokens__migration", |_| {
+                Err(StdError::generic_err("broken"))
+            });
+        broken.load(&store, "1").unwrap_err();
+        broken.migrate(&mut store, 1).unwrap_err();
+    }
+
+    #[test]
+    fn registry_lists_all_namespaces() {
+        let registry = StorageRegistry::new().register("tokens", &migration());
+        let names: Vec<_> = registry
+            .entries()
+            .iter()
+            .map(|e| e.key.name.as_str())
+            .collect();
+        assert_eq!(names, vec!["tokens", "tokens_v2", "tokens__migration"]);
+        registry.assert_no_collisions();
+    }
+}
@@ -0,0 +1,210 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(feature = "iterator")]
+use cosmwasm_std::{Binary, Order};
+use cosmwasm_std::{StdError, StdResult, Storage};
+
+use crate::keys::{Prefixer, PrimaryKey};
+use crate::map::Map;
+#[cfg(feature = "iterator")]
+use crate::pagination::{paginate_de, Page};
+#[cfg(feature = "iterator")]
+use crate::prefix::{Bound, DeserializedKV};
+use crate::registry::{DescribeStorage, StorageKey};
+
+/// NestedMap is a map of maps, eg. the allowances of every owner, keyed by spender.
+/// It is stored just like a `Map<(K1, K2), T>`, so it can replace one without a migration,
+/// but also allows to list, count and remove all entries of one outer key.
+pub struct NestedMap<'a, K1, K2, T> {
+    map: Map<'a, (K1, K2), T>,
+}
+
+impl<'a, K1, K2, T> NestedMap<'a, K1, K2, T> {
+    pub const fn new(namespace: &'a str) -> Self {
+        NestedMap {
+            map: Map::new(namespace),
+        }
+    }
+}
+
+impl<'a, K1, K2, T> NestedMap<'a, K1, K2, T>
+where
+    T: Serialize + DeserializeOwned,
+    K1: PrimaryKey<'a> + Prefixer<'a>,
+    K2: PrimaryKey<'a>,
+{
+    /// The underlying map, eg. for `prefix_range` over several outer keys
+    pub fn map(&self) -> &Map<'a, (K1, K2), T> {
+        &self.map
+    }
+
+    pub fn save(&self, store: &mut dyn Storage, k1: K1, k2: K2, data: &T) -> StdResult<()> {
+        self.map.save(store, (k1, k2), data)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, k1: K1, k2: K2) {
+        self.map.remove(store, (k1, k2))
+    }
+
+    /// load will return an error if no data is set at the given keys, or on parse error
+    pub fn load(&self, store: &dyn Storage, k1: K1, k2: K2) -> StdResult<T> {
+        self.map.load(store, (k1, k2))
+    }
+
+    /// may_load will parse the data stored at the keys if present, returns Ok(None) if no data there.
+    pub fn may_load(&self, store: &dyn Storage, k1: K1, k2: K2) -> StdResult<Option<T>> {
+        self.map.may_load(store, (k1, k2))
+    }
+
+    /// See `Map::update`
+    pub fn update<A, E>(&self, store: &mut dyn Storage, k1: K1, k2: K2, action: A) -> Result<T, E>
+    where
+        A: FnOnce(Option<T>) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        self.map.update(store, (k1, k2), action)
+    }
+}
+
+#[cfg(feature = "iterator")]
+impl<'a, K1, K2, T> NestedMap<'a, K1, K2, T>
+where
+    T: Serialize + DeserializeOwned,
+    K1: PrimaryKey<'a> + Prefixer<'a>,
+    K2: PrimaryKey<'a>,
+{
+    /// Iterates over all entries of k1, with the inner keys deserialized
+    /// (eg. `&str` as `String`). The bounds apply to the inner key.
+    pub fn entries_of<'c>(
+        &self,
+        store: &'c dyn Storage,
+        k1: K1,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<DeserializedKV<K2, T>>> + 'c>
+    where
+        T: 'c,
+        K2::Output: 'c,
+    {
+        self.map.prefix(k1).range_de(store, min, max, order)
+    }
+
+    /// Returns the number of entries of k1. This iterates over all of them,
+    /// but doesn't deserialize the values.
+    pub fn count_of(&self, store: &dyn Storage, k1: K1) -> usize {
+        self.map
+            .prefix(k1)
+            .keys(store, None, None, Order::Ascending)
+            .count()
+    }
+
+    /// Removes the entries of k1, at most limit of them if given.
+    /// Returns true if there are entries left to remove.
+    pub fn remove_all_of(&self, store: &mut dyn Storage, k1: K1, limit: Option<usize>) -> bool {
+        self.map.prefix(k1).clear(store, limit)
+    }
+
+    /// Returns a page of the entries of k1, starting after the given inner key.
+    /// The limit is applied like in `paginate`, and the cursor in `next` can be ignored,
+    /// as the last inner key is the start of the next page.
+    pub fn page_of(
+        &self,
+        store: &dyn Storage,
+        k1: K1,
+        start_after: Option<K2>,
+        limit: Option<u32>,
+        order: Order,
+    ) -> StdResult<Page<(K2::Output, T)>> {
+        let cursor = start_after.map(|k2| Binary::from(k2.joined_key()));
+        paginate_de(store, &self.map.prefix(k1), cursor, limit, order)
+    }
+}
+
+impl<'a, K1, K2, T> DescribeStorage for NestedMap<'a, K1, K2, T> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        self.map.storage_keys()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    const ALLOWANCES: NestedMap<&[u8], &str, u64> = NestedMap::new("allowances");
+
+    #[test]
+    fn save_and_load() {
+        let mut store = MockStorage::new();
+        ALLOWANCES
+            .save(&mut store, b"owner", "spender", &100)
+            .unwrap();
+        assert_eq!(ALLOWANCES.load(&store, b"owner", "spender").unwrap(), 100);
+        assert_eq!(
+            ALLOWANCES.may_load(&store, b"owner", "other").unwrap(),
+            None
+        );
+
+        let updated = ALLOWANCES
+            .update(&mut store, b"owner", "spender", |a| -> StdResult<_> {
+                Ok(a.unwrap_or_default() + 50)
+            })
+            .unwrap();
+        assert_eq!(updated, 150);
+
+        // stored like the composite key map
+        let map: Map<(&[u8], &str), u64> = Map::new("allowances");
+        assert_eq!(map.load(&store, (b"owner", "spender")).unwrap(), 150);
+
+        ALLOWANCES.remove(&mut store, b"owner", "spender");
+        assert_eq!(
+            ALLOWANCES.may_load(&store, b"owner", "spender").unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn entries_of_one_owner() {
+        let mut store = MockStorage::new();
+        ALLOWANCES.save(&mut store, b"alice", "carl", &3).unwrap();
+        ALLOWANCES.save(&mut store, b"alice", "bert", &2).unwrap();
+        ALLOWANCES.save(&mut store, b"alice", "dora", &4).unwrap();
+        ALLOWANCES.save(&mut store, b"bob", "anna", &1).unwrap();
+
+        let all: StdResult<Vec<_>> = ALLOWANCES
+            .entries_of(&store, b"alice", None, None, Order::Ascending)
+            .collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                ("bert".to_string(), 2),
+                ("carl".to_string(), 3),
+                ("dora".to_string(), 4),
+            ]
+        );
+        assert_eq!(ALLOWANCES.count_of(&store, b"alice"), 3);
+        assert_eq!(ALLOWANCES.count_of(&store, b"bob"), 1);
+        assert_eq!(ALLOWANCES.count_of(&store, b"carl"), 0);
+
+        // typed pagination
+        let page = ALLOWANCES
+            .page_of(&store, b"alice", Some("bert"), Some(1), Order::Ascending)
+            .unwrap();
+        assert_eq!(page.items, vec![("carl".to_string(), 3)]);
+        assert!(page.next.is_some());
+        let page = ALLOWANCES
+            .page_of(&store, b"alice", Some("carl"), None, Order::Ascending)
+            .unwrap();
+        assert_eq!(page.items, vec![("dora".to_string(), 4)]);
+        assert_eq!(page.next, None);
+
+        // remove in batches, without touching other owners
+        assert!(ALLOWANCES.remove_all_of(&mut store, b"alice", Some(2)));
+        assert!(!ALLOWANCES.remove_all_of(&mut store, b"alice", Some(2)));
+        assert_eq!(ALLOWANCES.count_of(&store, b"alice"), 0);
+        assert_eq!(ALLOWANCES.load(&store, b"bob", "anna").unwrap(), 1);
+    }
+}
@@ -0,0 +1,246 @@
+//! Pagination for enumerable queries, with the same semantics for all storage types
+//! that return a `Prefix`: `Map`, `SnapshotMap`, `IndexedMap` and its indexes.
+//!
+//! Every page comes with an opaque cursor, which is passed back as `start_after` to get
+//! the next page. It is the raw key of the last entry (below the prefix), so it also works
+//! for indexes, which return the primary key rather than their own key.
+#![cfg(feature = "iterator")]
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use cosmwasm_std::{Binary, Order, StdResult, Storage, KV};
+
+use crate::de::KeyDeserialize;
+use crate::prefix::DeserializedKV;
+use crate::{Bound, Prefix};
+
+/// Number of items returned if no limit is given
+pub const DEFAULT_LIMIT: u32 = 10;
+/// Larger limits are reduced to this
+pub const MAX_LIMIT: u32 = 30;
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Page<I> {
+    pub items: Vec<I>,
+    /// Pass this as `start_after` to get the next page. None if this is the last page
+    pub next: Option<Binary>,
+}
+
+/// Returns up to limit entries of the prefix, starting after the given cursor (in the given order).
+/// The limit defaults to DEFAULT_LIMIT and is capped at MAX_LIMIT.
+///
+/// Usage, for a simple map: paginate(store, &BALANCES.prefix(()), start_after, limit, Order::Ascending)
+pub fn paginate<T, K>(
+    store: &dyn Storage,
+    prefix: &Prefix<T, K>,
+    start_after: Option<Binary>,
+    limit: Option<u32>,
+    order: Order,
+) -> StdResult<Page<KV<T>>>
+where
+    T: Serialize + DeserializeOwned,
+{
+    let limit = limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT) as usize;
+    let start = start_after.map(|cursor| Bound::Exclusive(cursor.into()));
+    let (min, max) = match order {
+        Order::Ascending => (start, None),
+        Order::Descending => (None, start),
+    };
+
+    let mut iter = prefix.range_raw(store, min, max, order);
+    let mut items = Vec::with_capacity(limit);
+    let mut last = None;
+    for (raw, kv) in iter.by_ref().take(limit) {
+        items.push(kv?);
+        last = Some(raw);
+    }
+    // only return a cursor if there is anything left
+    let next = match iter.next() {
+        Some(_) => last.map(Binary::from),
+        None => None,
+    };
+    Ok(Page { items, next })
+}
+
+/// Like `paginate`, but deserializes the keys into `K::Output`
+pub fn paginate_de<T, K>(
+    store: &dyn Storage,
+    prefix: &Prefix<T, K>,
+    start_after: Option<Binary>,
+    limit: Option<u32>,
+    order: Order,
+) -> StdResult<Page<DeserializedKV<K, T>>>
+where
+    T: Serialize + DeserializeOwned,
+    K: KeyDeserialize,
+{
+    let page = paginate(store, prefix, start_after, limit, order)?;
+    let items = page
+        .items
+        .into_iter()
+        .map(|(k, v)| Ok((K::from_vec(k)?, v)))
+        .collect::<StdResult<_>>()?;
+    Ok(Page {
+        items,
+        next: page.next,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    use crate::indexes::index_string;
+    use crate::{Index, IndexList, IndexedMap, Map, MultiIndex, PkOwned, SnapshotMap, Strategy};
+
+    const BALANCES: Map<&str, u64> = Map::new("balances");
+
+    fn fill(store: &mut MockStorage, count: u64) {
+        for i in 0..count {
+            BALANCES
+                .save(store, &format!("addr{:02}", i), &(i * 10))
+                .unwrap();
+        }
+    }
+
+    // walks through all pages, returning the keys of every page
+    fn all_pages(store: &MockStorage, limit: u32, order: Order) -> Vec<Vec<String>> {
+        let mut pages = vec![];
+        let mut cursor = None;
+        loop {
+            let page =
+                paginate_de(store, &BALANCES.prefix(()), cursor, Some(limit), order).unwrap();
+            pages.push(page.items.into_iter().map(|(k, _)| k).collect());
+            match page.next {
+                Some(next) => cursor = Some(next),
+                None => return pages,
+            }
+        }
+    }
+
+    #[test]
+    fn paginate_map() {
+        let mut store = MockStorage::new();
+        fill(&mut store, 7);
+
+        let pages = all_pages(&store, 3, Order::Ascending);
+        assert_eq!(
+            pages,
+            vec![
+                vec!["addr00", "addr01", "addr02"],
+                vec!["addr03", "addr04", "addr05"],
+                vec!["addr06"],
+            ]
+        );
+
+        let pages = all_pages(&store, 4, Order::Descending);
+        assert_eq!(
+            pages,
+            vec![
+                vec!["addr06", "addr05", "addr04", "addr03"],
+                vec!["addr02", "addr01", "addr00"],
+            ]
+        );
+
+        // an exact fit doesn't return a cursor to an empty page
+        let pages = all_pages(&store, 7, Order::Ascending);
+        assert_eq!(pages.len(), 1);
+    }
+
+    #[test]
+    fn limits_are_applied() {
+        let mut store = MockStorage::new();
+        fill(&mut store, 40);
+        let prefix = BALANCES.prefix(());
+
+        let page = paginate(&store, &prefix, None, None, Order::Ascending).unwrap();
+        assert_eq!(page.items.len(), DEFAULT_LIMIT as usize);
+        assert_eq!(page.items[0], (b"addr00".to_vec(), 0));
+        let page = paginate(&store, &prefix, None, Some(100), Order::Ascending).unwrap();
+        assert_eq!(page.items.len(), MAX_LIMIT as usize);
+        // a page is never empty, as long as there are items left
+        let page = paginate(&store, &prefix, None, Some(0), Order::Ascending).unwrap();
+        assert_eq!(page.items.len(), 1);
+        assert!(page.next.is_some());
+
+        let empty = Map::<&str, u64>::new("empty").prefix(());
+        let page = paginate(&store, &empty, None, None, Order::Ascending).unwrap();
+        assert_eq!(
+            page,
+            Page {
+                items: vec![],
+                next: None
+            }
+        );
+    }
+
+    struct DataIndexes<'a> {
+        owner: MultiIndex<'a, (PkOwned, PkOwned), String>,
+    }
+
+    impl<'a> IndexList<String> for DataIndexes<'a> {
+        fn get_indexes(&'_ self) -> Box<dyn Iterator<Item = &'_ dyn Index<String>> + '_> {
+            let v: Vec<&dyn Index<String>> = vec![&self.owner];
+            Box::new(v.into_iter())
+        }
+    }
+
+    #[test]
+    fn paginate_index() {
+        let mut store = MockStorage::new();
+        let indexes = DataIndexes {
+            // (owner, kind), the owner is the first word of the value
+            owner: MultiIndex::new(
+                |d| {
+                    let mut parts = d.split(' ');
+                    let owner = parts.next().unwrap();
+                    (index_string(owner), index_string(parts.next().unwrap()))
+                },
+                "tokens",
+                "tokens__owner",
+            ),
+        };
+        let map: IndexedMap<&str, String, _> = IndexedMap::new("tokens", indexes);
+        map.save(&mut store, "1", &"alice cat".to_string()).unwrap();
+        map.save(&mut store, "2", &"alice dog".to_string()).unwrap();
+        map.save(&mut store, "3", &"bob cat".to_string()).unwrap();
+        map.save(&mut store, "4", &"alice ant".to_string()).unwrap();
+
+        // all tokens of alice, ordered by kind. The cursor refers to the index entry,
+        // so the following pages continue in index order
+        let prefix = map.idx.owner.sub_prefix(index_string("alice"));
+        let page = paginate(&store, &prefix, None, Some(2), Order::Ascending).unwrap();
+        let pks: Vec<_> = page.items.iter().map(|(k, _)| k.clone()).collect();
+        assert_eq!(pks, vec![b"4".to_vec(), b"1".to_vec()]);
+
+        let page = paginate(&store, &prefix, page.next, Some(2), Order::Ascending).unwrap();
+        assert_eq!(page.items, vec![(b"2".to_vec(), "alice dog".to_string())]);
+        assert_eq!(page.next, None);
+    }
+
+    #[test]
+    fn paginate_snapshot_map() {
+        let mut store = MockStorage::new();
+        let members: SnapshotMap<&str, u64> = SnapshotMap::new(
+            "members",
+            "members__checkpoints",
+            "members__changelog",
+            Strategy::EveryBlock,
+        );
+        members.save(&mut store, "carl", &3, 1).unwrap();
+        members.save(&mut store, "anna", &1, 1).unwrap();
+        members.save(&mut store, "bert", &2, 2).unwrap();
+
+        let prefix = members.prefix(());
+        let page = paginate_de(&store, &prefix, None, Some(2), Order::Ascending).unwrap();
+        assert_eq!(
+            page.items,
+            vec![("anna".to_string(), 1), ("bert".to_string(), 2)]
+        );
+        let page = paginate_de(&store, &prefix, page.next, Some(2), Order::Ascending).unwrap();
+        assert_eq!(page.items, vec![("carl".to_string(), 3)]);
+        assert_eq!(page.next, None);
+    }
+}
@@ -2,12 +2,13 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::marker::PhantomData;
 
-use crate::helpers::{may_deserialize, must_deserialize, nested_namespaces_with_key};
-use cosmwasm_std::{to_vec, StdError, StdResult, Storage};
+use crate::helpers::{may_deserialize_with, must_deserialize_with, nested_namespaces_with_key};
+use crate::serializer::{Json, Serializer};
+use cosmwasm_std::{StdError, StdResult, Storage};
 use std::ops::Deref;
 
 #[derive(Debug, Clone)]
-pub struct Path<T>
+pub struct Path<T, S = Json>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -15,9 +16,10 @@ where
     storage_key: Vec<u8>,
     // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
     data: PhantomData<T>,
+    serializer: PhantomData<S>,
 }
 
-impl<T> Deref for Path<T>
+impl<T, S> Deref for Path<T, S>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -28,9 +30,10 @@ where
     }
 }
 
-impl<T> Path<T>
+impl<T, S> Path<T, S>
 where
     T: Serialize + DeserializeOwned,
+    S: Serializer<T>,
 {
     pub fn new(namespace: &[u8], keys: &[&[u8]]) -> Self {
         let l = keys.len();
@@ -39,12 +42,13 @@ where
         Path {
             storage_key,
             data: PhantomData,
+            serializer: PhantomData,
         }
     }
 
     /// save will serialize the model and store, returns an error on serialization issues
     pub fn save(&self, store: &mut dyn Storage, data: &T) -> StdResult<()> {
-        store.set(&self.storage_key, &to_vec(data)?);
+        store.set(&self.storage_key, &S::to_vec(data)?);
         Ok(())
     }
 
@@ -55,14 +59,14 @@ where
     /// load will return an error if no data is set at the given key, or on parse error
     pub fn load(&self, store: &dyn Storage) -> StdResult<T> {
         let value = store.get(&self.storage_key);
-        must_deserialize(&value)
+        must_deserialize_with::<T, S>(&value)
     }
 
     /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
     /// returns an error on issues parsing
     pub fn may_load(&self, store: &dyn Storage) -> StdResult<Option<T>> {
         let value = store.get(&self.storage_key);
-        may_deserialize(&value)
+        may_deserialize_with::<T, S>(&value)
     }
 
     /// Loads the data, perform the specified action, and store the result
@@ -6,8 +6,10 @@ use std::marker::PhantomData;
 use cosmwasm_std::{Order, StdResult, Storage, KV};
 use std::ops::Deref;
 
+use crate::de::KeyDeserialize;
 use crate::helpers::nested_namespaces_with_key;
-use crate::iter_helpers::{concat, deserialize_kv, trim};
+use crate::iter_helpers::{concat, deserialize_key, deserialize_kv, trim};
+use crate::keys::{Prefixer, PrimaryKey};
 use crate::Endian;
 
 /// Bound is used to defines the two ends of a range, more explicit than Option<u8>
@@ -21,31 +23,77 @@ pub enum Bound {
 }
 
 impl Bound {
-    /// Turns optional binary, like Option<CanonicalAddr> into an inclusive bound
-    pub fn inclusive<T: Into<Vec<u8>>>(limit: T) -> Self {
-        Bound::Inclusive(limit.into())
+    /// Turns a key, like &str, U64Key or (&[u8], U32Key) into an inclusive bound.
+    /// The key is encoded just like it is stored, so composite keys are length-prefixed
+    pub fn inclusive<'a, K: PrimaryKey<'a>>(limit: K) -> Self {
+        Bound::Inclusive(limit.joined_key())
     }
 
-    /// Turns optional binary, like Option<CanonicalAddr> into an exclusive bound
-    pub fn exclusive<T: Into<Vec<u8>>>(limit: T) -> Self {
-        Bound::Exclusive(limit.into())
+    /// Turns a key, like &str, U64Key or (&[u8], U32Key) into an exclusive bound.
+    /// The key is encoded just like it is stored, so composite keys are length-prefixed
+    pub fn exclusive<'a, K: PrimaryKey<'a>>(limit: K) -> Self {
+        Bound::Exclusive(limit.joined_key())
     }
 
-    /// Turns an int, like Option<u32> into an inclusive bound
+    /// Turns an int, like Option<u32> or Option<i64> into an inclusive bound.
+    /// This uses the same encoding as the int keys (eg. `I64Key`)
     pub fn inclusive_int<T: Endian>(limit: T) -> Self {
-        Bound::Inclusive(limit.to_be_bytes().into())
+        Bound::Inclusive(limit.to_sortable_bytes().into())
     }
 
-    /// Turns an int, like Option<u64> into an exclusive bound
+    /// Turns an int, like Option<u64> or Option<i32> into an exclusive bound.
+    /// This uses the same encoding as the int keys (eg. `I32Key`)
     pub fn exclusive_int<T: Endian>(limit: T) -> Self {
-        Bound::Exclusive(limit.to_be_bytes().into())
+        Bound::Exclusive(limit.to_sortable_bytes().into())
+    }
+}
+
+/// PrefixBound is used to define the two ends of a `prefix_range`.
+/// Unlike Bound, it limits the prefix of the keys (all elements but the last),
+/// so every key under a matching prefix is part of the range.
+#[derive(Clone, Debug)]
+pub enum PrefixBound<'a, K: Prefixer<'a>> {
+    Inclusive((K, PhantomData<&'a ()>)),
+    Exclusive((K, PhantomData<&'a ()>)),
+}
+
+impl<'a, K: Prefixer<'a>> PrefixBound<'a, K> {
+    pub fn inclusive(limit: K) -> Self {
+        PrefixBound::Inclusive((limit, PhantomData))
+    }
+
+    pub fn exclusive(limit: K) -> Self {
+        PrefixBound::Exclusive((limit, PhantomData))
+    }
+
+    /// Encodes the prefix just like it is stored, as length-prefixed namespaces
+    pub fn to_raw_bound(&self) -> Bound {
+        match self {
+            PrefixBound::Inclusive((limit, _)) => {
+                Bound::Inclusive(nested_namespaces_with_key(&[], &limit.prefix(), b""))
+            }
+            PrefixBound::Exclusive((limit, _)) => {
+                Bound::Exclusive(nested_namespaces_with_key(&[], &limit.prefix(), b""))
+            }
+        }
     }
 }
 
 type DeserializeFn<T> = fn(&dyn Storage, &[u8], KV) -> StdResult<KV<T>>;
 
+/// A key-value pair, with the key deserialized via KeyDeserialize
+pub(crate) type DeserializedKV<K, T> = (<K as KeyDeserialize>::Output, T);
+
+type DeserializeKeyFn<K, T> = fn(StdResult<KV<T>>) -> StdResult<DeserializedKV<K, T>>;
+
+/// A deserialized key-value pair, along with the raw key it was stored under
+pub(crate) type RawKV<T> = (Vec<u8>, StdResult<KV<T>>);
+
+/// Prefix is a scoped view over all the keys under the given namespaces.
+/// K is used to deserialize the keys returned by `range_de` and `keys_de`;
+/// it defaults to the raw bytes returned by `range` and `keys`.
 #[derive(Clone)]
-pub struct Prefix<T>
+pub struct Prefix<T, K = Vec<u8>>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -53,11 +101,12 @@ where
     storage_prefix: Vec<u8>,
     // see https://doc.rust-lang.org/std/marker/struct.PhantomData.html#unused-type-parameters for why this is needed
     data: PhantomData<T>,
+    key_type: PhantomData<K>,
     pk_name: Vec<u8>,
     de_fn: DeserializeFn<T>,
 }
 
-impl<T> Deref for Prefix<T>
+impl<T, K> Deref for Prefix<T, K>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -68,7 +117,7 @@ where
     }
 }
 
-impl<T> Prefix<T>
+impl<T, K> Prefix<T, K>
 where
     T: Serialize + DeserializeOwned,
 {
@@ -89,6 +138,7 @@ where
         Prefix {
             storage_prefix,
             data: PhantomData,
+            key_type: PhantomData,
             pk_name: pk_name.to_vec(),
             de_fn,
         }
@@ -111,6 +161,28 @@ where
         Box::new(mapped)
     }
 
+    /// Like `range`, but also returns the raw key (relative to the prefix) of every entry,
+    /// as indexes return the primary key rather than their own key
+    pub(crate) fn range_raw<'a>(
+        &self,
+        store: &'a dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = RawKV<T>> + 'a>
+    where
+        T: 'a,
+    {
+        let de_fn = self.de_fn;
+        let pk_name = self.pk_name.clone();
+        let mapped =
+            range_with_prefix(store, &self.storage_prefix, min, max, order).map(move |kv| {
+                let raw = kv.0.clone();
+                (raw, (de_fn)(store, &pk_name, kv))
+            });
+        Box::new(mapped)
+    }
+
     pub fn keys<'a>(
         &self,
         store: &'a dyn Storage,
@@ -127,6 +199,79 @@ where
     }
 }
 
+impl<T, K> Prefix<T, K>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Returns true if there is no data stored under this prefix
+    pub fn is_empty(&self, store: &dyn Storage) -> bool {
+        range_with_prefix(store, &self.storage_prefix, None, None, Order::Ascending)
+            .next()
+            .is_none()
+    }
+
+    /// Removes the entries stored under this prefix, at most limit of them if given.
+    /// This allows to bound the work done in one call, for large collections.
+    /// Returns true if there are entries left to remove.
+    ///
+    /// This is not public, as a prefix of an IndexedMap must not be cleared without its indexes.
+    /// Use `Map::clear_prefix` or `IndexedMap::clear_prefix`.
+    pub(crate) fn clear(&self, store: &mut dyn Storage, limit: Option<usize>) -> bool {
+        let keys: Vec<_> =
+            range_with_prefix(store, &self.storage_prefix, None, None, Order::Ascending)
+                .map(|(k, _)| k)
+                .take(limit.unwrap_or(usize::MAX))
+                .collect();
+        for k in keys {
+            store.remove(&concat(&self.storage_prefix, &k));
+        }
+        !self.is_empty(store)
+    }
+}
+
+impl<T, K> Prefix<T, K>
+where
+    T: Serialize + DeserializeOwned,
+    K: KeyDeserialize,
+{
+    /// Like `range`, but deserializes the returned keys into `K::Output`
+    pub fn range_de<'a>(
+        &self,
+        store: &'a dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<DeserializedKV<K, T>>> + 'a>
+    where
+        T: 'a,
+        K::Output: 'a,
+    {
+        // use a fn pointer, so the iterator type doesn't depend on K
+        let de_fn: DeserializeKeyFn<K, T> = deserialize_key::<K, T>;
+        Box::new(self.range(store, min, max, order).map(de_fn))
+    }
+
+    /// Like `range_de`, but only returns the keys.
+    /// Note this still loads the values, as some indexes need them to find the primary key.
+    /// Use `Map::keys_de` to skip that on plain maps.
+    pub fn keys_de<'a>(
+        &self,
+        store: &'a dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<K::Output>> + 'a>
+    where
+        T: 'a,
+        K::Output: 'a,
+    {
+        let mapped = self
+            .range_de(store, min, max, order)
+            .map(|kv| kv.map(|(k, _)| k));
+        Box::new(mapped)
+    }
+}
+
 pub fn range_with_prefix<'a>(
     storage: &'a dyn Storage,
     namespace: &[u8],
@@ -146,6 +291,52 @@ pub fn range_with_prefix<'a>(
     Box::new(mapped)
 }
 
+/// Like `range_with_prefix`, but the bounds limit the prefix of the keys rather than the full key.
+/// Note that every element of the prefix is length-prefixed, so shorter elements always sort
+/// before longer ones. Ranges are only lexicographic for fixed length elements (like addresses).
+pub fn prefixed_range<'a, 'p, K: Prefixer<'p>>(
+    storage: &'a dyn Storage,
+    namespace: &[u8],
+    start: Option<PrefixBound<'p, K>>,
+    end: Option<PrefixBound<'p, K>>,
+    order: Order,
+) -> Box<dyn Iterator<Item = KV> + 'a> {
+    let start = calc_prefix_start_bound(namespace, start);
+    let end = calc_prefix_end_bound(namespace, end);
+
+    // get iterator from storage
+    let base_iterator = storage.range(Some(&start), Some(&end), order);
+
+    // make a copy for the closure to handle lifetimes safely
+    let prefix = namespace.to_vec();
+    let mapped = base_iterator.map(move |(k, v)| (trim(&prefix, &k), v));
+    Box::new(mapped)
+}
+
+fn calc_prefix_start_bound<'a, K: Prefixer<'a>>(
+    namespace: &[u8],
+    bound: Option<PrefixBound<'a, K>>,
+) -> Vec<u8> {
+    match bound.map(|b| b.to_raw_bound()) {
+        None => namespace.to_vec(),
+        // skip everything under the excluded prefix
+        Some(Bound::Inclusive(limit)) => concat(namespace, &limit),
+        Some(Bound::Exclusive(limit)) => concat(namespace, &namespace_upper_bound(&limit)),
+    }
+}
+
+fn calc_prefix_end_bound<'a, K: Prefixer<'a>>(
+    namespace: &[u8],
+    bound: Option<PrefixBound<'a, K>>,
+) -> Vec<u8> {
+    match bound.map(|b| b.to_raw_bound()) {
+        None => namespace_upper_bound(namespace),
+        // include everything under the included prefix
+        Some(Bound::Exclusive(limit)) => concat(namespace, &limit),
+        Some(Bound::Inclusive(limit)) => concat(namespace, &namespace_upper_bound(&limit)),
+    }
+}
+
 fn calc_start_bound(namespace: &[u8], bound: Option<Bound>) -> Vec<u8> {
     match bound {
         None => namespace.to_vec(),
@@ -199,6 +390,7 @@ mod test {
         let prefix = Prefix {
             storage_prefix: b"foo".to_vec(),
             data: PhantomData::<u64>,
+            key_type: PhantomData::<Vec<u8>>,
             pk_name: vec![],
             de_fn: |_, _, kv| deserialize_kv(kv),
         };
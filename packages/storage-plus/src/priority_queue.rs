@@ -0,0 +1,252 @@
+// this module requires iterator to be useful at all
+#![cfg(feature = "iterator")]
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use cosmwasm_std::{Order, StdResult, Storage, KV};
+
+use crate::de::KeyDeserialize;
+use crate::keys::U64Key;
+use crate::prefix::PrefixBound;
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::{Item, Map};
+
+/// An entry of the queue, as ((priority, sequence), value)
+type Entry<T> = ((u64, u64), T);
+
+/// PriorityQueue stores items ordered by a u64 priority, like an expiration height or time.
+/// The item with the lowest priority is always first, items with the same priority
+/// are returned in insertion order.
+///
+/// Every item is stored under its own key, so push and pop never load the whole queue.
+pub struct PriorityQueue<'a, T> {
+    // items stored by (priority, sequence)
+    items: Map<'a, (U64Key, U64Key), T>,
+    // the next sequence number, to keep items with the same priority apart
+    sequence: Item<'a, u64>,
+}
+
+impl<'a, T> PriorityQueue<'a, T> {
+    /// Usage: PriorityQueue::new("unbonding", "unbonding__seq")
+    pub const fn new(namespace: &'a str, sequence_key: &'a str) -> Self {
+        PriorityQueue {
+            items: Map::new(namespace),
+            sequence: Item::new(sequence_key),
+        }
+    }
+}
+
+impl<'a, T> PriorityQueue<'a, T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    /// Adds the given value to the queue, behind all items of a lower or equal priority
+    pub fn push(&self, store: &mut dyn Storage, priority: u64, value: &T) -> StdResult<()> {
+        let seq = self.sequence.may_load(store)?.unwrap_or_default();
+        self.sequence.save(store, &(seq + 1))?;
+        self.items.save(store, (priority.into(), seq.into()), value)
+    }
+
+    /// Returns the item with the lowest priority, without removing it
+    pub fn peek(&self, store: &dyn Storage) -> StdResult<Option<(u64, T)>> {
+        let first = self.entries(store, None, Order::Ascending).next();
+        first
+            .transpose()
+            .map(|e| e.map(|((priority, _), v)| (priority, v)))
+    }
+
+    /// Removes the item with the lowest priority and returns it
+    pub fn pop(&self, store: &mut dyn Storage) -> StdResult<Option<(u64, T)>> {
+        let first = self.entries(store, None, Order::Ascending).next();
+        match first.transpose()? {
+            Some(((priority, seq), value)) => {
+                self.items.remove(store, (priority.into(), seq.into()));
+                Ok(Some((priority, value)))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Removes and returns up to limit items with a priority up to (and including) max_priority,
+    /// eg. the claims that have matured at the current height.
+    /// The bool is true if there are more items due, call it again (eg. in the next block)
+    /// until it is false.
+    pub fn pop_until(
+        &self,
+        store: &mut dyn Storage,
+        max_priority: u64,
+        limit: usize,
+    ) -> StdResult<(Vec<(u64, T)>, bool)> {
+        // load one more, to know if there are items left
+        let mut due: Vec<_> = self
+            .entries(store, Some(max_priority), Order::Ascending)
+            .take(limit.saturating_add(1))
+            .collect::<StdResult<_>>()?;
+        let more = due.len() > limit;
+        due.truncate(limit);
+        let popped = due
+            .into_iter()
+            .map(|((priority, seq), value)| {
+                self.items.remove(store, (priority.into(), seq.into()));
+                (priority, value)
+            })
+            .collect();
+        Ok((popped, more))
+    }
+
+    /// Returns true if there are no items in the queue
+    pub fn is_empty(&self, store: &dyn Storage) -> StdResult<bool> {
+        Ok(self.peek(store)?.is_none())
+    }
+
+    /// Iterates over all items along with their priority, lowest priority first
+    /// (unless order is Descending)
+    pub fn iter<'c>(
+        &self,
+        store: &'c dyn Storage,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<(u64, T)>> + 'c>
+    where
+        T: 'c,
+    {
+        let mapped = self
+            .entries(store, None, order)
+            .map(|e| e.map(|((priority, _), v)| (priority, v)));
+        Box::new(mapped)
+    }
+
+    fn entries<'c>(
+        &self,
+        store: &'c dyn Storage,
+        max_priority: Option<u64>,
+        order: Order,
+    ) -> Box<dyn Iterator<Item = StdResult<Entry<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        let max = max_priority.map(|p| PrefixBound::inclusive(U64Key::new(p)));
+        let parse_fn: fn(StdResult<KV<T>>) -> StdResult<Entry<T>> = parse_entry;
+        Box::new(
+            self.items
+                .prefix_range(store, None, max, order)
+                .map(parse_fn),
+        )
+    }
+}
+
+impl<'a, T> DescribeStorage for PriorityQueue<'a, T> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        let mut keys = self.items.storage_keys();
+        keys.extend(self.sequence.storage_keys());
+        keys
+    }
+}
+
+fn parse_entry<T>(kv: StdResult<KV<T>>) -> StdResult<Entry<T>> {
+    let (k, v) = kv?;
+    Ok((<(U64Key, U64Key)>::from_vec(k)?, v))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    const QUEUE: PriorityQueue<String> = PriorityQueue::new("jobs", "jobs__seq");
+
+    fn push(store: &mut dyn Storage, priority: u64, job: &str) {
+        QUEUE.push(store, priority, &job.to_string()).unwrap();
+    }
+
+    #[test]
+    fn push_and_pop_by_priority() {
+        let mut store = MockStorage::new();
+        assert!(QUEUE.is_empty(&store).unwrap());
+        assert_eq!(QUEUE.pop(&mut store).unwrap(), None);
+
+        push(&mut store, 300, "c");
+        push(&mut store, 100, "a");
+        push(&mut store, 200, "b");
+        assert!(!QUEUE.is_empty(&store).unwrap());
+
+        // peek doesn't remove
+        assert_eq!(QUEUE.peek(&store).unwrap(), Some((100, "a".to_string())));
+        assert_eq!(QUEUE.peek(&store).unwrap(), Some((100, "a".to_string())));
+
+        assert_eq!(QUEUE.pop(&mut store).unwrap(), Some((100, "a".to_string())));
+        assert_eq!(QUEUE.pop(&mut store).unwrap(), Some((200, "b".to_string())));
+        assert_eq!(QUEUE.pop(&mut store).unwrap(), Some((300, "c".to_string())));
+        assert_eq!(QUEUE.pop(&mut store).unwrap(), None);
+        assert!(QUEUE.is_empty(&store).unwrap());
+    }
+
+    #[test]
+    fn same_priority_keeps_insertion_order() {
+        let mut store = MockStorage::new();
+
+        push(&mut store, 5, "first");
+        push(&mut store, 5, "second");
+        push(&mut store, 1, "urgent");
+        push(&mut store, 5, "third");
+
+        let all: StdResult<Vec<_>> = QUEUE.iter(&store, Order::Ascending).collect();
+        assert_eq!(
+            all.unwrap(),
+            vec![
+                (1, "urgent".to_string()),
+                (5, "first".to_string()),
+                (5, "second".to_string()),
+                (5, "third".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn pop_until_takes_all_due_items() {
+        let mut store = MockStorage::new();
+
+        push(&mut store, 10, "a");
+        push(&mut store, 20, "b");
+        push(&mut store, 20, "c");
+        push(&mut store, 21, "d");
+
+        assert_eq!(QUEUE.pop_until(&mut store, 9, 10).unwrap(), (vec![], false));
+        assert_eq!(
+            QUEUE.pop_until(&mut store, 20, 10).unwrap(),
+            (
+                vec![
+                    (10, "a".to_string()),
+                    (20, "b".to_string()),
+                    (20, "c".to_string())
+                ],
+                false
+            )
+        );
+        // the rest is untouched
+        let rest: StdResult<Vec<_>> = QUEUE.iter(&store, Order::Descending).collect();
+        assert_eq!(rest.unwrap(), vec![(21, "d".to_string())]);
+    }
+
+    #[test]
+    fn pop_until_is_bounded() {
+        let mut store = MockStorage::new();
+
+        push(&mut store, 10, "a");
+        push(&mut store, 20, "b");
+        push(&mut store, 20, "c");
+        push(&mut store, 21, "d");
+
+        assert_eq!(
+            QUEUE.pop_until(&mut store, 20, 2).unwrap(),
+            (vec![(10, "a".to_string()), (20, "b".to_string())], true)
+        );
+        // exactly the rest of the due items
+        assert_eq!(
+            QUEUE.pop_until(&mut store, 20, 1).unwrap(),
+            (vec![(20, "c".to_string())], false)
+        );
+        assert_eq!(QUEUE.pop_until(&mut store, 20, 0).unwrap(), (vec![], false));
+        assert_eq!(QUEUE.pop_until(&mut store, 21, 0).unwrap(), (vec![], true));
+    }
+}
@@ -0,0 +1,253 @@
+//! Describes which keys the storage types of a contract write to, so overlapping
+//! declarations can be caught in a unit test, rather than corrupting state.
+//!
+//! Every `Item` (and `Sequence`) writes a single key, stored as is. All other types
+//! write under a namespace, which is length-prefixed like every key from `helpers.rs`.
+//! Two declarations collide if one of them could write to a key of the other.
+
+use std::fmt;
+
+use cosmwasm_std::{StdError, StdResult};
+
+use crate::helpers::namespaces_with_key;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum KeyKind {
+    /// A single key, like an Item
+    Single,
+    /// All keys starting with the (length-prefixed) namespace, like a Map
+    Namespace,
+}
+
+/// StorageKey describes one key or namespace used by a storage type
+#[derive(Clone, Debug, PartialEq)]
+pub struct StorageKey {
+    /// the key or namespace as declared, eg. "tokens__owner"
+    pub name: String,
+    pub kind: KeyKind,
+    /// the raw key, or the prefix shared by all keys in the namespace
+    pub raw: Vec<u8>,
+}
+
+impl StorageKey {
+    pub fn single(key: &[u8]) -> Self {
+        StorageKey {
+            name: String::from_utf8_lossy(key).into(),
+            kind: KeyKind::Single,
+            raw: key.to_vec(),
+        }
+    }
+
+    pub fn namespace(namespace: &[u8]) -> Self {
+        StorageKey {
+            name: String::from_utf8_lossy(namespace).into(),
+            kind: KeyKind::Namespace,
+            raw: namespaces_with_key(&[namespace], b""),
+        }
+    }
+
+    /// Returns true if both could write to the same key
+    pub fn overlaps(&self, other: &StorageKey) -> bool {
+        match (self.kind, other.kind) {
+            (KeyKind::Single, KeyKind::Single) => self.raw == other.raw,
+            (KeyKind::Single, KeyKind::Namespace) => self.raw.starts_with(&other.raw),
+            (KeyKind::Namespace, KeyKind::Single) => other.raw.starts_with(&self.raw),
+            (KeyKind::Namespace, KeyKind::Namespace) => {
+                self.raw.starts_with(&other.raw) || other.raw.starts_with(&self.raw)
+            }
+        }
+    }
+}
+
+impl fmt::Display for StorageKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.kind {
+            KeyKind::Single => write!(f, "key \"{}\"", self.name),
+            KeyKind::Namespace => write!(f, "namespace \"{}\"", self.name),
+        }
+    }
+}
+
+/// DescribeStorage is implemented by all storage types, and lists all keys and namespaces they write to
+pub trait DescribeStorage {
+    fn storage_keys(&self) -> Vec<StorageKey>;
+}
+
+/// A StorageKey along with the label of the declaration it belongs to
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegisteredKey {
+    pub label: String,
+    pub key: StorageKey,
+}
+
+impl fmt::Display for RegisteredKey {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} ({})", self.label, self.key)
+    }
+}
+
+/// StorageRegistry collects the storage declarations of a contract. Usage:
+///
+///   StorageRegistry::new()
+///       .register("CONFIG", &CONFIG)
+///       .register("tokens", &tokens())
+///       .assert_no_collisions();
+#[derive(Clone, Debug, Default)]
+pub struct StorageRegistry {
+    entries: Vec<RegisteredKey>,
+}
+
+impl StorageRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds all keys of the given storage type, under the given label
+    pub fn register(mut self, label: &str, storage: &dyn DescribeStorage) -> Self {
+        let keys = storage.storage_keys().into_iter().map(|key| RegisteredKey {
+            label: label.to_string(),
+            key,
+        });
+        self.entries.extend(keys);
+        self
+    }
+
+    /// All registered keys, in the order they were registered
+    pub fn entries(&self) -> &[RegisteredKey] {
+        &self.entries
+    }
+
+    /// Returns all pairs of keys that overlap. This includes keys of the same declaration,
+    /// eg. a SnapshotMap using the same namespace for the data and the changelog.
+    pub fn collisions(&self) -> Vec<(&RegisteredKey, &RegisteredKey)> {
+        let mut res = vec![];
+        for (i, a) in self.entries.iter().enumerate() {
+            for b in &self.entries[i + 1..] {
+                if a.key.overlaps(&b.key) {
+                    res.push((a, b));
+                }
+            }
+        }
+        res
+    }
+
+    /// Returns an error listing all collisions, if there are any
+    pub fn check(&self) -> StdResult<()> {
+        let collisions = self.collisions();
+        if collisions.is_empty() {
+            return Ok(());
+        }
+        let msg: Vec<_> = collisions
+            .into_iter()
+            .map(|(a, b)| format!("{} overlaps with {}", a, b))
+            .collect();
+        Err(StdError::generic_err(format!(
+            "Storage collisions: {}",
+            msg.join("; ")
+        )))
+    }
+
+    /// Test helper, panics if any of the registered keys overlap
+    pub fn assert_no_collisions(&self) {
+        if let Err(err) = self.check() {
+            panic!("{}", err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{Item, Map, Sequence, U64Key};
+
+    const CONFIG: Item<u64> = Item::new("config");
+    const TOKENS: Map<&str, u64> = Map::new("tokens");
+    const OWNERS: Map<&str, u64> = Map::new("tokens__owner");
+
+    #[test]
+    fn describe_keys() {
+        assert_eq!(
+            CONFIG.storage_keys(),
+            vec![StorageKey {
+                name: "config".to_string(),
+                kind: KeyKind::Single,
+                raw: b"config".to_vec(),
+            }]
+        );
+        assert_eq!(
+            TOKENS.storage_keys(),
+            vec![StorageKey {
+                name: "tokens".to_string(),
+                kind: KeyKind::Namespace,
+                raw: b"\x00\x06tokens".to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn distinct_names_dont_collide() {
+        let registry = StorageRegistry::new()
+            .register("CONFIG", &CONFIG)
+            .register("TOKENS", &TOKENS)
+            .register("OWNERS", &OWNERS)
+            // an item with the name of a map is fine, as map keys are length-prefixed
+            .register("TOKEN_COUNT", &Sequence::<u64>::new("tokens"));
+        assert_eq!(registry.entries().len(), 4);
+        assert_eq!(registry.collisions().len(), 0);
+        registry.check().unwrap();
+        registry.assert_no_collisions();
+    }
+
+    #[test]
+    fn same_names_collide() {
+        let other: Map<U64Key, u64> = Map::new("tokens");
+        let registry = StorageRegistry::new()
+            .register("CONFIG", &CONFIG)
+            .register("TOKENS", &TOKENS)
+            .register("OTHER", &other)
+            .register("ADMIN", &Item::<u64>::new("config"));
+        let collisions = registry.collisions();
+        assert_eq!(collisions.len(), 2);
+        assert_eq!(collisions[0].0.label, "CONFIG");
+        assert_eq!(collisions[0].1.label, "ADMIN");
+        assert_eq!(collisions[1].0.label, "TOKENS");
+        assert_eq!(collisions[1].1.label, "OTHER");
+
+        let err = registry.check().unwrap_err();
+        assert!(err
+            .to_string()
+            .contains(r#"TOKENS (namespace "tokens") overlaps with OTHER (namespace "tokens")"#));
+    }
+
+    #[test]
+    fn single_key_inside_namespace_collides() {
+        // this item is stored just like the key "john" in TOKENS
+        let item: Item<u64> = Item::new("\x00\x06tokensjohn");
+        let registry = StorageRegistry::new()
+            .register("TOKENS", &TOKENS)
+            .register("ITEM", &item);
+        assert_eq!(registry.collisions().len(), 1);
+    }
+
+    #[test]
+    fn nested_namespaces_collide() {
+        // a map using a composite namespace, overlapping with TOKENS
+        let nested = StorageKey {
+            name: "tokens/john".to_string(),
+            kind: KeyKind::Namespace,
+            raw: namespaces_with_key(&[b"tokens", b"john"], b""),
+        };
+        assert!(nested.overlaps(&TOKENS.storage_keys()[0]));
+        assert!(TOKENS.storage_keys()[0].overlaps(&nested));
+        assert!(!nested.overlaps(&OWNERS.storage_keys()[0]));
+    }
+
+    #[test]
+    #[should_panic(expected = "Storage collisions")]
+    fn assert_no_collisions_panics() {
+        StorageRegistry::new()
+            .register("A", &CONFIG)
+            .register("B", &CONFIG)
+            .assert_no_collisions();
+    }
+}
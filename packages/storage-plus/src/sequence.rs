@@ -0,0 +1,194 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use cosmwasm_std::{StdError, StdResult, Storage, Uint128};
+
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::Item;
+
+/// SequenceNumber is implemented for the types that can back a `Sequence`: u64 and Uint128
+/// (rather than u128, which can't be stored as json)
+pub trait SequenceNumber: Serialize + DeserializeOwned + Copy + Default + ToString {
+    fn one() -> Self;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+}
+
+impl SequenceNumber for u64 {
+    fn one() -> Self {
+        1
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        u64::checked_add(self, other)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        u64::checked_sub(self, other)
+    }
+}
+
+impl SequenceNumber for Uint128 {
+    fn one() -> Self {
+        Uint128(1)
+    }
+
+    fn checked_add(self, other: Self) -> Option<Self> {
+        self.u128().checked_add(other.u128()).map(Uint128)
+    }
+
+    fn checked_sub(self, other: Self) -> Option<Self> {
+        self.u128().checked_sub(other.u128()).map(Uint128)
+    }
+}
+
+/// Sequence is a counter stored under a single key, starting at 0.
+/// Use it for unique ids (`next` returns 1 the first time) or to count items.
+///
+/// It is stored just like an `Item<u64>` (or `Item<Uint128>`), so it can replace
+/// an existing counter item without a migration.
+pub struct Sequence<'a, N = u64> {
+    value: Item<'a, N>,
+}
+
+impl<'a, N> Sequence<'a, N> {
+    pub const fn new(storage_key: &'a str) -> Self {
+        Sequence {
+            value: Item::new(storage_key),
+        }
+    }
+}
+
+impl<'a, N> Sequence<'a, N>
+where
+    N: SequenceNumber,
+{
+    /// Returns the current value, 0 if it was never set
+    pub fn current(&self, store: &dyn Storage) -> StdResult<N> {
+        Ok(self.value.may_load(store)?.unwrap_or_default())
+    }
+
+    /// Increments the sequence by one and returns the new value, eg. the next unique id
+    pub fn next(&self, store: &mut dyn Storage) -> StdResult<N> {
+        self.increment_by(store, N::one())
+    }
+
+    /// Increments the sequence by amount and returns the new value.
+    /// Returns an error on overflow, leaving the sequence unchanged.
+    pub fn increment_by(&self, store: &mut dyn Storage, amount: N) -> StdResult<N> {
+        let current = self.current(store)?;
+        let value = current.checked_add(amount).ok_or_else(|| {
+            StdError::generic_err(format!(
+                "Sequence overflow: {} + {}",
+                current.to_string(),
+                amount.to_string()
+            ))
+        })?;
+        self.value.save(store, &value)?;
+        Ok(value)
+    }
+
+    /// Decrements the sequence by one and returns the new value.
+    /// Returns an error if it is already 0.
+    pub fn decrement(&self, store: &mut dyn Storage) -> StdResult<N> {
+        self.decrement_by(store, N::one())
+    }
+
+    /// Decrements the sequence by amount and returns the new value.
+    /// Returns an error on underflow, leaving the sequence unchanged.
+    pub fn decrement_by(&self, store: &mut dyn Storage, amount: N) -> StdResult<N> {
+        let current = self.current(store)?;
+        let value = current
+            .checked_sub(amount)
+            .ok_or_else(|| StdError::underflow(current, amount))?;
+        self.value.save(store, &value)?;
+        Ok(value)
+    }
+
+    /// Sets the sequence to the given value
+    pub fn set(&self, store: &mut dyn Storage, value: N) -> StdResult<()> {
+        self.value.save(store, &value)
+    }
+}
+
+impl<'a, N> DescribeStorage for Sequence<'a, N> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        self.value.storage_keys()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    const IDS: Sequence = Sequence::new("ids");
+    const SUPPLY: Sequence<Uint128> = Sequence::new("supply");
+
+    #[test]
+    fn next_and_current() {
+        let mut store = MockStorage::new();
+        assert_eq!(IDS.current(&store).unwrap(), 0);
+
+        assert_eq!(IDS.next(&mut store).unwrap(), 1);
+        assert_eq!(IDS.next(&mut store).unwrap(), 2);
+        assert_eq!(IDS.current(&store).unwrap(), 2);
+        assert_eq!(IDS.increment_by(&mut store, 10).unwrap(), 12);
+        assert_eq!(IDS.current(&store).unwrap(), 12);
+
+        // same storage layout as a plain Item
+        let item: Item<u64> = Item::new("ids");
+        assert_eq!(item.load(&store).unwrap(), 12);
+        item.save(&mut store, &40).unwrap();
+        assert_eq!(IDS.next(&mut store).unwrap(), 41);
+    }
+
+    #[test]
+    fn checked_decrement() {
+        let mut store = MockStorage::new();
+
+        // can't go below 0
+        match IDS.decrement(&mut store).unwrap_err() {
+            StdError::Underflow {
+                minuend,
+                subtrahend,
+                ..
+            } => {
+                assert_eq!(minuend, "0");
+                assert_eq!(subtrahend, "1");
+            }
+            e => panic!("Unexpected error {}", e),
+        }
+
+        IDS.set(&mut store, 3).unwrap();
+        assert_eq!(IDS.decrement(&mut store).unwrap(), 2);
+        IDS.decrement_by(&mut store, 5).unwrap_err();
+        assert_eq!(IDS.current(&store).unwrap(), 2);
+        assert_eq!(IDS.decrement_by(&mut store, 2).unwrap(), 0);
+    }
+
+    #[test]
+    fn checked_increment() {
+        let mut store = MockStorage::new();
+
+        IDS.set(&mut store, u64::MAX - 1).unwrap();
+        assert_eq!(IDS.next(&mut store).unwrap(), u64::MAX);
+        IDS.next(&mut store).unwrap_err();
+        assert_eq!(IDS.current(&store).unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn uint128_backing() {
+        let mut store = MockStorage::new();
+
+        let big = Uint128(u64::MAX as u128);
+        assert_eq!(SUPPLY.increment_by(&mut store, big).unwrap(), big);
+        assert_eq!(
+            SUPPLY.next(&mut store).unwrap(),
+            Uint128(u64::MAX as u128 + 1)
+        );
+        assert_eq!(SUPPLY.decrement_by(&mut store, big).unwrap(), Uint128(1));
+        SUPPLY.decrement_by(&mut store, Uint128(2)).unwrap_err();
+        assert_eq!(SUPPLY.current(&store).unwrap(), Uint128(1));
+    }
+}
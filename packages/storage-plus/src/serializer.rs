@@ -0,0 +1,327 @@
+//! Serializers turn the values of `Item`, `Map` and `Path` into bytes and back.
+//! `Json` is the default, and what all the other storage types use.
+//! `Compact` is a denser binary encoding for hot paths (like balances),
+//! and `Raw` stores byte values as they are.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::any::type_name;
+use std::convert::TryInto;
+
+use cosmwasm_std::{from_slice, to_vec, Binary, StdError, StdResult, Uint128};
+
+pub trait Serializer<T> {
+    fn to_vec(data: &T) -> StdResult<Vec<u8>>;
+    fn from_slice(value: &[u8]) -> StdResult<T>;
+}
+
+/// Json stores values as json, using `cosmwasm_std::to_vec` and `from_slice`
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Json;
+
+impl<T: Serialize + DeserializeOwned> Serializer<T> for Json {
+    #[inline]
+    fn to_vec(data: &T) -> StdResult<Vec<u8>> {
+        to_vec(data)
+    }
+
+    #[inline]
+    fn from_slice(value: &[u8]) -> StdResult<T> {
+        from_slice(value)
+    }
+}
+
+/// Raw stores byte values as they are, without any encoding.
+/// Storage doesn't support empty values, so saving an empty value returns an error.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Raw;
+
+impl Serializer<Vec<u8>> for Raw {
+    #[inline]
+    fn to_vec(data: &Vec<u8>) -> StdResult<Vec<u8>> {
+        non_empty(data.clone())
+    }
+
+    #[inline]
+    fn from_slice(value: &[u8]) -> StdResult<Vec<u8>> {
+        Ok(value.to_vec())
+    }
+}
+
+impl Serializer<Binary> for Raw {
+    #[inline]
+    fn to_vec(data: &Binary) -> StdResult<Vec<u8>> {
+        non_empty(data.to_vec())
+    }
+
+    #[inline]
+    fn from_slice(value: &[u8]) -> StdResult<Binary> {
+        Ok(Binary::from(value))
+    }
+}
+
+fn non_empty(data: Vec<u8>) -> StdResult<Vec<u8>> {
+    if data.is_empty() {
+        return Err(StdError::generic_err("Cannot store an empty value"));
+    }
+    Ok(data)
+}
+
+/// Compact is a binary encoding (much like bincode) for all types implementing `CompactEncoding`.
+/// Ints are stored as fixed size big endian, `Uint128` as a u128, and strings and lists
+/// are prefixed with their length. Unlike json, it doesn't store any field names.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Compact;
+
+impl<T: CompactEncoding> Serializer<T> for Compact {
+    fn to_vec(data: &T) -> StdResult<Vec<u8>> {
+        let mut out = Vec::new();
+        data.encode(&mut out);
+        Ok(out)
+    }
+
+    fn from_slice(value: &[u8]) -> StdResult<T> {
+        let mut data = value;
+        let res = T::decode(&mut data)?;
+        if !data.is_empty() {
+            return Err(StdError::parse_err(
+                type_name::<T>(),
+                "trailing bytes after value",
+            ));
+        }
+        Ok(res)
+    }
+}
+
+/// CompactEncoding defines how a type is stored with the `Compact` serializer.
+/// Structs can implement it by encoding (and decoding) all fields in order:
+///
+///   fn encode(&self, out: &mut Vec<u8>) {
+///       self.owner.encode(out);
+///       self.amount.encode(out);
+///   }
+pub trait CompactEncoding: Sized {
+    /// appends the encoded value to out
+    fn encode(&self, out: &mut Vec<u8>);
+
+    /// reads one value from the front of data, and advances data past it
+    fn decode(data: &mut &[u8]) -> StdResult<Self>;
+}
+
+/// Splits off the first n bytes of data
+fn take<'a, T>(data: &mut &'a [u8], n: usize) -> StdResult<&'a [u8]> {
+    if data.len() < n {
+        return Err(StdError::parse_err(
+            type_name::<T>(),
+            "unexpected end of data",
+        ));
+    }
+    let (first, rest) = data.split_at(n);
+    *data = rest;
+    Ok(first)
+}
+
+fn encode_len(len: usize, out: &mut Vec<u8>) {
+    (len as u32).encode(out);
+}
+
+fn decode_len(data: &mut &[u8]) -> StdResult<usize> {
+    Ok(u32::decode(data)? as usize)
+}
+
+macro_rules! impl_compact_int {
+    ($($ty:ty),* $(,)?) => {
+        $(
+            impl CompactEncoding for $ty {
+                fn encode(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_be_bytes());
+                }
+
+                fn decode(data: &mut &[u8]) -> StdResult<Self> {
+                    let bytes = take::<$ty>(data, std::mem::size_of::<$ty>())?;
+                    // the length was checked by take
+                    Ok(<$ty>::from_be_bytes(bytes.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+#[rustfmt::skip]
+impl_compact_int![
+    i8, i16, i32, i64, i128,
+    u8, u16, u32, u64, u128,
+];
+
+impl CompactEncoding for bool {
+    fn encode(&self, out: &mut Vec<u8>) {
+        out.push(*self as u8);
+    }
+
+    fn decode(data: &mut &[u8]) -> StdResult<Self> {
+        match u8::decode(data)? {
+            0 => Ok(false),
+            1 => Ok(true),
+            _ => Err(StdError::parse_err("bool", "invalid value")),
+        }
+    }
+}
+
+impl CompactEncoding for Uint128 {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.u128().encode(out);
+    }
+
+    fn decode(data: &mut &[u8]) -> StdResult<Self> {
+        u128::decode(data).map(Uint128::from)
+    }
+}
+
+impl CompactEncoding for String {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        out.extend_from_slice(self.as_bytes());
+    }
+
+    fn decode(data: &mut &[u8]) -> StdResult<Self> {
+        let len = decode_len(data)?;
+        let bytes = take::<String>(data, len)?;
+        String::from_utf8(bytes.to_vec()).map_err(StdError::invalid_utf8)
+    }
+}
+
+impl CompactEncoding for Binary {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        out.extend_from_slice(self.as_slice());
+    }
+
+    fn decode(data: &mut &[u8]) -> StdResult<Self> {
+        let len = decode_len(data)?;
+        take::<Binary>(data, len).map(Binary::from)
+    }
+}
+
+impl<T: CompactEncoding> CompactEncoding for Vec<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        encode_len(self.len(), out);
+        for item in self {
+            item.encode(out);
+        }
+    }
+
+    fn decode(data: &mut &[u8]) -> StdResult<Self> {
+        let len = decode_len(data)?;
+        // don't trust the length for the allocation, every item takes at least one byte
+        let mut res = Vec::with_capacity(len.min(data.len()));
+        for _ in 0..len {
+            res.push(T::decode(data)?);
+        }
+        Ok(res)
+    }
+}
+
+impl<T: CompactEncoding> CompactEncoding for Option<T> {
+    fn encode(&self, out: &mut Vec<u8>) {
+        match self {
+            None => out.push(0),
+            Some(v) => {
+                out.push(1);
+                v.encode(out);
+            }
+        }
+    }
+
+    fn decode(data: &mut &[u8]) -> StdResult<Self> {
+        match u8::decode(data)? {
+            0 => Ok(None),
+            1 => T::decode(data).map(Some),
+            _ => Err(StdError::parse_err(type_name::<Self>(), "invalid tag")),
+        }
+    }
+}
+
+impl<T: CompactEncoding, U: CompactEncoding> CompactEncoding for (T, U) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+    }
+
+    fn decode(data: &mut &[u8]) -> StdResult<Self> {
+        Ok((T::decode(data)?, U::decode(data)?))
+    }
+}
+
+impl<T: CompactEncoding, U: CompactEncoding, V: CompactEncoding> CompactEncoding for (T, U, V) {
+    fn encode(&self, out: &mut Vec<u8>) {
+        self.0.encode(out);
+        self.1.encode(out);
+        self.2.encode(out);
+    }
+
+    fn decode(data: &mut &[u8]) -> StdResult<Self> {
+        Ok((T::decode(data)?, U::decode(data)?, V::decode(data)?))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn roundtrip<T, S>(data: T) -> Vec<u8>
+    where
+        T: std::fmt::Debug + PartialEq,
+        S: Serializer<T>,
+    {
+        let bytes = S::to_vec(&data).unwrap();
+        assert_eq!(data, S::from_slice(&bytes).unwrap());
+        bytes
+    }
+
+    #[test]
+    fn json_works() {
+        let bytes = roundtrip::<_, Json>(Uint128(1234));
+        assert_eq!(bytes, br#""1234""#.to_vec());
+    }
+
+    #[test]
+    fn raw_works() {
+        let bytes = roundtrip::<_, Raw>(b"some bytes".to_vec());
+        assert_eq!(bytes, b"some bytes".to_vec());
+        let bytes = roundtrip::<_, Raw>(Binary::from(b"binary".as_ref()));
+        assert_eq!(bytes, b"binary".to_vec());
+        <Raw as Serializer<Vec<u8>>>::to_vec(&vec![]).unwrap_err();
+    }
+
+    #[test]
+    fn compact_is_fixed_size_for_ints() {
+        assert_eq!(roundtrip::<_, Compact>(Uint128(1234)).len(), 16);
+        assert_eq!(roundtrip::<_, Compact>(-7i32), vec![0xff, 0xff, 0xff, 0xf9]);
+        assert_eq!(roundtrip::<_, Compact>(true), vec![1]);
+    }
+
+    #[test]
+    fn compact_composite_values() {
+        let bytes = roundtrip::<_, Compact>("foo".to_string());
+        assert_eq!(bytes, vec![0, 0, 0, 3, b'f', b'o', b'o']);
+
+        roundtrip::<_, Compact>(Binary::from(b"data".as_ref()));
+        roundtrip::<_, Compact>(vec![Some(5u64), None, Some(7)]);
+        roundtrip::<_, Compact>(("john".to_string(), Uint128(44), 12u64));
+        roundtrip::<_, Compact>(Vec::<(String, bool)>::new());
+    }
+
+    #[test]
+    fn compact_rejects_invalid_data() {
+        // too short
+        assert!(<Compact as Serializer<u64>>::from_slice(&[0, 1, 2]).is_err());
+        // too long
+        assert!(<Compact as Serializer<u16>>::from_slice(&[0, 1, 2]).is_err());
+        // string length beyond the data
+        assert!(<Compact as Serializer<String>>::from_slice(&[0, 0, 0, 9, b'a']).is_err());
+        // no valid bool
+        assert!(<Compact as Serializer<bool>>::from_slice(&[2]).is_err());
+        // huge length doesn't allocate up front
+        assert!(<Compact as Serializer<Vec<u64>>>::from_slice(&[255, 255, 255, 255]).is_err());
+    }
+}
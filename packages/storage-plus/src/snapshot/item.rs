@@ -0,0 +1,378 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use cosmwasm_std::{Order, StdError, StdResult, Storage};
+
+use crate::keys::U64Key;
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::snapshot::{ChangeSet, Checkpoints, Strategy};
+use crate::{Bound, Item, Map};
+
+/// Item that maintains a snapshot of one or more checkpoints.
+/// We can query historical data as well as current state.
+/// What data is snapshotted depends on the Strategy.
+pub struct SnapshotItem<'a, T> {
+    primary: Item<'a, T>,
+
+    // maps height to number of checkpoints, and decides when to write the changelog
+    checkpoints: Checkpoints<'a>,
+
+    // this stores all changes by height. Must differentiate between no data written,
+    // and explicit None (just inserted)
+    changelog: Map<'a, U64Key, ChangeSet<T>>,
+}
+
+impl<'a, T> SnapshotItem<'a, T> {
+    /// Usage: SnapshotItem::new("total", "total__check", "total__change", Strategy::EveryBlock)
+    pub const fn new(
+        storage_key: &'a str,
+        checkpoints: &'a str,
+        changelog: &'a str,
+        strategy: Strategy,
+    ) -> Self {
+        SnapshotItem {
+            primary: Item::new(storage_key),
+            checkpoints: Checkpoints::new(checkpoints, strategy),
+            changelog: Map::new(changelog),
+        }
+    }
+
+    pub fn add_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.add_checkpoint(store, height)
+    }
+
+    pub fn remove_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.remove_checkpoint(store, height)
+    }
+}
+
+impl<'a, T> SnapshotItem<'a, T>
+where
+    T: Serialize + DeserializeOwned + Clone,
+{
+    // this gets the path of the data to use elsewhere
+    pub fn as_slice(&self) -> &[u8] {
+        self.primary.as_slice()
+    }
+
+    /// should_checkpoint looks at the strategy and determines if we want to checkpoint
+    fn should_checkpoint(&self, store: &dyn Storage) -> StdResult<bool> {
+        self.checkpoints.should_checkpoint(store, |height| {
+            // any changelog since then?
+            let start = Bound::inclusive(U64Key::from(height));
+            let first = self
+                .changelog
+                .range(store, Some(start), None, Order::Ascending)
+                .next()
+                .transpose()?;
+            Ok(first.is_some())
+        })
+    }
+
+    /// load old value and store changelog
+    fn write_change(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        // if there is already data in the changelog for this block, do not write more
+        if self
+            .changelog
+            .may_load(store, U64Key::from(height))?
+            .is_some()
+        {
+            return Ok(());
+        }
+        // otherwise, store the previous value
+        let old = self.primary.may_load(store)?;
+        self.changelog
+            .save(store, U64Key::from(height), &ChangeSet { old })
+    }
+
+    pub fn save(&self, store: &mut dyn Storage, data: &T, height: u64) -> StdResult<()> {
+        if self.should_checkpoint(store)? {
+            self.write_change(store, height)?;
+        }
+        self.primary.save(store, data)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        if self.should_checkpoint(store)? {
+            self.write_change(store, height)?;
+        }
+        self.primary.remove(store);
+        Ok(())
+    }
+
+    /// load will return an error if no data is set, or on parse error
+    pub fn load(&self, store: &dyn Storage) -> StdResult<T> {
+        self.primary.load(store)
+    }
+
+    /// may_load will parse the data stored if present, returns Ok(None) if no data there.
+    /// returns an error on issues parsing
+    pub fn may_load(&self, store: &dyn Storage) -> StdResult<Option<T>> {
+        self.primary.may_load(store)
+    }
+
+    // may_load_at_height reads historical data from given checkpoints.
+    // Only returns `Ok` if we have the data to be able to give the correct answer
+    // (Strategy::EveryBlock or Strategy::Selected and h is registered as checkpoint)
+    //
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn may_load_at_height(&self, store: &dyn Storage, height: u64) -> StdResult<Option<T>> {
+        self.assert_checkpointed(store, height)?;
+
+        // this will look for the first snapshot >= given height
+        // If None, there is no snapshot since that time.
+        let start = Bound::inclusive(U64Key::new(height));
+        let first = self
+            .changelog
+            .range(store, Some(start), None, Order::Ascending)
+            .next();
+
+        if let Some(r) = first {
+            // if we found a match, return this last one
+            r.map(|(_, v)| v.old)
+        } else {
+            // otherwise, return current value
+            self.may_load(store)
+        }
+    }
+
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn assert_checkpointed(&self, store: &dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.assert_checkpointed(store, height)
+    }
+
+    /// Removes the history (changelog and checkpoints) below before_height, which can
+    /// no longer be queried afterwards. Removes at most limit entries of each per call.
+    /// Returns true if there is more to prune, call it again (eg. in the next block) until it returns false.
+    /// A limit of 0 removes nothing and returns false.
+    pub fn prune(
+        &self,
+        store: &mut dyn Storage,
+        before_height: u64,
+        limit: usize,
+    ) -> StdResult<bool> {
+        if limit == 0 {
+            return Ok(false);
+        }
+        let state = match self.checkpoints.start_prune(store, before_height)? {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+
+        let end = Bound::exclusive(U64Key::new(state.height));
+        let heights: Vec<u64> = self
+            .changelog
+            .keys_de(store, None, Some(end), Order::Ascending)
+            .take(limit)
+            .collect::<StdResult<_>>()?;
+        for height in &heights {
+            self.changelog.remove(store, (*height).into());
+        }
+
+        let changelog_done = heights.len() < limit;
+        self.checkpoints
+            .finish_prune(store, state, changelog_done, limit)
+    }
+
+    /// All history below this height was removed by prune
+    pub fn pruned_height(&self, store: &dyn Storage) -> StdResult<u64> {
+        self.checkpoints.pruned_height(store)
+    }
+
+    /// Loads the data, perform the specified action, and store the result
+    /// in the database. This is shorthand for some common sequences, which may be useful.
+    ///
+    /// It assumes, that data was initialized before, and if it doesn't exist, `Err(StdError::NotFound)`
+    /// is returned.
+    pub fn update<A, E>(&self, store: &mut dyn Storage, height: u64, action: A) -> Result<T, E>
+    where
+        A: FnOnce(T) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let input = self.load(store)?;
+        let output = action(input)?;
+        self.save(store, &output, height)?;
+        Ok(output)
+    }
+}
+
+impl<'a, T> DescribeStorage for SnapshotItem<'a, T> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        let mut keys = self.primary.storage_keys();
+        keys.extend(self.checkpoints.storage_keys());
+        keys.extend(self.changelog.storage_keys());
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use cosmwasm_std::testing::MockStorage;
+
+    type TestItem = SnapshotItem<'static, u64>;
+
+    const NEVER: TestItem =
+        SnapshotItem::new("never", "never__check", "never__change", Strategy::Never);
+    const EVERY: TestItem = SnapshotItem::new(
+        "every",
+        "every__check",
+        "every__change",
+        Strategy::EveryBlock,
+    );
+    const SELECT: TestItem = SnapshotItem::new(
+        "select",
+        "select__check",
+        "select__change",
+        Strategy::Selected,
+    );
+
+    // Fills an item (u64) with the following writes:
+    // 1: 5
+    // 2: 7
+    // 3: 1, then 8
+    // 4: None, then 13
+    // 5: None, then 22
+    // Final value -> 22
+    // Value at beginning of 3 -> 7
+    // Value at beginning of 5 -> 13
+    fn init_data(item: &TestItem, storage: &mut dyn Storage) {
+        item.save(storage, &5, 1).unwrap();
+        item.save(storage, &7, 2).unwrap();
+
+        // checkpoint 3
+        item.add_checkpoint(storage, 3).unwrap();
+
+        // also use update to set - to ensure this works
+        item.save(storage, &1, 3).unwrap();
+        item.update(storage, 3, |_| -> StdResult<u64> { Ok(8) })
+            .unwrap();
+
+        item.remove(storage, 4).unwrap();
+        item.save(storage, &13, 4).unwrap();
+
+        // checkpoint 5
+        item.add_checkpoint(storage, 5).unwrap();
+        item.remove(storage, 5).unwrap();
+        item.save(storage, &22, 5).unwrap();
+        // and delete it later (unknown if all data present)
+        item.remove_checkpoint(storage, 5).unwrap();
+    }
+
+    const FINAL_VALUE: Option<u64> = Some(22);
+
+    const VALUE_START_3: Option<u64> = Some(7);
+
+    const VALUE_START_5: Option<u64> = Some(13);
+
+    fn assert_final_value(item: &TestItem, storage: &dyn Storage) {
+        assert_eq!(FINAL_VALUE, item.may_load(storage).unwrap());
+    }
+
+    fn assert_value_at_height(
+        item: &TestItem,
+        storage: &dyn Storage,
+        height: u64,
+        value: Option<u64>,
+    ) {
+        assert_eq!(value, item.may_load_at_height(storage, height).unwrap());
+    }
+
+    fn assert_missing_checkpoint(item: &TestItem, storage: &dyn Storage, height: u64) {
+        assert!(item.may_load_at_height(storage, height).is_err());
+    }
+
+    #[test]
+    fn never_works_like_normal_item() {
+        let mut storage = MockStorage::new();
+        init_data(&NEVER, &mut storage);
+        assert_final_value(&NEVER, &storage);
+
+        // historical queries return error
+        assert_missing_checkpoint(&NEVER, &storage, 3);
+        assert_missing_checkpoint(&NEVER, &storage, 5);
+    }
+
+    #[test]
+    fn every_blocks_stores_present_and_past() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+        assert_final_value(&EVERY, &storage);
+
+        // historical queries return historical values
+        assert_value_at_height(&EVERY, &storage, 3, VALUE_START_3);
+        assert_value_at_height(&EVERY, &storage, 5, VALUE_START_5);
+    }
+
+    #[test]
+    fn selected_shows_3_not_5() {
+        let mut storage = MockStorage::new();
+        init_data(&SELECT, &mut storage);
+        assert_final_value(&SELECT, &storage);
+
+        // historical queries return historical values
+        assert_value_at_height(&SELECT, &storage, 3, VALUE_START_3);
+        // never checkpointed
+        assert_missing_checkpoint(&SELECT, &storage, 1);
+        // deleted checkpoint
+        assert_missing_checkpoint(&SELECT, &storage, 5);
+    }
+
+    #[test]
+    fn handle_multiple_writes_in_one_block() {
+        let mut storage = MockStorage::new();
+
+        EVERY.save(&mut storage, &5, 1).unwrap();
+        EVERY.save(&mut storage, &7, 2).unwrap();
+        EVERY.save(&mut storage, &2, 2).unwrap();
+
+        // update and save - query at 3 => 2, at 4 => 12
+        EVERY
+            .update(&mut storage, 3, |_| -> StdResult<u64> { Ok(9) })
+            .unwrap();
+        EVERY.save(&mut storage, &12, 3).unwrap();
+        assert_eq!(Some(5), EVERY.may_load_at_height(&storage, 2).unwrap());
+        assert_eq!(Some(2), EVERY.may_load_at_height(&storage, 3).unwrap());
+        assert_eq!(Some(12), EVERY.may_load_at_height(&storage, 4).unwrap());
+
+        // save and remove - query at 4 => 1, at 5 => None
+        EVERY.save(&mut storage, &17, 4).unwrap();
+        EVERY.remove(&mut storage, 4).unwrap();
+        assert_eq!(Some(12), EVERY.may_load_at_height(&storage, 4).unwrap());
+        assert_eq!(None, EVERY.may_load_at_height(&storage, 5).unwrap());
+
+        // remove and update - query at 5 => None, at 6 => 16
+        EVERY.remove(&mut storage, 5).unwrap();
+        EVERY.save(&mut storage, &16, 5).unwrap();
+        assert_eq!(None, EVERY.may_load_at_height(&storage, 5).unwrap());
+        assert_eq!(Some(16), EVERY.may_load_at_height(&storage, 6).unwrap());
+    }
+
+    #[test]
+    fn prune_history() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+
+        // a limit of 0 does nothing
+        assert!(!EVERY.prune(&mut storage, 5, 0).unwrap());
+        assert_eq!(EVERY.pruned_height(&storage).unwrap(), 0);
+
+        // changes at 1, 2, 3, 4 and 5
+        assert!(EVERY.prune(&mut storage, 5, 2).unwrap());
+        assert_eq!(EVERY.pruned_height(&storage).unwrap(), 5);
+        let err = EVERY.may_load_at_height(&storage, 3).unwrap_err();
+        assert!(err.to_string().contains("Height 3 was pruned"), "{}", err);
+        assert_value_at_height(&EVERY, &storage, 5, VALUE_START_5);
+
+        assert!(EVERY.prune(&mut storage, 5, 2).unwrap());
+        assert!(!EVERY.prune(&mut storage, 5, 2).unwrap());
+        assert_value_at_height(&EVERY, &storage, 5, VALUE_START_5);
+        assert_final_value(&EVERY, &storage);
+
+        let left: StdResult<Vec<_>> = EVERY
+            .changelog
+            .keys_de(&storage, None, None, Order::Ascending)
+            .collect();
+        assert_eq!(left.unwrap(), vec![5]);
+    }
+}
@@ -0,0 +1,937 @@
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+use std::convert::TryInto;
+use std::iter::Peekable;
+
+use cosmwasm_std::{from_slice, Binary, Order, StdError, StdResult, Storage, KV};
+
+use crate::de::KeyDeserialize;
+use crate::helpers::{decode_length, encode_length, may_deserialize};
+use crate::iter_helpers::concat;
+use crate::keys::{EmptyPrefix, PrimaryKey, U64Key};
+use crate::map::Map;
+use crate::path::Path;
+use crate::prefix::{range_with_prefix, DeserializedKV, Prefix};
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::snapshot::{ChangeSet, Checkpoints, Strategy};
+use crate::{Bound, Prefixer};
+
+/// Map that maintains a snapshots of one or more checkpoints.
+/// We can query historical data as well as current state.
+/// What data is snapshotted depends on the Strategy.
+pub struct SnapshotMap<'a, K, T> {
+    primary: Map<'a, K, T>,
+
+    // maps height to number of checkpoints, and decides when to write the changelog
+    checkpoints: Checkpoints<'a>,
+
+    // this stores all changes (key, height). Must differentiate between no data written,
+    // and explicit None (just inserted)
+    changelog: Map<'a, (K, U64Key), ChangeSet<T>>,
+}
+
+impl<'a, K, T> SnapshotMap<'a, K, T> {
+    /// Usage: SnapshotMap::new(snapshot_names!("foobar"), Strategy::EveryBlock)
+    pub const fn new(
+        pk: &'a str,
+        checkpoints: &'a str,
+        changelog: &'a str,
+        strategy: Strategy,
+    ) -> Self {
+        SnapshotMap {
+            primary: Map::new(pk),
+            checkpoints: Checkpoints::new(checkpoints, strategy),
+            changelog: Map::new(changelog),
+        }
+    }
+
+    pub fn add_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.add_checkpoint(store, height)
+    }
+
+    pub fn remove_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.remove_checkpoint(store, height)
+    }
+}
+
+impl<'a, K, T> SnapshotMap<'a, K, T>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    K: PrimaryKey<'a> + Prefixer<'a>,
+{
+    pub fn key(&self, k: K) -> Path<T> {
+        self.primary.key(k)
+    }
+
+    pub fn prefix(&self, p: K::Prefix) -> Prefix<T, K::Suffix> {
+        self.primary.prefix(p)
+    }
+
+    /// should_checkpoint looks at the strategy and determines if we want to checkpoint
+    fn should_checkpoint(&self, store: &dyn Storage, k: &K) -> StdResult<bool> {
+        self.checkpoints.should_checkpoint(store, |height| {
+            // any changelog for the given key since then?
+            let start = Bound::inclusive(U64Key::from(height));
+            let first = self
+                .changelog
+                .prefix(k.clone())
+                .range(store, Some(start), None, Order::Ascending)
+                .next()
+                .transpose()?;
+            Ok(first.is_some())
+        })
+    }
+
+    /// load old value and store changelog
+    fn write_change(&self, store: &mut dyn Storage, k: K, height: u64) -> StdResult<()> {
+        // if there is already data in the changelog for this key and block, do not write more
+        if self
+            .changelog
+            .may_load(store, (k.clone(), U64Key::from(height)))?
+            .is_some()
+        {
+            return Ok(());
+        }
+        // otherwise, store the previous value
+        let old = self.primary.may_load(store, k.clone())?;
+        self.changelog
+            .save(store, (k, U64Key::from(height)), &ChangeSet { old })
+    }
+
+    pub fn save(&self, store: &mut dyn Storage, k: K, data: &T, height: u64) -> StdResult<()> {
+        if self.should_checkpoint(store, &k)? {
+            self.write_change(store, k.clone(), height)?;
+        }
+        self.primary.save(store, k, data)
+    }
+
+    pub fn remove(&self, store: &mut dyn Storage, k: K, height: u64) -> StdResult<()> {
+        if self.should_checkpoint(store, &k)? {
+            self.write_change(store, k.clone(), height)?;
+        }
+        self.primary.remove(store, k);
+        Ok(())
+    }
+
+    /// load will return an error if no data is set at the given key, or on parse error
+    pub fn load(&self, store: &dyn Storage, k: K) -> StdResult<T> {
+        self.primary.load(store, k)
+    }
+
+    /// may_load will parse the data stored at the key if present, returns Ok(None) if no data there.
+    /// returns an error on issues parsing
+    pub fn may_load(&self, store: &dyn Storage, k: K) -> StdResult<Option<T>> {
+        self.primary.may_load(store, k)
+    }
+
+    // may_load_at_height reads historical data from given checkpoints.
+    // Only returns `Ok` if we have the data to be able to give the correct answer
+    // (Strategy::EveryBlock or Strategy::Selected and h is registered as checkpoint)
+    //
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn may_load_at_height(
+        &self,
+        store: &dyn Storage,
+        k: K,
+        height: u64,
+    ) -> StdResult<Option<T>> {
+        self.assert_checkpointed(store, height)?;
+
+        // this will look for the first snapshot of the given address >= given height
+        // If None, there is no snapshot since that time.
+        let start = Bound::inclusive(U64Key::new(height));
+        let first = self
+            .changelog
+            .prefix(k.clone())
+            .range(store, Some(start), None, Order::Ascending)
+            .next();
+
+        if let Some(r) = first {
+            // if we found a match, return this last one
+            r.map(|(_, v)| v.old)
+        } else {
+            // otherwise, return current value
+            self.may_load(store, k)
+        }
+    }
+
+    // If there is no checkpoint for that height, then we return StdError::NotFound
+    pub fn assert_checkpointed(&self, store: &dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints.assert_checkpointed(store, height)
+    }
+
+    /// Removes the history (changelog and checkpoints) below before_height, which can
+    /// no longer be queried afterwards. This visits at most limit changelog entries
+    /// (and removes at most limit checkpoints) per call. The changelog is ordered by key,
+    /// not by height, so all of it is visited over the calls, however low before_height is.
+    /// Returns true if there is more to prune, call it again (eg. in the next block) until it returns false.
+    /// A limit of 0 removes nothing and returns false.
+    pub fn prune(
+        &self,
+        store: &mut dyn Storage,
+        before_height: u64,
+        limit: usize,
+    ) -> StdResult<bool> {
+        if limit == 0 {
+            return Ok(false);
+        }
+        let mut state = match self.checkpoints.start_prune(store, before_height)? {
+            Some(state) => state,
+            None => return Ok(false),
+        };
+
+        let changelog = self.changelog.no_prefix();
+        let start = state.cursor.as_ref().map(|c| Bound::Exclusive(c.to_vec()));
+        let keys: Vec<_> = changelog
+            .keys(store, start, None, Order::Ascending)
+            .take(limit)
+            .collect();
+        for key in &keys {
+            if changelog_height(key)? < state.height {
+                store.remove(&concat(&changelog, key));
+            }
+        }
+
+        if let Some(last) = keys.last() {
+            state.cursor = Some(Binary::from(last.as_slice()));
+        }
+        let changelog_done = keys.len() < limit;
+        self.checkpoints
+            .finish_prune(store, state, changelog_done, limit)
+    }
+
+    /// All history below this height was removed by prune
+    pub fn pruned_height(&self, store: &dyn Storage) -> StdResult<u64> {
+        self.checkpoints.pruned_height(store)
+    }
+
+    /// Loads the data, perform the specified action, and store the result
+    /// in the database. This is shorthand for some common sequences, which may be useful.
+    ///
+    /// If the data exists, `action(Some(value))` is called. Otherwise `action(None)` is called.
+    ///
+    /// This is a bit more customized than needed to only read "old" value 1 time, not 2 per naive approach
+    pub fn update<A, E>(
+        &self,
+        store: &mut dyn Storage,
+        k: K,
+        height: u64,
+        action: A,
+    ) -> Result<T, E>
+    where
+        A: FnOnce(Option<T>) -> Result<T, E>,
+        E: From<StdError>,
+    {
+        let input = self.may_load(store, k.clone())?;
+        let output = action(input)?;
+        self.save(store, k, &output, height)?;
+        Ok(output)
+    }
+}
+
+// short-cut for simple keys, rather than .prefix(()).range(...)
+impl<'a, K, T> SnapshotMap<'a, K, T>
+where
+    T: Serialize + DeserializeOwned + Clone,
+    K: PrimaryKey<'a> + Prefixer<'a>,
+    K::Prefix: EmptyPrefix,
+{
+    // I would prefer not to copy code from Prefix, but no other way
+    // with lifetimes (create Prefix inside function and return ref = no no)
+    pub fn range<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<cosmwasm_std::KV<T>>> + 'c>
+    where
+        T: 'c,
+    {
+        self.prefix(K::Prefix::new()).range(store, min, max, order)
+    }
+
+    /// Like `range`, but deserializes the keys into the key type
+    pub fn range_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<DeserializedKV<K::Suffix, T>>> + 'c>
+    where
+        T: 'c,
+        <K::Suffix as KeyDeserialize>::Output: 'c,
+    {
+        self.primary.range_de(store, min, max, order)
+    }
+
+    /// Deserialized keys of the current state, without loading the values
+    pub fn keys_de<'c>(
+        &self,
+        store: &'c dyn Storage,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: cosmwasm_std::Order,
+    ) -> Box<dyn Iterator<Item = StdResult<<K::Suffix as KeyDeserialize>::Output>> + 'c>
+    where
+        T: 'c,
+        <K::Suffix as KeyDeserialize>::Output: 'c,
+    {
+        self.primary.keys_de(store, min, max, order)
+    }
+
+    /// Like `range`, but returns the entries as they were at the given height, by merging
+    /// the current state with the keys changed since in the changelog. Just like `may_load_at_height`,
+    /// this returns an error if we don't have the data for that height.
+    ///
+    /// The keys are merged lazily, and a page (eg. `.take(limit)`) stops reading at its last key.
+    /// But the keys removed since only show up in the changelog, so every changelog entry
+    /// between the keys of the page is read, including the changes before height. A page can read
+    /// the whole history of the map, so use it in queries, and keep the history short with `prune`.
+    /// This relies on the changelog key being the length-prefixed key followed by the height,
+    /// so it is only available for simple keys.
+    pub fn range_at_height<'c>(
+        &self,
+        store: &'c dyn Storage,
+        height: u64,
+        min: Option<Bound>,
+        max: Option<Bound>,
+        order: Order,
+    ) -> StdResult<Box<dyn Iterator<Item = StdResult<KV<T>>> + 'c>>
+    where
+        T: 'c,
+    {
+        self.assert_checkpointed(store, height)?;
+
+        // all current keys, and all keys changed since (that may be removed by now)
+        let current = self
+            .primary
+            .keys(store, min.clone(), max.clone(), order)
+            .map(Ok);
+        let mut streams: Vec<KeyStream> = vec![Box::new(current)];
+        let changelog = self.changelog.no_prefix();
+        for len in changelog_key_lengths(store, &changelog)? {
+            streams.push(changed_keys(
+                store, &changelog, len, height, &min, &max, order,
+            ));
+        }
+
+        let primary = self.primary.no_prefix();
+        let mapped = MergeKeys::new(streams, order).filter_map(move |k| {
+            let k = match k {
+                Ok(k) => k,
+                Err(e) => return Some(Err(e)),
+            };
+            match value_at_height(store, &primary, &changelog, &k, height) {
+                Ok(Some(v)) => Some(Ok((k, v))),
+                Ok(None) => None,
+                Err(e) => Some(Err(e)),
+            }
+        });
+        Ok(Box::new(mapped))
+    }
+}
+
+type KeyStream<'c> = Box<dyn Iterator<Item = StdResult<Vec<u8>>> + 'c>;
+
+// the value of a (simple) key at the given height: the old value of the first change since,
+// or the current value if it wasn't changed since
+fn value_at_height<T: DeserializeOwned>(
+    store: &dyn Storage,
+    primary: &[u8],
+    changelog: &[u8],
+    k: &[u8],
+    height: u64,
+) -> StdResult<Option<T>> {
+    let changes = concat(changelog, &concat(&encode_length(k), k));
+    let start = Bound::inclusive(U64Key::new(height));
+    match range_with_prefix(store, &changes, Some(start), None, Order::Ascending).next() {
+        Some((_, v)) => Ok(from_slice::<ChangeSet<T>>(&v)?.old),
+        None => may_deserialize(&store.get(&concat(primary, k))),
+    }
+}
+
+// The changelog keys start with the length of the (simple) key, so they are sorted by length first.
+// This finds every length in use, with one lookup each.
+fn changelog_key_lengths(store: &dyn Storage, changelog: &[u8]) -> StdResult<Vec<usize>> {
+    let mut lengths = vec![];
+    let mut next = 0;
+    while next <= 0xFFFF {
+        let start = Bound::Inclusive((next as u16).to_be_bytes().to_vec());
+        match range_with_prefix(store, changelog, Some(start), None, Order::Ascending).next() {
+            Some((k, _)) if k.len() >= 2 => {
+                let len = decode_length(&k);
+                lengths.push(len);
+                next = len + 1;
+            }
+            Some(_) => return Err(StdError::generic_err("Invalid changelog key")),
+            None => break,
+        }
+    }
+    Ok(lengths)
+}
+
+// The keys of the given length with a change at or after height, in order.
+// Keys of the same length sort just like the primary keys, so these can be merged with them.
+fn changed_keys<'c>(
+    store: &'c dyn Storage,
+    changelog: &[u8],
+    len: usize,
+    height: u64,
+    min: &Option<Bound>,
+    max: &Option<Bound>,
+    order: Order,
+) -> KeyStream<'c> {
+    let namespace = concat(changelog, &(len as u16).to_be_bytes());
+    // the changelog keys are the key followed by the height, these bounds include
+    // all keys of this length within min and max (and maybe a few more, removed below)
+    let start = min.as_ref().map(|b| {
+        let b = bound_bytes(b);
+        Bound::Inclusive(b[..b.len().min(len)].to_vec())
+    });
+    let end = max.as_ref().map(|b| match bound_bytes(b) {
+        b if b.len() < len => Bound::Exclusive(b.to_vec()),
+        b => Bound::Inclusive([&b[..len], &[0xff; 8]].concat()),
+    });
+    let (min, max) = (min.clone(), max.clone());
+    let keys = range_with_prefix(store, &namespace, start, end, order).filter_map(move |(k, _)| {
+        if k.len() != len + 8 {
+            return Some(Err(StdError::generic_err("Invalid changelog key")));
+        }
+        let (pk, changed) = k.split_at(len);
+        let changed = u64::from_be_bytes(changed.try_into().unwrap());
+        if changed >= height && in_bounds(pk, &min, &max) {
+            Some(Ok(pk.to_vec()))
+        } else {
+            None
+        }
+    });
+    Box::new(keys)
+}
+
+/// Merges sorted streams of keys into one sorted stream, without duplicates
+struct MergeKeys<'c> {
+    streams: Vec<Peekable<KeyStream<'c>>>,
+    order: Order,
+    last: Option<Vec<u8>>,
+}
+
+impl<'c> MergeKeys<'c> {
+    fn new(streams: Vec<KeyStream<'c>>, order: Order) -> Self {
+        MergeKeys {
+            streams: streams.into_iter().map(Iterator::peekable).collect(),
+            order,
+            last: None,
+        }
+    }
+}
+
+impl<'c> Iterator for MergeKeys<'c> {
+    type Item = StdResult<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            // pick the stream with the first key in order, errors are returned right away
+            let mut first: Option<(usize, &Vec<u8>)> = None;
+            for (i, stream) in self.streams.iter_mut().enumerate() {
+                match stream.peek() {
+                    Some(Ok(key)) => {
+                        let before = match first {
+                            None => true,
+                            Some((_, f)) => match self.order {
+                                Order::Ascending => key < f,
+                                Order::Descending => key > f,
+                            },
+                        };
+                        if before {
+                            first = Some((i, key));
+                        }
+                    }
+                    Some(Err(_)) => return stream.next(),
+                    None => {}
+                }
+            }
+            let (i, _) = first?;
+            let key = self.streams[i].next()?.ok()?;
+            if self.last.as_ref() != Some(&key) {
+                self.last = Some(key.clone());
+                return Some(Ok(key));
+            }
+        }
+    }
+}
+
+fn bound_bytes(b: &Bound) -> &[u8] {
+    match b {
+        Bound::Inclusive(b) | Bound::Exclusive(b) => b,
+    }
+}
+
+// returns true if the key is within the (raw) bounds
+fn in_bounds(k: &[u8], min: &Option<Bound>, max: &Option<Bound>) -> bool {
+    let above_min = match min {
+        Some(Bound::Inclusive(b)) => k >= b.as_slice(),
+        Some(Bound::Exclusive(b)) => k > b.as_slice(),
+        None => true,
+    };
+    let below_max = match max {
+        Some(Bound::Inclusive(b)) => k <= b.as_slice(),
+        Some(Bound::Exclusive(b)) => k < b.as_slice(),
+        None => true,
+    };
+    above_min && below_max
+}
+
+// the height is the last element of the changelog key, so it is not length-prefixed
+fn changelog_height(key: &[u8]) -> StdResult<u64> {
+    if key.len() < 8 {
+        return Err(StdError::invalid_data_size(8, key.len()));
+    }
+    let height = key[key.len() - 8..].try_into().unwrap();
+    Ok(u64::from_be_bytes(height))
+}
+
+impl<'a, K, T> DescribeStorage for SnapshotMap<'a, K, T> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        let mut keys = self.primary.storage_keys();
+        keys.extend(self.checkpoints.storage_keys());
+        keys.extend(self.changelog.storage_keys());
+        keys
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::snapshot::Retention;
+    use cosmwasm_std::testing::MockStorage;
+
+    type TestMap = SnapshotMap<'static, &'static [u8], u64>;
+    const NEVER: TestMap =
+        SnapshotMap::new("never", "never__check", "never__change", Strategy::Never);
+    const EVERY: TestMap = SnapshotMap::new(
+        "every",
+        "every__check",
+        "every__change",
+        Strategy::EveryBlock,
+    );
+    const SELECT: TestMap = SnapshotMap::new(
+        "select",
+        "select__check",
+        "select__change",
+        Strategy::Selected,
+    );
+
+    // Fills a map &[u8] -> u64 with the following writes:
+    // 1: A = 5
+    // 2: B = 7
+    // 3: C = 1, A = 8
+    // 4: B = None, C = 13
+    // 5: A = None, D = 22
+    // Final values -> C = 13, D = 22
+    // Values at beginning of 3 -> A = 5, B = 7
+    // Values at beginning of 5 -> A = 8, C = 13
+    fn init_data(map: &TestMap, storage: &mut dyn Storage) {
+        map.save(storage, b"A", &5, 1).unwrap();
+        map.save(storage, b"B", &7, 2).unwrap();
+
+        // checkpoint 3
+        map.add_checkpoint(storage, 3).unwrap();
+
+        // also use update to set - to ensure this works
+        map.save(storage, b"C", &1, 3).unwrap();
+        map.update(storage, b"A", 3, |_| -> StdResult<u64> { Ok(8) })
+            .unwrap();
+
+        map.remove(storage, b"B", 4).unwrap();
+        map.save(storage, b"C", &13, 4).unwrap();
+
+        // checkpoint 5
+        map.add_checkpoint(storage, 5).unwrap();
+        map.remove(storage, b"A", 5).unwrap();
+        map.update(storage, b"D", 5, |_| -> StdResult<u64> { Ok(22) })
+            .unwrap();
+        // and delete it later (unknown if all data present)
+        map.remove_checkpoint(storage, 5).unwrap();
+    }
+
+    const FINAL_VALUES: &[(&[u8], Option<u64>)] = &[
+        (b"A", None),
+        (b"B", None),
+        (b"C", Some(13)),
+        (b"D", Some(22)),
+    ];
+
+    const VALUES_START_3: &[(&[u8], Option<u64>)] =
+        &[(b"A", Some(5)), (b"B", Some(7)), (b"C", None), (b"D", None)];
+
+    const VALUES_START_5: &[(&[u8], Option<u64>)] = &[
+        (b"A", Some(8)),
+        (b"B", None),
+        (b"C", Some(13)),
+        (b"D", None),
+    ];
+
+    fn assert_final_values(map: &TestMap, storage: &dyn Storage) {
+        for (k, v) in FINAL_VALUES.iter().cloned() {
+            assert_eq!(v, map.may_load(storage, k).unwrap());
+        }
+    }
+
+    fn assert_values_at_height(
+        map: &TestMap,
+        storage: &dyn Storage,
+        height: u64,
+        values: &[(&[u8], Option<u64>)],
+    ) {
+        for (k, v) in values.iter().cloned() {
+            assert_eq!(v, map.may_load_at_height(storage, k, height).unwrap());
+        }
+    }
+
+    fn assert_missing_checkpoint(map: &TestMap, storage: &dyn Storage, height: u64) {
+        for k in &[b"A", b"B", b"C", b"D"] {
+            assert!(map.may_load_at_height(storage, *k, height).is_err());
+        }
+    }
+
+    #[test]
+    fn never_works_like_normal_map() {
+        let mut storage = MockStorage::new();
+        init_data(&NEVER, &mut storage);
+        assert_final_values(&NEVER, &storage);
+
+        // historical queries return error
+        assert_missing_checkpoint(&NEVER, &storage, 3);
+        assert_missing_checkpoint(&NEVER, &storage, 5);
+    }
+
+    #[test]
+    fn every_blocks_stores_present_and_past() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+        assert_final_values(&EVERY, &mut storage);
+
+        // historical queries return historical values
+        assert_values_at_height(&EVERY, &storage, 3, VALUES_START_3);
+        assert_values_at_height(&EVERY, &storage, 5, VALUES_START_5);
+    }
+
+    #[test]
+    fn selected_shows_3_not_5() {
+        let mut storage = MockStorage::new();
+        init_data(&SELECT, &mut storage);
+        assert_final_values(&SELECT, &mut storage);
+
+        // historical queries return historical values
+        assert_values_at_height(&SELECT, &storage, 3, VALUES_START_3);
+        // never checkpointed
+        assert_missing_checkpoint(&NEVER, &storage, 1);
+        // deleted checkpoint
+        assert_missing_checkpoint(&NEVER, &storage, 5);
+    }
+
+    #[test]
+    fn handle_multiple_writes_in_one_block() {
+        let mut storage = MockStorage::new();
+
+        println!("SETUP");
+        EVERY.save(&mut storage, b"A", &5, 1).unwrap();
+        EVERY.save(&mut storage, b"B", &7, 2).unwrap();
+        EVERY.save(&mut storage, b"C", &2, 2).unwrap();
+
+        // update and save - A query at 3 => 5, at 4 => 12
+        EVERY
+            .update(&mut storage, b"A", 3, |_| -> StdResult<u64> { Ok(9) })
+            .unwrap();
+        EVERY.save(&mut storage, b"A", &12, 3).unwrap();
+        assert_eq!(
+            Some(5),
+            EVERY.may_load_at_height(&storage, b"A", 2).unwrap()
+        );
+        assert_eq!(
+            Some(5),
+            EVERY.may_load_at_height(&storage, b"A", 3).unwrap()
+        );
+        assert_eq!(
+            Some(12),
+            EVERY.may_load_at_height(&storage, b"A", 4).unwrap()
+        );
+
+        // save and remove - B query at 4 => 7, at 5 => None
+        EVERY.save(&mut storage, b"B", &17, 4).unwrap();
+        EVERY.remove(&mut storage, b"B", 4).unwrap();
+        assert_eq!(
+            Some(7),
+            EVERY.may_load_at_height(&storage, b"B", 3).unwrap()
+        );
+        assert_eq!(
+            Some(7),
+            EVERY.may_load_at_height(&storage, b"B", 4).unwrap()
+        );
+        assert_eq!(None, EVERY.may_load_at_height(&storage, b"B", 5).unwrap());
+
+        // remove and update - C query at 5 => 2, at 6 => 16
+        EVERY.remove(&mut storage, b"C", 5).unwrap();
+        EVERY
+            .update(&mut storage, b"C", 5, |_| -> StdResult<u64> { Ok(16) })
+            .unwrap();
+        assert_eq!(
+            Some(2),
+            EVERY.may_load_at_height(&storage, b"C", 4).unwrap()
+        );
+        assert_eq!(
+            Some(2),
+            EVERY.may_load_at_height(&storage, b"C", 5).unwrap()
+        );
+        assert_eq!(
+            Some(16),
+            EVERY.may_load_at_height(&storage, b"C", 6).unwrap()
+        );
+    }
+
+    fn assert_pruned(map: &TestMap, storage: &dyn Storage, height: u64) {
+        for k in &[b"A", b"B", b"C", b"D"] {
+            let err = map.may_load_at_height(storage, *k, height).unwrap_err();
+            assert!(err.to_string().contains("was pruned"), "{}", err);
+        }
+    }
+
+    #[test]
+    fn prune_in_batches() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+        assert_eq!(EVERY.pruned_height(&storage).unwrap(), 0);
+
+        // the changelog has 8 entries, ordered by key: A1 A3 A5 B2 B4 C3 C4 D5
+        assert!(EVERY.prune(&mut storage, 5, 3).unwrap());
+        // queries below the height fail right away
+        assert_eq!(EVERY.pruned_height(&storage).unwrap(), 5);
+        assert_pruned(&EVERY, &storage, 3);
+        assert_pruned(&EVERY, &storage, 4);
+        assert_values_at_height(&EVERY, &storage, 5, VALUES_START_5);
+
+        assert!(EVERY.prune(&mut storage, 5, 3).unwrap());
+        assert!(!EVERY.prune(&mut storage, 5, 3).unwrap());
+        // nothing left to do
+        assert!(!EVERY.prune(&mut storage, 5, 3).unwrap());
+        assert!(!EVERY.prune(&mut storage, 4, 3).unwrap());
+
+        // only the changes at 5 are left, and everything since still works
+        let left = EVERY
+            .changelog
+            .no_prefix()
+            .keys(&storage, None, None, Order::Ascending)
+            .count();
+        assert_eq!(left, 2);
+        assert_values_at_height(&EVERY, &storage, 5, VALUES_START_5);
+        assert_final_values(&EVERY, &storage);
+        assert_pruned(&EVERY, &storage, 1);
+    }
+
+    #[test]
+    fn prune_with_zero_limit() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+
+        // nothing is removed, and a loop until false ends
+        assert!(!EVERY.prune(&mut storage, 5, 0).unwrap());
+        assert_eq!(EVERY.pruned_height(&storage).unwrap(), 0);
+        assert_values_at_height(&EVERY, &storage, 3, VALUES_START_3);
+
+        // one entry per call visits the 8 changelog entries in 8 calls
+        let mut calls = 1;
+        while EVERY.prune(&mut storage, 5, 1).unwrap() {
+            calls += 1;
+        }
+        assert!(calls <= 9, "{} calls", calls);
+        assert_pruned(&EVERY, &storage, 4);
+        assert_values_at_height(&EVERY, &storage, 5, VALUES_START_5);
+    }
+
+    #[test]
+    fn prune_removes_checkpoints() {
+        let mut storage = MockStorage::new();
+        init_data(&SELECT, &mut storage);
+        SELECT.add_checkpoint(&mut storage, 6).unwrap();
+
+        assert!(!SELECT.prune(&mut storage, 4, 10).unwrap());
+        assert_pruned(&SELECT, &storage, 3);
+        // checkpoint 6 is kept
+        SELECT.assert_checkpointed(&storage, 6).unwrap();
+        assert_final_values(&SELECT, &storage);
+
+        // pruning further restarts from the beginning
+        assert!(!SELECT.prune(&mut storage, 7, 10).unwrap());
+        assert_pruned(&SELECT, &storage, 6);
+        assert!(SELECT.changelog.is_empty(&storage));
+    }
+
+    #[test]
+    fn retention_policy() {
+        assert_eq!(Retention::KeepBlocks(10).prune_height(100), 91);
+        assert_eq!(Retention::KeepBlocks(10).prune_height(5), 0);
+        assert_eq!(Retention::KeepSince(42).prune_height(100), 42);
+
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+        let retention = Retention::KeepBlocks(2);
+        assert!(!EVERY
+            .prune(&mut storage, retention.prune_height(6), 100)
+            .unwrap());
+        assert_pruned(&EVERY, &storage, 4);
+        assert_values_at_height(&EVERY, &storage, 5, VALUES_START_5);
+    }
+
+    fn range_at(map: &TestMap, storage: &dyn Storage, height: u64) -> Vec<(Vec<u8>, u64)> {
+        map.range_at_height(storage, height, None, None, Order::Ascending)
+            .unwrap()
+            .collect::<StdResult<_>>()
+            .unwrap()
+    }
+
+    #[test]
+    fn range_at_height() {
+        let mut storage = MockStorage::new();
+        init_data(&EVERY, &mut storage);
+
+        assert_eq!(
+            range_at(&EVERY, &storage, 3),
+            vec![(b"A".to_vec(), 5), (b"B".to_vec(), 7)]
+        );
+        assert_eq!(
+            range_at(&EVERY, &storage, 5),
+            vec![(b"A".to_vec(), 8), (b"C".to_vec(), 13)]
+        );
+        // nothing changed since, this is the current state
+        assert_eq!(
+            range_at(&EVERY, &storage, 6),
+            vec![(b"C".to_vec(), 13), (b"D".to_vec(), 22)]
+        );
+        assert_eq!(range_at(&EVERY, &storage, 1), vec![]);
+
+        // with bounds and order
+        let res: StdResult<Vec<_>> = EVERY
+            .range_at_height(
+                &storage,
+                5,
+                Some(Bound::exclusive(b"A".as_ref())),
+                Some(Bound::inclusive(b"D".as_ref())),
+                Order::Descending,
+            )
+            .unwrap()
+            .collect();
+        assert_eq!(res.unwrap(), vec![(b"C".to_vec(), 13)]);
+        let res: StdResult<Vec<_>> = EVERY
+            .range_at_height(&storage, 3, None, None, Order::Descending)
+            .unwrap()
+            .collect();
+        assert_eq!(res.unwrap(), vec![(b"B".to_vec(), 7), (b"A".to_vec(), 5)]);
+    }
+
+    #[test]
+    fn range_at_height_with_different_key_lengths() {
+        let mut storage = MockStorage::new();
+        let keys: &[&'static [u8]] = &[b"", b"A", b"AA", b"AB", b"B", b"BA", b"BAA", b"C"];
+        // height 1: every key = its index, height 2: every other key removed,
+        // height 3: the rest updated
+        for (i, k) in keys.iter().enumerate() {
+            EVERY.save(&mut storage, k, &(i as u64), 1).unwrap();
+        }
+        for (i, k) in keys.iter().enumerate() {
+            if i % 2 == 0 {
+                EVERY.remove(&mut storage, k, 2).unwrap();
+            } else {
+                EVERY.save(&mut storage, k, &(i as u64 * 10), 3).unwrap();
+            }
+        }
+
+        let bounds: Vec<Option<Bound>> = vec![
+            None,
+            Some(Bound::inclusive(b"A".as_ref())),
+            Some(Bound::exclusive(b"A".as_ref())),
+            Some(Bound::inclusive(b"AB".as_ref())),
+            Some(Bound::exclusive(b"BA".as_ref())),
+            Some(Bound::inclusive(b"BAAA".as_ref())),
+        ];
+        for height in 2..5 {
+            for min in bounds.iter() {
+                for max in bounds.iter() {
+                    // what we expect, from loading every key at that height
+                    let mut expected: Vec<_> = keys
+                        .iter()
+                        .filter(|k| in_bounds(k, min, max))
+                        .filter_map(|k| {
+                            EVERY
+                                .may_load_at_height(&storage, k, height)
+                                .unwrap()
+                                .map(|v| (k.to_vec(), v))
+                        })
+                        .collect();
+                    let ascending: Vec<_> = EVERY
+                        .range_at_height(
+                            &storage,
+                            height,
+                            min.clone(),
+                            max.clone(),
+                            Order::Ascending,
+                        )
+                        .unwrap()
+                        .collect::<StdResult<_>>()
+                        .unwrap();
+                    assert_eq!(ascending, expected);
+
+                    expected.reverse();
+                    let descending: Vec<_> = EVERY
+                        .range_at_height(
+                            &storage,
+                            height,
+                            min.clone(),
+                            max.clone(),
+                            Order::Descending,
+                        )
+                        .unwrap()
+                        .collect::<StdResult<_>>()
+                        .unwrap();
+                    assert_eq!(descending, expected);
+                }
+            }
+        }
+
+        // a page only returns the first entries
+        let page: Vec<_> = EVERY
+            .range_at_height(&storage, 2, None, None, Order::Ascending)
+            .unwrap()
+            .take(2)
+            .collect::<StdResult<_>>()
+            .unwrap();
+        assert_eq!(page, vec![(b"".to_vec(), 0), (b"A".to_vec(), 1)]);
+    }
+
+    #[test]
+    fn range_at_height_needs_history() {
+        let mut storage = MockStorage::new();
+        init_data(&SELECT, &mut storage);
+        init_data(&NEVER, &mut storage);
+
+        assert_eq!(
+            range_at(&SELECT, &storage, 3),
+            vec![(b"A".to_vec(), 5), (b"B".to_vec(), 7)]
+        );
+        // checkpoint 5 was removed
+        assert!(SELECT
+            .range_at_height(&storage, 5, None, None, Order::Ascending)
+            .is_err());
+        assert!(NEVER
+            .range_at_height(&storage, 3, None, None, Order::Ascending)
+            .is_err());
+
+        // pruned heights
+        SELECT.prune(&mut storage, 4, 10).unwrap();
+        assert!(SELECT
+            .range_at_height(&storage, 3, None, None, Order::Ascending)
+            .is_err());
+    }
+}
@@ -0,0 +1,218 @@
+#![cfg(feature = "iterator")]
+mod item;
+mod map;
+
+pub use item::SnapshotItem;
+pub use map::SnapshotMap;
+
+use serde::{Deserialize, Serialize};
+
+use cosmwasm_std::{Binary, Order, StdError, StdResult, Storage};
+
+use crate::keys::U64Key;
+use crate::map::Map;
+use crate::prefix::Bound;
+use crate::registry::{DescribeStorage, StorageKey};
+use crate::Item;
+
+/// Checkpoints tracks the heights we want to be able to query historical data for,
+/// and is shared by SnapshotMap and SnapshotItem.
+/// What data is snapshotted depends on the Strategy.
+pub(crate) struct Checkpoints<'a> {
+    // maps height to number of checkpoints (only used for selected)
+    checkpoints: Map<'a, U64Key, u32>,
+
+    // How aggressive we are about checkpointing all data
+    strategy: Strategy,
+
+    // how far the history was pruned. This is stored under the checkpoints name,
+    // which can't collide with the (length-prefixed) keys of the checkpoints map
+    pruned: Item<'a, PruneState>,
+}
+
+/// PruneState tracks how much history was removed by `prune`
+#[derive(Serialize, Deserialize, Clone, Default, Debug, PartialEq)]
+pub(crate) struct PruneState {
+    /// all history below this height is removed (or being removed)
+    pub height: u64,
+    /// set while there are entries left to remove
+    pub in_progress: bool,
+    /// the last changelog key visited, if pruning takes several calls
+    pub cursor: Option<Binary>,
+}
+
+impl<'a> DescribeStorage for Checkpoints<'a> {
+    fn storage_keys(&self) -> Vec<StorageKey> {
+        let mut keys = self.checkpoints.storage_keys();
+        keys.extend(self.pruned.storage_keys());
+        keys
+    }
+}
+
+impl<'a> Checkpoints<'a> {
+    pub const fn new(checkpoints: &'a str, strategy: Strategy) -> Self {
+        Checkpoints {
+            checkpoints: Map::new(checkpoints),
+            strategy,
+            pruned: Item::new(checkpoints),
+        }
+    }
+
+    pub fn add_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        self.checkpoints
+            .update::<_, StdError>(store, height.into(), |count| {
+                Ok(count.unwrap_or_default() + 1)
+            })?;
+        Ok(())
+    }
+
+    pub fn remove_checkpoint(&self, store: &mut dyn Storage, height: u64) -> StdResult<()> {
+        let count = self
+            .checkpoints
+            .may_load(store, height.into())?
+            .unwrap_or_default();
+        if count <= 1 {
+            self.checkpoints.remove(store, height.into());
+            Ok(())
+        } else {
+            self.checkpoints.save(store, height.into(), &(count - 1))
+        }
+    }
+
+    /// should_checkpoint looks at the strategy and determines if we want to checkpoint.
+    /// For Selected, changed_since is called with the most recent checkpoint, and must
+    /// return true if the data was already written to the changelog since then.
+    pub fn should_checkpoint<F>(&self, store: &dyn Storage, changed_since: F) -> StdResult<bool>
+    where
+        F: FnOnce(u64) -> StdResult<bool>,
+    {
+        match self.strategy {
+            Strategy::EveryBlock => Ok(true),
+            Strategy::Never => Ok(false),
+            Strategy::Selected => {
+                // most recent checkpoint
+                let checkpoint = self
+                    .checkpoints
+                    .keys_de(store, None, None, Order::Descending)
+                    .next()
+                    .transpose()?;
+                match checkpoint {
+                    // there must be at least one open checkpoint and no changelog since then
+                    Some(height) => Ok(!changed_since(height)?),
+                    // otherwise, we don't save this
+                    None => Ok(false),
+                }
+            }
+        }
+    }
+
+    // If there is no checkpoint for that height, then we return StdError::NotFound.
+    // If the height was pruned, we return an error saying so
+    pub fn assert_checkpointed(&self, store: &dyn Storage, height: u64) -> StdResult<()> {
+        let pruned = self.pruned_height(store)?;
+        if height < pruned {
+            return Err(StdError::generic_err(format!(
+                "Height {} was pruned, the lowest height available is {}",
+                height, pruned
+            )));
+        }
+        let has = match self.strategy {
+            Strategy::EveryBlock => true,
+            Strategy::Never => false,
+            Strategy::Selected => self.checkpoints.may_load(store, height.into())?.is_some(),
+        };
+        match has {
+            true => Ok(()),
+            false => Err(StdError::not_found("checkpoint")),
+        }
+    }
+
+    /// All history below this height was removed by prune
+    pub fn pruned_height(&self, store: &dyn Storage) -> StdResult<u64> {
+        Ok(self.pruned.may_load(store)?.unwrap_or_default().height)
+    }
+
+    /// Starts pruning below before_height, or continues an unfinished prune.
+    /// Returns None if there is nothing left to prune.
+    pub fn start_prune(
+        &self,
+        store: &mut dyn Storage,
+        before_height: u64,
+    ) -> StdResult<Option<PruneState>> {
+        let mut state = self.pruned.may_load(store)?.unwrap_or_default();
+        if before_height > state.height {
+            // a new target means we need to visit all entries again
+            state = PruneState {
+                height: before_height,
+                in_progress: true,
+                cursor: None,
+            };
+            self.pruned.save(store, &state)?;
+        }
+        Ok(if state.in_progress { Some(state) } else { None })
+    }
+
+    /// Removes at most limit checkpoints below state.height, and stores the state.
+    /// changelog_done must be true if there are no more changelog entries to remove.
+    /// Returns true if there is more to prune.
+    pub fn finish_prune(
+        &self,
+        store: &mut dyn Storage,
+        mut state: PruneState,
+        changelog_done: bool,
+        limit: usize,
+    ) -> StdResult<bool> {
+        let end = Bound::exclusive(U64Key::new(state.height));
+        let heights: Vec<u64> = self
+            .checkpoints
+            .keys_de(store, None, Some(end), Order::Ascending)
+            .take(limit)
+            .collect::<StdResult<_>>()?;
+        for height in &heights {
+            self.checkpoints.remove(store, (*height).into());
+        }
+
+        state.in_progress = !changelog_done || heights.len() == limit;
+        if !state.in_progress {
+            state.cursor = None;
+        }
+        self.pruned.save(store, &state)?;
+        Ok(state.in_progress)
+    }
+}
+
+/// Retention describes how much history to keep, and returns the height to prune before
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Retention {
+    /// Keeps the given number of blocks, including the current one
+    KeepBlocks(u64),
+    /// Keeps everything since the given height
+    KeepSince(u64),
+}
+
+impl Retention {
+    /// Returns the lowest height to keep at the given height, to be passed to `prune`
+    pub fn prune_height(&self, current_height: u64) -> u64 {
+        match self {
+            Retention::KeepBlocks(blocks) => (current_height + 1).saturating_sub(*blocks),
+            Retention::KeepSince(height) => *height,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Strategy {
+    EveryBlock,
+    Never,
+    /// Only writes for linked blocks - does a few more reads to save some writes.
+    /// Probably uses more gas, but less total disk usage.
+    ///
+    /// Note that you need a trusted source (eg. own contract) to set/remove checkpoints.
+    /// Useful when the checkpoint setting happens in the same contract as the snapshotting.
+    Selected,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub(crate) struct ChangeSet<T> {
+    pub old: Option<T>,
+}
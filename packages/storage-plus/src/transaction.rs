@@ -1,10 +1,11 @@
+//! StorageTransaction buffers all writes on top of a storage, so a handler can update many
+//! keys (eg. the balances of all investors) and only write them once everything succeeded.
+//! `cw-multi-test` builds its caches on it as well. Ranges need the `iterator` feature.
 #[cfg(feature = "iterator")]
 use std::cmp::Ordering;
 use std::collections::BTreeMap;
 #[cfg(feature = "iterator")]
-use std::iter;
-#[cfg(feature = "iterator")]
-use std::iter::Peekable;
+use std::iter::{self, Peekable};
 #[cfg(feature = "iterator")]
 use std::ops::{Bound, RangeBounds};
 
@@ -17,13 +18,30 @@ use cosmwasm_std::{Order, KV};
 /// This is internal as it can change any time if the map implementation is swapped out.
 type BTreeMapPairRef<'a, T = Vec<u8>> = (&'a Vec<u8>, &'a T);
 
+/// Runs action on a transaction over storage, and only writes the changes if it returns Ok.
+/// On error, storage is left untouched. Usage:
+///
+///   transactional(deps.storage, |store| -> StdResult<_> {
+///       for (addr, amount) in payouts {
+///           BALANCES.update(store, &addr, |b| -> StdResult<_> { Ok(b.unwrap_or_default() + amount) })?;
+///       }
+///       Ok(())
+///   })?;
+pub fn transactional<T, E, F>(storage: &mut dyn Storage, action: F) -> Result<T, E>
+where
+    F: FnOnce(&mut dyn Storage) -> Result<T, E>,
+{
+    let mut tx = StorageTransaction::new(storage);
+    let res = action(&mut tx)?;
+    tx.prepare().commit(storage);
+    Ok(res)
+}
+
 pub struct StorageTransaction<'a> {
     /// read-only access to backing storage
     storage: &'a dyn Storage,
     /// these are local changes not flushed to backing storage
     local_state: BTreeMap<Vec<u8>, Delta>,
-    /// a log of local changes not yet flushed to backing storage
-    rep_log: RepLog,
 }
 
 impl<'a> StorageTransaction<'a> {
@@ -31,49 +49,42 @@ impl<'a> StorageTransaction<'a> {
         StorageTransaction {
             storage,
             local_state: BTreeMap::new(),
-            rep_log: RepLog::new(),
         }
     }
 
     /// prepares this transaction to be committed to storage
     pub fn prepare(self) -> RepLog {
-        self.rep_log
+        RepLog {
+            changes: self.local_state,
+        }
     }
 
-    /// rollback will consume the checkpoint and drop all changes (not really needed, going out of scope does the same, but nice for clarity)
-    #[allow(dead_code)]
+    /// rollback will consume the transaction and drop all changes (going out of scope does the same)
     pub fn rollback(self) {}
 }
 
 impl<'a> Storage for StorageTransaction<'a> {
     fn get(&self, key: &[u8]) -> Option<Vec<u8>> {
         match self.local_state.get(key) {
-            Some(val) => match val {
-                Delta::Set { value } => Some(value.clone()),
-                Delta::Delete {} => None,
-            },
+            Some(Delta::Set { value }) => Some(value.clone()),
+            Some(Delta::Delete {}) => None,
             None => self.storage.get(key),
         }
     }
 
     fn set(&mut self, key: &[u8], value: &[u8]) {
-        let op = Op::Set {
-            key: key.to_vec(),
+        let delta = Delta::Set {
             value: value.to_vec(),
         };
-        self.local_state.insert(key.to_vec(), op.to_delta());
-        self.rep_log.append(op);
+        self.local_state.insert(key.to_vec(), delta);
     }
 
     fn remove(&mut self, key: &[u8]) {
-        let op = Op::Delete { key: key.to_vec() };
-        self.local_state.insert(key.to_vec(), op.to_delta());
-        self.rep_log.append(op);
+        self.local_state.insert(key.to_vec(), Delta::Delete {});
     }
 
     #[cfg(feature = "iterator")]
-    /// range allows iteration over a set of keys, either forwards or backwards
-    /// uses standard rust range notation, and eg db.range(b"foo"..b"bar") also works reverse
+    /// range merges the local changes with the backing storage
     fn range<'b>(
         &'b self,
         start: Option<&[u8]>,
@@ -104,65 +115,34 @@ impl<'a> Storage for StorageTransaction<'a> {
     }
 }
 
+/// RepLog holds the changes of a prepared transaction, until they are written with `commit`
 pub struct RepLog {
-    /// this is a list of changes to be written to backing storage upon commit
-    ops_log: Vec<Op>,
+    changes: BTreeMap<Vec<u8>, Delta>,
 }
 
 impl RepLog {
-    fn new() -> Self {
-        RepLog { ops_log: vec![] }
-    }
-
-    /// appends an op to the list of changes to be applied upon commit
-    fn append(&mut self, op: Op) {
-        self.ops_log.push(op);
-    }
-
-    /// applies the stored list of `Op`s to the provided `Storage`
-    pub fn commit<S: Storage + ?Sized>(self, storage: &mut S) {
-        for op in self.ops_log {
-            op.apply(storage);
-        }
+    /// Number of keys written or removed
+    pub fn len(&self) -> usize {
+        self.changes.len()
     }
-}
 
-/// Op is the user operation, which can be stored in the RepLog.
-/// Currently Set or Delete.
-enum Op {
-    /// represents the `Set` operation for setting a key-value pair in storage
-    Set {
-        key: Vec<u8>,
-        value: Vec<u8>,
-    },
-    Delete {
-        key: Vec<u8>,
-    },
-}
-
-impl Op {
-    /// applies this `Op` to the provided storage
-    pub fn apply<S: Storage + ?Sized>(&self, storage: &mut S) {
-        match self {
-            Op::Set { key, value } => storage.set(&key, &value),
-            Op::Delete { key } => storage.remove(&key),
-        }
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
     }
 
-    /// converts the Op to a delta, which can be stored in a local cache
-    pub fn to_delta(&self) -> Delta {
-        match self {
-            Op::Set { value, .. } => Delta::Set {
-                value: value.clone(),
-            },
-            Op::Delete { .. } => Delta::Delete {},
+    /// applies all changes to the provided storage
+    pub fn commit(self, storage: &mut dyn Storage) {
+        for (key, delta) in self.changes {
+            match delta {
+                Delta::Set { value } => storage.set(&key, &value),
+                Delta::Delete {} => storage.remove(&key),
+            }
         }
     }
 }
 
-/// Delta is the changes, stored in the local transaction cache.
-/// This is either Set{value} or Delete{}. Note that this is the "value"
-/// part of a BTree, so the Key (from the Op) is stored separately.
+/// Delta is the change of one key, stored in the local transaction cache.
+/// This is either Set{value} or Delete{}.
 enum Delta {
     Set { value: Vec<u8> },
     Delete {},
@@ -204,7 +184,7 @@ where
         match order {
             Ordering::Less => self.take_left(),
             Ordering::Equal => {
-                //
+                // the local change overrides the stored value
                 let _ = self.right.next();
                 self.take_left()
             }
@@ -212,12 +192,13 @@ where
         }
     }
 
-    /// take_left must only be called when we know self.left.next() will return Some
+    /// take_left must only be called when we know self.left.next() will return Some.
+    /// It returns None if the local change deletes the key.
     fn take_left(&mut self) -> Option<KV> {
         let (lkey, lval) = self.left.next().unwrap();
         match lval {
             Delta::Set { value } => Some((lkey.clone(), value.clone())),
-            Delta::Delete {} => self.next(),
+            Delta::Delete {} => None,
         }
     }
 }
@@ -231,20 +212,23 @@ where
     type Item = KV;
 
     fn next(&mut self) -> Option<Self::Item> {
-        let (left, right) = (self.left.peek(), self.right.peek());
-        match (left, right) {
-            (Some(litem), Some(ritem)) => {
-                let (lkey, _) = litem;
-                let (rkey, _) = ritem;
-
-                // we just use cloned keys to avoid double mutable references
-                // (we must release the return value from peek, before beginning to call next or other mut methods
-                let (l, r) = (lkey.to_vec(), rkey.to_vec());
-                self.pick_match(l, r)
+        // skip over deleted keys until we find a value, or both sides are done
+        loop {
+            let (left, right) = (self.left.peek(), self.right.peek());
+            let item = match (left, right) {
+                (Some(litem), Some(ritem)) => {
+                    // we just use cloned keys to avoid double mutable references
+                    // (we must release the return value from peek, before calling next)
+                    let (l, r) = (litem.0.to_vec(), ritem.0.to_vec());
+                    self.pick_match(l, r)
+                }
+                (Some(_), None) => self.take_left(),
+                (None, Some(_)) => return self.right.next(),
+                (None, None) => return None,
+            };
+            if item.is_some() {
+                return item;
             }
-            (Some(_), None) => self.take_left(),
-            (None, Some(_)) => self.right.next(),
-            (None, None) => None,
         }
     }
 }
@@ -263,7 +247,102 @@ mod test {
     use std::cell::RefCell;
     use std::ops::{Deref, DerefMut};
 
-    use cosmwasm_std::MemoryStorage;
+    use cosmwasm_std::testing::MockStorage;
+    use cosmwasm_std::{MemoryStorage, StdError, StdResult};
+
+    use crate::Map;
+
+    const BALANCES: Map<&str, u64> = Map::new("balances");
+
+    #[test]
+    #[cfg(feature = "iterator")]
+    fn reads_own_writes() {
+        let mut base = MockStorage::new();
+        base.set(b"foo", b"bar");
+        base.set(b"food", b"bank");
+
+        let mut tx = StorageTransaction::new(&base);
+        tx.set(b"ant", b"hill");
+        tx.set(b"foo", b"baz");
+        tx.remove(b"food");
+        assert_eq!(tx.get(b"foo"), Some(b"baz".to_vec()));
+        assert_eq!(tx.get(b"food"), None);
+
+        let all: Vec<_> = tx.range(None, None, Order::Ascending).collect();
+        assert_eq!(
+            all,
+            vec![
+                (b"ant".to_vec(), b"hill".to_vec()),
+                (b"foo".to_vec(), b"baz".to_vec()),
+            ]
+        );
+        let all: Vec<_> = tx.range(Some(b"b"), None, Order::Descending).collect();
+        assert_eq!(all, vec![(b"foo".to_vec(), b"baz".to_vec())]);
+        // empty range with start > end
+        assert_eq!(
+            tx.range(Some(b"z"), Some(b"a"), Order::Ascending).count(),
+            0
+        );
+
+        // nothing written until commit
+        assert_eq!(base.get(b"foo"), Some(b"bar".to_vec()));
+        let log = tx.prepare();
+        assert_eq!(log.len(), 3);
+        log.commit(&mut base);
+        assert_eq!(base.get(b"ant"), Some(b"hill".to_vec()));
+        assert_eq!(base.get(b"foo"), Some(b"baz".to_vec()));
+        assert_eq!(base.get(b"food"), None);
+    }
+
+    #[test]
+    fn typed_rollback_has_no_effect() {
+        let base = MockStorage::new();
+        let mut tx = StorageTransaction::new(&base);
+        BALANCES.save(&mut tx, "john", &100).unwrap();
+        assert_eq!(BALANCES.load(&tx, "john").unwrap(), 100);
+        tx.rollback();
+
+        assert_eq!(BALANCES.may_load(&base, "john").unwrap(), None);
+    }
+
+    fn pay_all(store: &mut dyn Storage, payouts: &[(&str, u64)]) -> StdResult<()> {
+        for (addr, amount) in payouts {
+            BALANCES.update(store, addr, |b| -> StdResult<_> {
+                Ok(b.unwrap_or_default() + amount)
+            })?;
+            // take it from the treasury
+            BALANCES.update(store, "treasury", |b| -> StdResult<_> {
+                b.unwrap_or_default()
+                    .checked_sub(*amount)
+                    .ok_or_else(|| StdError::generic_err("treasury empty"))
+            })?;
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn transactional_commits_on_success() {
+        let mut store = MockStorage::new();
+        BALANCES.save(&mut store, "treasury", &100).unwrap();
+
+        transactional(&mut store, |tx| pay_all(tx, &[("john", 30), ("mary", 50)])).unwrap();
+        assert_eq!(BALANCES.load(&store, "treasury").unwrap(), 20);
+        assert_eq!(BALANCES.load(&store, "john").unwrap(), 30);
+        assert_eq!(BALANCES.load(&store, "mary").unwrap(), 50);
+    }
+
+    #[test]
+    fn transactional_writes_nothing_on_error() {
+        let mut store = MockStorage::new();
+        BALANCES.save(&mut store, "treasury", &100).unwrap();
+
+        // the second payout fails, after the first one was written
+        let err =
+            transactional(&mut store, |tx| pay_all(tx, &[("john", 30), ("mary", 80)])).unwrap_err();
+        assert_eq!(err, StdError::generic_err("treasury empty"));
+        assert_eq!(BALANCES.load(&store, "treasury").unwrap(), 100);
+        assert_eq!(BALANCES.may_load(&store, "john").unwrap(), None);
+    }
 
     #[test]
     fn wrap_storage() {